@@ -1,5 +1,41 @@
+use crate::span::Span;
+
+/// A node in the syntax tree, along with the span of source it was parsed from.
+#[derive(Debug, Clone)]
+pub struct Expression {
+  pub kind: ExpressionKind,
+  pub span: Span,
+  /// The type of the expression's value, filled in by the type checker.
+  pub ty: Option<Type>,
+}
+
+impl Expression {
+  pub fn new(kind: ExpressionKind, span: Span) -> Self {
+    Self {
+      kind,
+      span,
+      ty: None,
+    }
+  }
+}
+
+/// Expressions are compared structurally, so two trees parsed from differently laid out sources are equal.
+impl PartialEq for Expression {
+  fn eq(&self, other: &Self) -> bool {
+    self.kind == other.kind
+  }
+}
+
+impl From<ExpressionKind> for Expression {
+  fn from(kind: ExpressionKind) -> Self {
+    Self::new(kind, Span::default())
+  }
+}
+
 #[derive(Debug, PartialEq, Clone)]
-pub enum Expression {
+pub enum ExpressionKind {
+  /// Placeholder for a construct that failed to parse.
+  Error,
   Number(String),
   Decimal(String),
   String(String),
@@ -8,6 +44,10 @@ pub enum Expression {
   VariableRef(String),
   Comment(String),
   Return(Option<Box<Expression>>),
+  /// `break`, optionally naming the labeled loop to exit.
+  Break(Option<String>),
+  /// `continue`, optionally naming the labeled loop to continue.
+  Continue(Option<String>),
   FuncCall(String, Vec<Expression>),
   Export(Box<Expression>),
   Declare(Box<Expression>),
@@ -23,32 +63,77 @@ pub enum Expression {
   },
   Function {
     name: String,
-    ret: String,
-    args: Vec<(String, String)>,
+    ret: Type,
+    args: Vec<(String, Type)>,
     body: Option<Box<Expression>>,
   },
   Block {
     expressions: Vec<Expression>,
   },
+  /// The declaration of a struct type, e.g. `struct Point { x: i32, y: i32 }`.
+  Struct {
+    name: String,
+    fields: Vec<(String, Type)>,
+  },
+  /// The declaration of an enum, e.g. `enum Color { Red, Green = 5 }`.
+  /// Variants that hold values, e.g. `Circle(f64)`, make it a tagged union.
+  Enum {
+    name: String,
+    variants: Vec<Variant>,
+  },
+  /// A value of an enum type, e.g. `Color::Red` or `Shape::Circle(1.0)`.
+  EnumVariant {
+    name: String,
+    variant: String,
+    args: Vec<Expression>,
+  },
+  /// `match (value) { pattern => body, ... }`, which runs the body of the first arm whose pattern matches.
+  Match {
+    value: Box<Expression>,
+    arms: Vec<MatchArm>,
+  },
+  /// A value of a struct type, e.g. `Point { x: 1, y: 2 }`.
+  StructLiteral {
+    name: String,
+    fields: Vec<(String, Expression)>,
+  },
+  /// `expr.field`, which looks through a pointer to a struct as well.
+  FieldAccess {
+    expr: Box<Expression>,
+    field: String,
+  },
+  /// An array of the given elements, e.g. `[1, 2, 3]`.
+  ArrayLiteral(Vec<Expression>),
+  /// `expr[index]`, which indexes into an array or a pointer.
+  Index {
+    expr: Box<Expression>,
+    index: Box<Expression>,
+  },
+  /// `expr as ty`, which explicitly converts a value to another type.
+  Cast {
+    expr: Box<Expression>,
+    ty: Type,
+  },
   VariableDeclaration {
     name: String,
-    ty: String,
+    ty: Type,
     mutable: bool,
   },
   For {
     conditions: [Box<Expression>; 3],
     body: Box<Expression>,
+    label: Option<String>,
   },
   While {
     condition: Box<Expression>,
     body: Box<Expression>,
+    label: Option<String>,
   },
   If {
     condition: Box<Expression>,
     body: Box<Expression>,
-  },
-  Else {
-    body: Box<Expression>,
+    /// The body of the `else` branch, which is another `If` for `else if`.
+    else_body: Option<Box<Expression>>,
   },
   UnaryOperation {
     operator: UnaryOperator,
@@ -62,6 +147,125 @@ pub enum Expression {
   },
 }
 
+/// A variant of an enum declaration.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Variant {
+  pub name: String,
+  /// The types of the values the variant holds.
+  pub fields: Vec<Type>,
+  /// The discriminant given to the variant, e.g. the `5` of `Green = 5`.
+  pub value: Option<i64>,
+}
+
+impl Variant {
+  /// Gets the discriminant of each variant. Like in C, a variant that isn't given one is one more than the variant before it.
+  pub fn discriminants(variants: &[Variant]) -> Vec<i64> {
+    let mut next = 0;
+    variants
+      .iter()
+      .map(|variant| {
+        let value = variant.value.unwrap_or(next);
+        next = value.wrapping_add(1);
+        value
+      })
+      .collect()
+  }
+}
+
+/// An arm of a `match`.
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+  pub pattern: Pattern,
+  /// The span of the pattern.
+  pub span: Span,
+  pub body: Expression,
+}
+
+/// Arms are compared structurally, like expressions.
+impl PartialEq for MatchArm {
+  fn eq(&self, other: &Self) -> bool {
+    self.pattern == other.pattern && self.body == other.body
+  }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+  /// `_`, which matches anything.
+  Wildcard,
+  /// A number, char, or bool literal.
+  Literal(Expression),
+  /// A variant of an enum, binding the values it holds to names, e.g. `Shape::Circle(radius)`.
+  Variant {
+    name: String,
+    variant: String,
+    bindings: Vec<String>,
+  },
+}
+
+/// The type of a value, as written in the source.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Type {
+  Void,
+  Bool,
+  Char,
+  /// Strings are pointers to chars until they get a representation of their own.
+  String,
+  Int {
+    bits: u32,
+    signed: bool,
+  },
+  Float {
+    bits: u32,
+  },
+  Pointer(Box<Type>),
+  /// An array with a length known at compile time, or an unsized one that decays to a pointer, e.g. `i32[]`.
+  Array(Box<Type>, Option<u32>),
+  Function {
+    args: Vec<Type>,
+    ret: Box<Type>,
+  },
+  /// A user-defined type, looked up by name.
+  Named(String),
+  /// The definition of a struct, which is the type of the struct's own name. Its values are `Named`.
+  Struct {
+    name: String,
+    fields: Vec<(String, Type)>,
+  },
+  /// The definition of an enum, which is the type of the enum's own name. Its values are `Named`.
+  Enum {
+    name: String,
+    variants: Vec<Variant>,
+  },
+}
+
+impl Type {
+  /// Construct a type from its name, e.g. `i32`. Anything that isn't a primitive is a named type.
+  pub fn from_name<S: AsRef<str>>(name: S) -> Self {
+    let name = name.as_ref();
+    match name {
+      "void" => Self::Void,
+      "bool" => Self::Bool,
+      "char" => Self::Char,
+      "string" => Self::String,
+      "f32" => Self::Float { bits: 32 },
+      "f64" => Self::Float { bits: 64 },
+      _ => {
+        let int = name
+          .strip_prefix('i')
+          .map(|bits| (bits, true))
+          .or_else(|| name.strip_prefix('u').map(|bits| (bits, false)))
+          .and_then(|(bits, signed)| Some((bits.parse::<u32>().ok()?, signed)));
+        match int {
+          Some((bits, signed)) if [8, 16, 32, 64, 128].contains(&bits) => {
+            Self::Int { bits, signed }
+          }
+          _ => Self::Named(name.to_string()),
+        }
+      }
+    }
+  }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum UnaryOperator {
   Not,       // !
@@ -107,15 +311,26 @@ pub enum BinaryOperator {
   Ge, // >=
   Le, // <=
 
+  // Logical
+  LogicalAnd, // &&
+  LogicalOr,  // ||
+
   // Bitwise
   BitAND,     // &
   BitXOR,     // ^
-  BitOR,      // ||
+  BitOR,      // |
   LeftShift,  // <<
   RightShift, // >>
 }
 
 impl UnaryOperator {
+  /// The binding power of prefix operators, which bind tighter than casts, e.g. `-x as u8 => (-x) as u8`.
+  pub const PREFIX_BP: u8 = 25;
+  /// The binding power of postfix operators, which bind tighter than prefix ones, e.g. `-x++ => -(x++)`.
+  pub const POSTFIX_BP: u8 = 27;
+  /// The binding power of `as` casts, which bind tighter than any binary operator, e.g. `a * b as f64 => a * (b as f64)`.
+  pub const CAST_BP: u8 = 23;
+
   /// Construct a Unary Operator from its string literal.
   pub fn from<S: AsRef<str>>(literal: S) -> Option<Self> {
     match literal.as_ref() {
@@ -161,22 +376,64 @@ impl BinaryOperator {
       ">=" => Some(Self::Ge), // >=
       "<=" => Some(Self::Le), // <=
 
+      // Logical
+      "&&" => Some(Self::LogicalAnd), // &&
+      "||" => Some(Self::LogicalOr),  // ||
+
       // Bitwise
       "&" => Some(Self::BitAND),      // &
       "^" => Some(Self::BitXOR),      // ^
-      "||" => Some(Self::BitOR),      // ||
+      "|" => Some(Self::BitOR),       // |
       "<<" => Some(Self::LeftShift),  // <<
       ">>" => Some(Self::RightShift), // >>
       _ => None,
     }
   }
 
-  // /// The binding power of a operator.
-  // /// This is used to determine the order of operations for multiple binary operations.
-  // /// e.g. `1 + 1 * 2 => 1 + (1 * 2)`
-  // pub fn bp(&self) -> u32 {
-  //   match self {
-  //     _ => 0,
-  //   }
-  // }
+  /// The operator applied by a compound assignment, e.g. `Add` for `+=`.
+  pub fn base_operator(&self) -> Option<Self> {
+    match self {
+      Self::AddAssign => Some(Self::Add),
+      Self::SubtAssign => Some(Self::Subtract),
+      Self::MultAssign => Some(Self::Multiply),
+      Self::DivAssign => Some(Self::Divide),
+      Self::ModAssign => Some(Self::Modulo),
+      Self::LeftShiftAssign => Some(Self::LeftShift),
+      Self::RightShiftAssign => Some(Self::RightShift),
+      Self::BitANDAssign => Some(Self::BitAND),
+      Self::BitXORAssign => Some(Self::BitXOR),
+      Self::BitORAssign => Some(Self::BitOR),
+      _ => None,
+    }
+  }
+
+  /// The binding power of the operator, as `(left, right)`.
+  /// This is used to determine the order of operations for multiple binary operations.
+  /// e.g. `1 + 1 * 2 => 1 + (1 * 2)`
+  /// Left-associative operators bind tighter on their right, right-associative ones (assignments) on their left.
+  pub fn bp(&self) -> (u8, u8) {
+    match self {
+      Self::Assign
+      | Self::AddAssign
+      | Self::SubtAssign
+      | Self::MultAssign
+      | Self::DivAssign
+      | Self::ModAssign
+      | Self::LeftShiftAssign
+      | Self::RightShiftAssign
+      | Self::BitANDAssign
+      | Self::BitXORAssign
+      | Self::BitORAssign => (2, 1),
+      Self::LogicalOr => (3, 4),
+      Self::LogicalAnd => (5, 6),
+      Self::BitOR => (7, 8),
+      Self::BitXOR => (9, 10),
+      Self::BitAND => (11, 12),
+      Self::Eq | Self::Ne => (13, 14),
+      Self::Gt | Self::Lt | Self::Ge | Self::Le => (15, 16),
+      Self::LeftShift | Self::RightShift => (17, 18),
+      Self::Add | Self::Subtract => (19, 20),
+      Self::Multiply | Self::Divide | Self::Modulo => (21, 22),
+    }
+  }
 }
@@ -0,0 +1,162 @@
+use super::{llvm, BuildOptions};
+use crate::project::{Project, ProjectKind};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Links the object files of a project into an executable or library in the target directory, returning the path of
+/// the output.
+///
+/// Executables and shared libraries are linked through the C compiler driver, `cc` or `$CC`, so the C runtime and
+/// libc are found the same way they are for C programs. `lld` is used when it's installed, and the driver is passed
+/// `--target` when the target isn't the host. Static libraries are archived with `ar` or `$AR`.
+pub fn link(
+  project: &Project,
+  options: &BuildOptions,
+  objects: &[PathBuf],
+) -> Result<PathBuf, String> {
+  let target_triple = options.target_triple();
+  let output = options.target_dir().join(output_file_name(
+    project.kind,
+    project.output_name(),
+    &target_triple,
+  ));
+  let mut command = match project.kind {
+    ProjectKind::Executable | ProjectKind::Shared => {
+      let mut command = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_string()));
+      if project.kind == ProjectKind::Shared {
+        command.arg("-shared");
+      }
+      command.args(objects).arg("-o").arg(&output);
+      if !is_host(&target_triple) {
+        command.arg(format!("--target={}", target_triple));
+      }
+      add_link_flags(&mut command, project);
+      command
+    }
+    ProjectKind::Static => {
+      // `ar` adds to an existing archive, which would keep the objects of files that were since removed.
+      if output.exists() {
+        fs::remove_file(&output)
+          .map_err(|err| format!("could not remove \"{}\": {}", output.display(), err))?;
+      }
+      let mut command = Command::new(env::var("AR").unwrap_or_else(|_| "ar".to_string()));
+      command.arg("rcs").arg(&output).args(objects);
+      command
+    }
+  };
+  run(&mut command).map_err(|err| {
+    if is_host(&target_triple) || project.kind == ProjectKind::Static {
+      err
+    } else {
+      format!(
+        "{}\nlinking for `{}` needs a C compiler that can target it, such as clang, which can be picked with `CC`",
+        err, target_triple
+      )
+    }
+  })?;
+  Ok(output)
+}
+
+/// Gets the file name of the linked output, following the conventions of the target's platform.
+fn output_file_name(kind: ProjectKind, name: &str, target_triple: &str) -> String {
+  let windows = target_triple.contains("windows");
+  let apple = target_triple.contains("apple") || target_triple.contains("darwin");
+  match kind {
+    ProjectKind::Executable if windows => format!("{}.exe", name),
+    ProjectKind::Executable => name.to_string(),
+    ProjectKind::Static if target_triple.ends_with("msvc") => format!("{}.lib", name),
+    ProjectKind::Static => format!("lib{}.a", name),
+    ProjectKind::Shared if windows => format!("{}.dll", name),
+    ProjectKind::Shared if apple => format!("lib{}.dylib", name),
+    ProjectKind::Shared => format!("lib{}.so", name),
+  }
+}
+
+/// Whether a target triple is the host's. The vendor is ignored, as `x86_64-unknown-linux-gnu` and
+/// `x86_64-pc-linux-gnu` are the same target.
+fn is_host(target_triple: &str) -> bool {
+  fn without_vendor(target_triple: &str) -> Vec<&str> {
+    let mut parts: Vec<&str> = target_triple.split('-').collect();
+    if parts.len() >= 4 {
+      parts.remove(1);
+    }
+    parts
+  }
+  without_vendor(target_triple) == without_vendor(&llvm::default_target_triple())
+}
+
+/// Adds the library search paths and libraries from the project, and picks `lld` when it's available.
+fn add_link_flags(command: &mut Command, project: &Project) {
+  if has_lld() {
+    command.arg("-fuse-ld=lld");
+  }
+  for path in project.library_paths.iter().flatten() {
+    command.arg(format!("-L{}", path));
+  }
+  for library in project.libraries.iter().flatten() {
+    command.arg(format!("-l{}", library));
+  }
+}
+
+fn has_lld() -> bool {
+  env::var_os("PATH")
+    .map(|paths| env::split_paths(&paths).any(|dir| Path::new(&dir).join("ld.lld").is_file()))
+    .unwrap_or(false)
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+  let program = command.get_program().to_string_lossy().into_owned();
+  let output = command
+    .output()
+    .map_err(|err| format!("could not run `{}`: {}", program, err))?;
+  if output.status.success() {
+    Ok(())
+  } else {
+    Err(format!(
+      "`{}` failed with {}\n{}",
+      program,
+      output.status,
+      String::from_utf8_lossy(&output.stderr).trim_end()
+    ))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn output_file_names() {
+    let name = |kind, target_triple| output_file_name(kind, "app", target_triple);
+    assert_eq!(name(ProjectKind::Executable, "x86_64-pc-linux-gnu"), "app");
+    assert_eq!(
+      name(ProjectKind::Shared, "x86_64-pc-linux-gnu"),
+      "libapp.so"
+    );
+    assert_eq!(name(ProjectKind::Static, "x86_64-pc-linux-gnu"), "libapp.a");
+    assert_eq!(
+      name(ProjectKind::Shared, "aarch64-apple-darwin"),
+      "libapp.dylib"
+    );
+    assert_eq!(
+      name(ProjectKind::Executable, "x86_64-pc-windows-msvc"),
+      "app.exe"
+    );
+    assert_eq!(
+      name(ProjectKind::Shared, "x86_64-pc-windows-msvc"),
+      "app.dll"
+    );
+    assert_eq!(
+      name(ProjectKind::Static, "x86_64-pc-windows-msvc"),
+      "app.lib"
+    );
+    assert_eq!(
+      name(ProjectKind::Static, "x86_64-pc-windows-gnu"),
+      "libapp.a"
+    );
+
+    assert!(is_host(&llvm::default_target_triple()));
+  }
+}
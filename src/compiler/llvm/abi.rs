@@ -0,0 +1,305 @@
+use llvm_sys::core::*;
+use llvm_sys::prelude::*;
+use llvm_sys::LLVMTypeKind;
+use std::os::raw::c_uint;
+
+extern "C" {
+  // Part of LLVM's C API since LLVM 12, but missing from llvm-sys's bindings.
+  fn LLVMCreateTypeAttribute(
+    context: LLVMContextRef,
+    kind_id: c_uint,
+    type_ref: LLVMTypeRef,
+  ) -> LLVMAttributeRef;
+}
+
+/// A C calling convention that structs and enums can be passed by value with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+  /// The System V AMD64 ABI, used by x86-64 Linux, macOS and the BSDs.
+  SysV,
+  /// The Microsoft x64 calling convention.
+  Win64,
+  /// The procedure call standard of 64-bit ARM.
+  AArch64,
+}
+
+impl Target {
+  pub fn from_triple(triple: &str) -> Option<Target> {
+    let arch = triple.split('-').next().unwrap_or("");
+    match arch {
+      "x86_64" if triple.contains("windows") => Some(Target::Win64),
+      "x86_64" => Some(Target::SysV),
+      "aarch64" | "arm64" => Some(Target::AArch64),
+      _ => None,
+    }
+  }
+}
+
+/// The size and alignment of a value, along with the offset of each scalar it's made of. The scalars of an enum's
+/// variants overlap, like the members of a C union.
+pub struct Layout {
+  pub size: u64,
+  pub align: u32,
+  pub scalars: Vec<(u64, LLVMTypeRef)>,
+  /// Whether the value is a struct or enum rather than a single scalar.
+  pub aggregate: bool,
+}
+
+/// How a value is passed to or returned from a C function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PassMode {
+  /// Passed as the value itself.
+  Direct,
+  /// Reinterpreted as another type, which is at least as big, so it's passed in the registers C would use.
+  Coerce(LLVMTypeRef),
+  /// Passed as a pointer to a copy of the value, which is `byval` if the copy goes where stack arguments go.
+  /// Returned values are written through an `sret` pointer passed before the other arguments.
+  Indirect { byval: bool },
+}
+
+/// How each argument and the return value of a C function are passed.
+#[derive(Debug, Clone)]
+pub struct Signature {
+  pub args: Vec<(LLVMTypeRef, PassMode)>,
+  pub ret: (LLVMTypeRef, PassMode),
+}
+
+impl Signature {
+  /// Works out the signature for `target`, or `None` if a struct or enum is passed by value and the target's
+  /// calling convention isn't supported.
+  pub unsafe fn new(
+    target: Option<Target>,
+    context: LLVMContextRef,
+    args: Vec<(LLVMTypeRef, Layout)>,
+    ret: (LLVMTypeRef, Layout),
+  ) -> Option<Signature> {
+    let uses_aggregates = args.iter().chain([&ret]).any(|(_, x)| x.aggregate);
+    let target = match target {
+      Some(target) => target,
+      None if uses_aggregates => return None,
+      // Scalars are passed the same way LLVM passes them to any function.
+      None => Target::SysV,
+    };
+    let mut registers = Registers::new(target);
+    let ret_mode = registers.classify(context, &ret.1, true);
+    let args = args
+      .into_iter()
+      .map(|(ty, layout)| (ty, registers.classify(context, &layout, false)))
+      .collect();
+    Some(Signature {
+      args,
+      ret: (ret.0, ret_mode),
+    })
+  }
+
+  /// Gets the LLVM type of the function, with its arguments and return value lowered.
+  pub unsafe fn function_type(&self, context: LLVMContextRef) -> LLVMTypeRef {
+    let mut params = vec![];
+    if let (ty, PassMode::Indirect { .. }) = self.ret {
+      params.push(LLVMPointerType(ty, 0));
+    }
+    for (ty, mode) in &self.args {
+      params.push(match mode {
+        PassMode::Direct => *ty,
+        PassMode::Coerce(coerced) => *coerced,
+        PassMode::Indirect { .. } => LLVMPointerType(*ty, 0),
+      });
+    }
+    let ret = match self.ret {
+      (ty, PassMode::Direct) => ty,
+      (_, PassMode::Coerce(coerced)) => coerced,
+      (_, PassMode::Indirect { .. }) => LLVMVoidTypeInContext(context),
+    };
+    LLVMFunctionType(ret, params.as_mut_ptr(), params.len() as c_uint, 0)
+  }
+
+  /// Gets the `sret` and `byval` attributes of the lowered arguments, by attribute index.
+  pub unsafe fn attributes(&self, context: LLVMContextRef) -> Vec<(c_uint, LLVMAttributeRef)> {
+    let mut attributes = vec![];
+    // Index 0 is the return value, so arguments start at 1.
+    let mut index = 1;
+    if let (ty, PassMode::Indirect { .. }) = self.ret {
+      attributes.push((index, type_attribute(context, "sret", ty)));
+      index += 1;
+    }
+    for (ty, mode) in &self.args {
+      if let PassMode::Indirect { byval: true } = mode {
+        attributes.push((index, type_attribute(context, "byval", *ty)));
+      }
+      index += 1;
+    }
+    attributes
+  }
+}
+
+unsafe fn type_attribute(context: LLVMContextRef, name: &str, ty: LLVMTypeRef) -> LLVMAttributeRef {
+  let kind = LLVMGetEnumAttributeKindForName(name.as_ptr() as *const _, name.len());
+  LLVMCreateTypeAttribute(context, kind, ty)
+}
+
+/// The class of an eightbyte of a value passed under the System V ABI.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Class {
+  Integer,
+  Sse,
+}
+
+/// Keeps track of the argument registers left, as a struct that doesn't fit in them is passed in memory.
+struct Registers {
+  target: Target,
+  int: u32,
+  sse: u32,
+}
+
+impl Registers {
+  fn new(target: Target) -> Self {
+    Registers {
+      target,
+      int: 6,
+      sse: 8,
+    }
+  }
+
+  unsafe fn classify(&mut self, context: LLVMContextRef, layout: &Layout, ret: bool) -> PassMode {
+    if !layout.aggregate {
+      if !ret {
+        self.take_scalar(layout);
+      }
+      return PassMode::Direct;
+    }
+    match self.target {
+      Target::SysV => self.classify_sysv(context, layout, ret),
+      Target::Win64 => match layout.size {
+        1 | 2 | 4 | 8 => PassMode::Coerce(LLVMIntTypeInContext(context, layout.size as c_uint * 8)),
+        _ => PassMode::Indirect { byval: false },
+      },
+      Target::AArch64 => classify_aarch64(context, layout, ret),
+    }
+  }
+
+  /// Counts the registers a scalar argument takes up.
+  unsafe fn take_scalar(&mut self, layout: &Layout) {
+    match layout.scalars.first().map(|(_, ty)| is_float(*ty)) {
+      Some(true) => self.sse = self.sse.saturating_sub(1),
+      Some(false) => self.int = self.int.saturating_sub(layout.size.div_ceil(8) as u32),
+      None => {}
+    }
+  }
+
+  unsafe fn classify_sysv(
+    &mut self,
+    context: LLVMContextRef,
+    layout: &Layout,
+    ret: bool,
+  ) -> PassMode {
+    if layout.size > 16 {
+      if ret {
+        // The pointer to return through takes up the first integer register.
+        self.int -= 1;
+      }
+      return PassMode::Indirect { byval: !ret };
+    }
+    if layout.size == 0 {
+      return PassMode::Direct;
+    }
+    let eightbytes = layout.size.div_ceil(8);
+    let mut parts = vec![];
+    for idx in 0..eightbytes {
+      let start = idx * 8;
+      let scalars: Vec<&(u64, LLVMTypeRef)> = layout
+        .scalars
+        .iter()
+        .filter(|(offset, _)| (start..start + 8).contains(offset))
+        .collect();
+      let class = if !scalars.is_empty() && scalars.iter().all(|(_, ty)| is_float(*ty)) {
+        Class::Sse
+      } else {
+        Class::Integer
+      };
+      let ty = match class {
+        Class::Integer => {
+          let bytes = (layout.size - start).min(8);
+          LLVMIntTypeInContext(context, bytes as c_uint * 8)
+        }
+        Class::Sse if scalars.iter().any(|(_, ty)| is_double(*ty)) => {
+          LLVMDoubleTypeInContext(context)
+        }
+        // Floats in both halves of the eightbyte are passed together in one register.
+        Class::Sse if scalars.iter().any(|(offset, _)| *offset >= start + 4) => {
+          LLVMVectorType(LLVMFloatTypeInContext(context), 2)
+        }
+        Class::Sse => LLVMFloatTypeInContext(context),
+      };
+      parts.push((class, ty));
+    }
+    // Arguments are passed in memory when their eightbytes don't all fit in the registers that are left.
+    let int = parts
+      .iter()
+      .filter(|(class, _)| *class == Class::Integer)
+      .count() as u32;
+    let sse = parts.len() as u32 - int;
+    if !ret {
+      if int > self.int || sse > self.sse {
+        return PassMode::Indirect { byval: true };
+      }
+      self.int -= int;
+      self.sse -= sse;
+    }
+    let mut types: Vec<LLVMTypeRef> = parts.into_iter().map(|(_, ty)| ty).collect();
+    if types.len() == 1 {
+      PassMode::Coerce(types[0])
+    } else {
+      PassMode::Coerce(LLVMStructTypeInContext(
+        context,
+        types.as_mut_ptr(),
+        types.len() as c_uint,
+        0,
+      ))
+    }
+  }
+}
+
+unsafe fn classify_aarch64(context: LLVMContextRef, layout: &Layout, ret: bool) -> PassMode {
+  // Homogeneous floating-point aggregates of up to four members are passed in floating-point registers.
+  if let Some((_, first)) = layout.scalars.first() {
+    let count = layout.scalars.len();
+    let kind = LLVMGetTypeKind(*first);
+    if is_float(*first)
+      && count <= 4
+      && layout
+        .scalars
+        .iter()
+        .all(|(_, ty)| LLVMGetTypeKind(*ty) == kind)
+      && layout.scalars.windows(2).all(|x| x[0].0 != x[1].0)
+    {
+      return PassMode::Coerce(LLVMArrayType(*first, count as c_uint));
+    }
+  }
+  if layout.size > 16 {
+    return PassMode::Indirect { byval: false };
+  }
+  if ret && layout.size <= 8 {
+    return PassMode::Coerce(LLVMIntTypeInContext(context, layout.size as c_uint * 8));
+  }
+  if layout.align >= 16 {
+    return PassMode::Coerce(LLVMIntTypeInContext(context, 128));
+  }
+  let words = layout.size.div_ceil(8) as c_uint;
+  let word = LLVMInt64TypeInContext(context);
+  if words == 1 {
+    PassMode::Coerce(word)
+  } else {
+    PassMode::Coerce(LLVMArrayType(word, words))
+  }
+}
+
+unsafe fn is_float(ty: LLVMTypeRef) -> bool {
+  matches!(
+    LLVMGetTypeKind(ty),
+    LLVMTypeKind::LLVMFloatTypeKind | LLVMTypeKind::LLVMDoubleTypeKind
+  )
+}
+
+unsafe fn is_double(ty: LLVMTypeRef) -> bool {
+  LLVMGetTypeKind(ty) == LLVMTypeKind::LLVMDoubleTypeKind
+}
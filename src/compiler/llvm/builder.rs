@@ -9,11 +9,11 @@ pub struct Builder {
 }
 
 impl Builder {
-  /// Create a new Builder in LLVM's global context.
-  pub fn new() -> Self {
+  /// Create a new Builder in the given context.
+  pub fn new(context: LLVMContextRef) -> Self {
     unsafe {
       Builder {
-        builder: LLVMCreateBuilder(),
+        builder: LLVMCreateBuilderInContext(context),
       }
     }
   }
@@ -23,6 +23,17 @@ impl Builder {
       LLVMPositionBuilderAtEnd(self.builder, bb);
     }
   }
+
+  pub fn position_before(&self, instruction: LLVMValueRef) {
+    unsafe {
+      LLVMPositionBuilderBefore(self.builder, instruction);
+    }
+  }
+
+  /// Gets the block the builder is currently inserting into.
+  pub fn insert_block(&self) -> LLVMBasicBlockRef {
+    unsafe { LLVMGetInsertBlock(self.builder) }
+  }
 }
 
 impl Drop for Builder {
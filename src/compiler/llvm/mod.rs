@@ -1,20 +1,29 @@
+mod abi;
 mod builder;
 mod module;
 mod target_machine;
+use super::{BuildOptions, Emit};
 use crate::ast::*;
-use crate::parser::SourceFile;
+use crate::parser::{Item, SourceFile};
 use llvm_sys::core::*;
 use llvm_sys::prelude::*;
-use llvm_sys::{LLVMAttributeIndex, LLVMIntPredicate, LLVMLinkage};
+use llvm_sys::{
+  LLVMAttributeIndex, LLVMIntPredicate, LLVMLinkage, LLVMRealPredicate, LLVMTypeKind,
+  LLVMUnnamedAddr, LLVMVisibility,
+};
 
+use abi::{Layout, PassMode, Signature};
 use builder::Builder;
 use llvm_sys::target::*;
+use llvm_sys::target_machine::LLVMCreateTargetDataLayout;
 use module::Module;
 use target_machine::TargetMachine;
 
+use std::collections::HashMap;
 use std::ffi::CString;
+use std::fs;
 use std::os::raw::{c_char, c_uint, c_ulonglong};
-use std::path::Path;
+use std::path::PathBuf;
 use std::ptr;
 
 const LLVM_FALSE: LLVMBool = 0;
@@ -26,13 +35,29 @@ macro_rules! c_str {
   };
 }
 
+#[allow(dead_code)]
 struct CompileContext {
   pub module: Module,
   pub context: LLVMContextRef,
   pub attribute_index: LLVMAttributeIndex,
   pub functions: Vec<FunctionRef>,
+  /// The builder used for every instruction, positioned where the next one should go.
+  pub builder: Builder,
+  /// The fields of every struct declared in the module, in the order they're laid out in.
+  pub structs: HashMap<String, Vec<(String, Type)>>,
+  /// The variants of every enum declared in the module.
+  pub enums: HashMap<String, Vec<Variant>>,
+  /// The sizes and alignments of types on the target.
+  pub target_data: LLVMTargetDataRef,
+  /// The C calling convention of the target, if structs and enums can be passed by value with it.
+  pub abi: Option<abi::Target>,
+  /// How the arguments of every `declare`d function are passed, which follows the C calling convention.
+  pub c_functions: HashMap<String, Signature>,
+  /// Whether indexing into an array traps when the index is out of bounds, as it does in debug builds.
+  pub bounds_checks: bool,
 }
 
+#[allow(dead_code)]
 struct FunctionRef {
   pub fn_ref: LLVMValueRef,
   pub args: Vec<(String, LLVMTypeRef)>,
@@ -41,50 +66,151 @@ struct FunctionRef {
   pub external: bool,
   /// Vec<(name, mutable, value)>
   pub vars: Vec<(String, bool, LLVMValueRef)>,
+  /// Vec<(label, continue block, break block)> for the loops being built, innermost last.
+  pub loops: Vec<(Option<String>, LLVMBasicBlockRef, LLVMBasicBlockRef)>,
 }
 
-pub fn compile(source: &SourceFile, target_triple: Option<String>) -> Result<(), String> {
+impl FunctionRef {
+  /// Exported functions can be linked against by other object files. The rest are internal, so LLVM can inline them
+  /// and leave them out when they're unused. `main` is called by the C runtime, so it's always external.
+  fn linkage(&self) -> LLVMLinkage {
+    if self.exported || self.name == "main" {
+      LLVMLinkage::LLVMExternalLinkage
+    } else {
+      LLVMLinkage::LLVMInternalLinkage
+    }
+  }
+}
+
+pub fn compile(
+  source: &SourceFile,
+  options: &BuildOptions,
+  imports: &HashMap<String, Item>,
+) -> Result<Option<PathBuf>, String> {
+  if let Some(dir) = options.output_path(&source.path, "").parent() {
+    fs::create_dir_all(dir)
+      .map_err(|err| format!("could not create \"{}\": {}", dir.display(), err))?;
+  }
+  build_module(source, options, imports, |module| {
+    emit(module, source, options)
+  })
+}
+
+/// Builds the LLVM module of a file, handing it to `output` to be written out.
+/// Every file gets its own context, so the types a file keeps to itself never clash with another file's.
+fn build_module<T>(
+  source: &SourceFile,
+  options: &BuildOptions,
+  imports: &HashMap<String, Item>,
+  output: impl FnOnce(&mut Module) -> Result<T, String>,
+) -> Result<T, String> {
+  let context = unsafe { LLVMContextCreate() };
+  let result = build_in_context(context, source, options, imports, output);
+  unsafe {
+    LLVMContextDispose(context);
+  }
+  result
+}
+
+fn build_in_context<T>(
+  context: LLVMContextRef,
+  source: &SourceFile,
+  options: &BuildOptions,
+  imports: &HashMap<String, Item>,
+  output: impl FnOnce(&mut Module) -> Result<T, String>,
+) -> Result<T, String> {
+  let target_triple_cstring = if let Some(target_triple) = &options.target_triple {
+    CString::new(target_triple.as_str()).unwrap()
+  } else {
+    TargetMachine::get_default_target_triple()
+  };
+  // This is necessary for maximum LLVM performance, see
+  // http://llvm.org/docs/Frontend/PerformanceTips.html
+  let target_machine = TargetMachine::new(target_triple_cstring.as_ptr())?;
+
   let c_module_name = CString::new(source.path.clone()).unwrap();
   let module_name_char_ptr = c_module_name.to_bytes_with_nul().as_ptr() as *const _;
-  let llvm_module = unsafe { LLVMModuleCreateWithName(module_name_char_ptr) };
+  let llvm_module = unsafe { LLVMModuleCreateWithNameInContext(module_name_char_ptr, context) };
+  let target_data = unsafe {
+    LLVMSetTarget(llvm_module, target_triple_cstring.as_ptr() as *const _);
+    let target_data = LLVMCreateTargetDataLayout(target_machine.tm);
+    LLVMSetModuleDataLayout(llvm_module, target_data);
+    target_data
+  };
+
   let mut ctx = CompileContext {
     module: Module {
       module: llvm_module,
       strings: vec![c_module_name],
     },
-    context: unsafe { LLVMContextCreate() },
+    context,
     attribute_index: 0,
     functions: vec![],
+    builder: Builder::new(context),
+    structs: HashMap::new(),
+    enums: HashMap::new(),
+    target_data,
+    abi: abi::Target::from_triple(&target_triple_cstring.to_string_lossy()),
+    c_functions: HashMap::new(),
+    bounds_checks: !options.release,
   };
 
-  let target_triple_cstring = if let Some(target_triple) = target_triple {
-    CString::new(target_triple).unwrap()
-  } else {
-    TargetMachine::get_default_target_triple()
-  };
-
-  // This is necessary for maximum LLVM performance, see
-  // http://llvm.org/docs/Frontend/PerformanceTips.html
+  let result = unsafe { declare_types(&mut ctx, &source.expressions, imports) }.and_then(|_| {
+    unsafe {
+      declare_imports(&mut ctx, imports);
+      define_globals(&mut ctx, &source.expressions);
+      declare_c_functions(
+        &mut ctx,
+        &source.expressions,
+        imports,
+        &target_triple_cstring.to_string_lossy(),
+      )?;
+    }
+    for expr in &source.expressions {
+      unsafe {
+        compile_expression(&mut ctx, expr, false, false);
+      }
+    }
+    output(&mut ctx.module)
+  });
   unsafe {
-    LLVMSetTarget(llvm_module, target_triple_cstring.as_ptr() as *const _);
+    LLVMDisposeTargetData(ctx.target_data);
   }
-  for expr in &source.expressions {
-    unsafe {
-      compile_expression(&mut ctx, expr, false, false);
+  result
+}
+
+/// Writes each kind of output the options ask for, returning the path of the object file if one was written.
+fn emit(
+  module: &mut Module,
+  source: &SourceFile,
+  options: &BuildOptions,
+) -> Result<Option<PathBuf>, String> {
+  let mut object = None;
+  for emit in Emit::ALL.iter().filter(|x| options.emit.contains(x)) {
+    let path = options.output_path(&source.path, emit.extension());
+    match emit {
+      Emit::LlvmIr => module.write_ir_file(&path)?,
+      Emit::LlvmBitcode => module.write_bitcode_file(&path)?,
+      Emit::Assembly => module.write_assembly_file(&path)?,
+      Emit::Object => module.write_object_file(&path)?,
+    }
+    println!(
+      "[plume] exported {} \"{}\"",
+      emit.description(),
+      path.display()
+    );
+    if *emit == Emit::Object {
+      object = Some(path);
     }
   }
+  Ok(object)
+}
 
-  let path = Path::new(&source.path);
-  match ctx
-    .module
-    .write_ir_file(path.with_extension("ll").as_path())
-  {
-    Ok(_) => println!("[plume] exported LLVM IR \"{}\"", source.path),
-    Err(err) => println!("[plume] error while exporting LLVM IR: {}", err),
-  };
-  ctx
-    .module
-    .write_object_file(path.with_extension("o").as_path())
+/// Gets the target triple of the host, which is compiled for when no target is given.
+pub fn default_target_triple() -> String {
+  TargetMachine::get_default_target_triple()
+    .to_string_lossy()
+    .into_owned()
 }
 
 pub fn init_llvm() {
@@ -98,6 +224,352 @@ pub fn init_llvm() {
   }
 }
 
+/// Creates the LLVM types of the structs and enums declared in or imported by a file, before anything can use them.
+/// They are all named before any is given a body, as their fields can refer to types declared after them.
+unsafe fn declare_types(
+  ctx: &mut CompileContext,
+  expressions: &[Expression],
+  imports: &HashMap<String, Item>,
+) -> Result<(), String> {
+  let mut structs: Vec<(String, Vec<(String, Type)>)> = vec![];
+  let mut enums: Vec<(String, Vec<Variant>)> = vec![];
+  let imported = imports.values().map(|x| x.ty.clone());
+  for expr in expressions {
+    let kind = match &expr.kind {
+      ExpressionKind::Export(inner) => &inner.kind,
+      kind => kind,
+    };
+    match kind {
+      ExpressionKind::Struct { name, fields } => structs.push((name.clone(), fields.clone())),
+      ExpressionKind::Enum { name, variants } => enums.push((name.clone(), variants.clone())),
+      _ => {}
+    }
+  }
+  for ty in imported {
+    match ty {
+      Type::Struct { name, fields } => structs.push((name, fields)),
+      Type::Enum { name, variants } => enums.push((name, variants)),
+      _ => {}
+    }
+  }
+  for (name, fields) in &structs {
+    declare_named_type(ctx, name);
+    ctx.structs.insert(name.to_string(), fields.clone());
+  }
+  for (name, variants) in &enums {
+    declare_named_type(ctx, name);
+    ctx.enums.insert(name.to_string(), variants.to_vec());
+  }
+  // Each file has its own LLVM context, so an imported type is laid out from its definition again. The types its
+  // fields use have to be declared in this file too, which they aren't if the defining file keeps them to itself.
+  let field_types = structs
+    .iter()
+    .flat_map(|(name, fields)| fields.iter().map(move |(_, ty)| (name, ty)))
+    .chain(enums.iter().flat_map(|(name, variants)| {
+      variants
+        .iter()
+        .flat_map(move |x| x.fields.iter().map(move |ty| (name, ty)))
+    }));
+  for (name, ty) in field_types {
+    if let Some(used) = undeclared_type(ctx, ty) {
+      return Err(format!(
+        "`{}` uses `{}`, which has to be imported along with it",
+        name, used
+      ));
+    }
+  }
+  // Fields are laid out like a C struct with the same fields, so structs can be shared with C code.
+  for (name, fields) in structs {
+    let field_types = fields
+      .iter()
+      .map(|(_, ty)| get_type(ctx.context, ty))
+      .collect();
+    set_body(ctx, &name, field_types);
+  }
+  // An enum's size depends on the types its variants hold, so enums holding other enums are laid out after them.
+  // Types that hold each other by value would never be laid out, but the validator rejects those.
+  let mut pending = enums;
+  while !pending.is_empty() {
+    let count = pending.len();
+    let mut rest = vec![];
+    for (name, variants) in pending {
+      match enum_body(ctx, &variants) {
+        Some(body) => set_body(ctx, &name, body),
+        None => rest.push((name, variants)),
+      }
+    }
+    if rest.len() == count {
+      break;
+    }
+    pending = rest;
+  }
+  Ok(())
+}
+
+/// Creates a named struct type, which is given its fields once every type has a name.
+unsafe fn declare_named_type(ctx: &mut CompileContext, name: &str) {
+  LLVMStructCreateNamed(ctx.context, ctx.module.new_string_ptr(name));
+}
+
+unsafe fn set_body(ctx: &CompileContext, name: &str, mut body: Vec<LLVMTypeRef>) {
+  let ty = get_type(ctx.context, &Type::Named(name.to_string()));
+  LLVMStructSetBody(ty, body.as_mut_ptr(), body.len() as c_uint, LLVM_FALSE);
+}
+
+/// Finds a struct or enum used by a type that isn't declared in the module.
+fn undeclared_type<'a>(ctx: &CompileContext, ty: &'a Type) -> Option<&'a str> {
+  match ty {
+    Type::Named(name) | Type::Struct { name, .. } | Type::Enum { name, .. } => {
+      Some(name.as_str()).filter(|x| !ctx.structs.contains_key(*x) && !ctx.enums.contains_key(*x))
+    }
+    Type::Pointer(ty) | Type::Array(ty, _) => undeclared_type(ctx, ty),
+    Type::Function { args, ret } => args
+      .iter()
+      .chain([&**ret])
+      .find_map(|x| undeclared_type(ctx, x)),
+    _ => None,
+  }
+}
+
+/// Declares the functions and globals a file imports, which are linked with the object file of the file that defines them.
+unsafe fn declare_imports(ctx: &mut CompileContext, imports: &HashMap<String, Item>) {
+  // Sorting keeps the declarations in the same order every time.
+  let mut imports: Vec<(&String, &Item)> = imports.iter().collect();
+  imports.sort_by_key(|(name, _)| *name);
+  for (name, item) in imports {
+    match &item.ty {
+      Type::Struct { .. } | Type::Enum { .. } => {}
+      // Functions from C are declared along with the file's own by `declare_c_functions`.
+      Type::Function { .. } if item.declared => {}
+      // Function types are pointers to the function.
+      Type::Function { .. } => {
+        let fn_type = LLVMGetElementType(get_type(ctx.context, &item.ty));
+        LLVMAddFunction(ctx.module.module, ctx.module.new_string_ptr(name), fn_type);
+      }
+      ty => {
+        let global = LLVMAddGlobal(
+          ctx.module.module,
+          get_type(ctx.context, ty),
+          ctx.module.new_string_ptr(name),
+        );
+        LLVMSetGlobalConstant(global, llvm_bool(!item.mutable));
+      }
+    }
+  }
+}
+
+/// Adds the variables declared at the top level of a file as globals, before any function can use them.
+/// Exported globals are visible to other object files, and `declare`d ones are defined by another one.
+unsafe fn define_globals(ctx: &mut CompileContext, expressions: &[Expression]) {
+  for expr in expressions {
+    let (expr, linkage, defined) = match &expr.kind {
+      ExpressionKind::Export(inner) => (&**inner, LLVMLinkage::LLVMExternalLinkage, true),
+      ExpressionKind::Declare(inner) => (&**inner, LLVMLinkage::LLVMExternalLinkage, false),
+      _ => (expr, LLVMLinkage::LLVMInternalLinkage, true),
+    };
+    let (declaration, init) = match &expr.kind {
+      ExpressionKind::BinaryOperation {
+        operator: BinaryOperator::Assign,
+        lhs,
+        rhs,
+      } => (&**lhs, Some(&**rhs)),
+      _ => (expr, None),
+    };
+    if let ExpressionKind::VariableDeclaration { name, ty, mutable } = &declaration.kind {
+      let llvm_type = get_type(ctx.context, ty);
+      let global = LLVMAddGlobal(
+        ctx.module.module,
+        llvm_type,
+        ctx.module.new_string_ptr(name),
+      );
+      LLVMSetLinkage(global, linkage);
+      LLVMSetGlobalConstant(global, llvm_bool(!mutable));
+      if !defined {
+        continue;
+      }
+      // The type checker makes sure initializers are constant, which LLVM folds as they're built.
+      // Globals without one start out zeroed, like in C.
+      let value = match init.and_then(|init| Some((init, build_body(ctx, None, init)?))) {
+        Some((init, value)) => build_conversion(ctx, value, &value_type(init), llvm_type),
+        None => LLVMConstNull(llvm_type),
+      };
+      LLVMSetInitializer(global, value);
+    }
+  }
+}
+
+/// Declares the functions that `declare` brings in from C, including those imported from other files. Their structs
+/// and enums are passed by value the way the target's C calling convention passes them, which often means splitting
+/// them across registers.
+unsafe fn declare_c_functions(
+  ctx: &mut CompileContext,
+  expressions: &[Expression],
+  imports: &HashMap<String, Item>,
+  target_triple: &str,
+) -> Result<(), String> {
+  let mut functions = vec![];
+  let mut imports: Vec<(&String, &Item)> =
+    imports.iter().filter(|(_, item)| item.declared).collect();
+  imports.sort_by_key(|(name, _)| *name);
+  for (name, item) in imports {
+    if let Type::Function { args, ret } = &item.ty {
+      functions.push((name, args.clone(), &**ret));
+    }
+  }
+  for expr in expressions {
+    let inner = match &expr.kind {
+      ExpressionKind::Declare(inner) => inner,
+      ExpressionKind::Export(inner) => match &inner.kind {
+        ExpressionKind::Declare(inner) => inner,
+        _ => continue,
+      },
+      _ => continue,
+    };
+    if let ExpressionKind::Function {
+      name, args, ret, ..
+    } = &inner.kind
+    {
+      functions.push((name, args.iter().map(|(_, ty)| ty.clone()).collect(), ret));
+    }
+  }
+  for (name, args, ret) in functions {
+    let args = args
+      .iter()
+      .map(|ty| (get_type(ctx.context, ty), layout(ctx, ty)))
+      .collect();
+    let ret = (get_type(ctx.context, ret), layout(ctx, ret));
+    let signature = Signature::new(ctx.abi, ctx.context, args, ret).ok_or_else(|| {
+      format!(
+        "`{}` takes or returns a struct or enum by value, which isn't supported for C functions on `{}` yet",
+        name, target_triple
+      )
+    })?;
+    let func = LLVMAddFunction(
+      ctx.module.module,
+      ctx.module.new_string_ptr(name),
+      signature.function_type(ctx.context),
+    );
+    for (index, attribute) in signature.attributes(ctx.context) {
+      LLVMAddAttributeAtIndex(func, index, attribute);
+    }
+    ctx.c_functions.insert(name.clone(), signature);
+  }
+  Ok(())
+}
+
+/// Gets the layout of a value, which decides how C functions are passed it.
+unsafe fn layout(ctx: &CompileContext, ty: &Type) -> Layout {
+  let llvm_type = get_type(ctx.context, ty);
+  // `void` has no size, and is returned the way LLVM returns it.
+  if LLVMTypeIsSized(llvm_type) != LLVM_TRUE {
+    return Layout {
+      size: 0,
+      align: 1,
+      scalars: vec![],
+      aggregate: false,
+    };
+  }
+  let mut scalars = vec![];
+  collect_scalars(ctx, ty, 0, &mut scalars);
+  // The variants of an enum overlap, so their scalars are put back in order.
+  scalars.sort_by_key(|(offset, _)| *offset);
+  Layout {
+    size: LLVMABISizeOfType(ctx.target_data, llvm_type),
+    align: LLVMABIAlignmentOfType(ctx.target_data, llvm_type),
+    scalars,
+    aggregate: LLVMGetTypeKind(llvm_type) == LLVMTypeKind::LLVMStructTypeKind,
+  }
+}
+
+/// Collects the scalars a value is made of, along with their offsets.
+unsafe fn collect_scalars(
+  ctx: &CompileContext,
+  ty: &Type,
+  offset: u64,
+  scalars: &mut Vec<(u64, LLVMTypeRef)>,
+) {
+  let llvm_type = get_type(ctx.context, ty);
+  let offset_of = |ty, idx| offset + LLVMOffsetOfElement(ctx.target_data, ty, idx as c_uint);
+  match ty {
+    Type::Named(name) | Type::Struct { name, .. } | Type::Enum { name, .. } => {
+      if let Some(fields) = ctx.structs.get(name) {
+        for (idx, (_, field)) in fields.iter().enumerate() {
+          collect_scalars(ctx, field, offset_of(llvm_type, idx), scalars);
+        }
+      } else if let Some(variants) = ctx.enums.get(name) {
+        scalars.push((offset, LLVMInt32TypeInContext(ctx.context)));
+        // Like the members of a C union, the values of every variant start where the payload does.
+        if LLVMCountStructElementTypes(llvm_type) == 2 {
+          let payload = offset_of(llvm_type, 1);
+          for variant in variants {
+            let variant_type = variant_type(ctx, variant);
+            for (idx, field) in variant.fields.iter().enumerate() {
+              let field_offset =
+                payload + LLVMOffsetOfElement(ctx.target_data, variant_type, idx as c_uint);
+              collect_scalars(ctx, field, field_offset, scalars);
+            }
+          }
+        }
+      }
+    }
+    Type::Array(elem, Some(len)) => {
+      let size = LLVMABISizeOfType(ctx.target_data, get_type(ctx.context, elem));
+      for idx in 0..*len as u64 {
+        collect_scalars(ctx, elem, offset + idx * size, scalars);
+      }
+    }
+    Type::Void => {}
+    _ => scalars.push((offset, llvm_type)),
+  }
+}
+
+/// Gets the layout of an enum, which is its `i32` tag followed by room for the values of its largest variant.
+/// A C-like enum is only the tag, which is laid out like the `int` of a C enum.
+/// Returns `None` if the types its variants hold haven't been laid out yet.
+unsafe fn enum_body(ctx: &CompileContext, variants: &[Variant]) -> Option<Vec<LLVMTypeRef>> {
+  let payloads: Vec<LLVMTypeRef> = variants
+    .iter()
+    .filter(|x| !x.fields.is_empty())
+    .map(|x| variant_type(ctx, x))
+    .collect();
+  if payloads.iter().any(|x| LLVMTypeIsSized(*x) == LLVM_FALSE) {
+    return None;
+  }
+  let mut body = vec![LLVMInt32TypeInContext(ctx.context)];
+  let size = payloads
+    .iter()
+    .map(|x| LLVMABISizeOfType(ctx.target_data, *x))
+    .max();
+  let align = payloads
+    .iter()
+    .map(|x| LLVMABIAlignmentOfType(ctx.target_data, *x))
+    .max();
+  // Like a C union, the payload is as aligned as its most aligned variant.
+  if let (Some(size), Some(align)) = (size, align) {
+    let align = align as c_ulonglong;
+    body.push(LLVMArrayType(
+      LLVMIntTypeInContext(ctx.context, align as c_uint * 8),
+      size.div_ceil(align) as c_uint,
+    ));
+  }
+  Some(body)
+}
+
+/// Gets the type of the values held by an enum variant, which are stored as a struct in the enum's payload.
+unsafe fn variant_type(ctx: &CompileContext, variant: &Variant) -> LLVMTypeRef {
+  let mut fields: Vec<LLVMTypeRef> = variant
+    .fields
+    .iter()
+    .map(|x| get_type(ctx.context, x))
+    .collect();
+  LLVMStructTypeInContext(
+    ctx.context,
+    fields.as_mut_ptr(),
+    fields.len() as c_uint,
+    LLVM_FALSE,
+  )
+}
+
 unsafe fn compile_expression(
   ctx: &mut CompileContext,
   expression: &Expression,
@@ -105,14 +577,13 @@ unsafe fn compile_expression(
   exported: bool,
 ) {
   // println!("compile_expression: {:?}", expression);
-  match expression {
-    Expression::Export(expr) => {
+  match &expression.kind {
+    ExpressionKind::Export(expr) => {
       compile_expression(ctx, expr, true, true);
     }
-    Expression::Declare(expr) => {
-      compile_expression(ctx, expr, true, false);
-    }
-    Expression::Function {
+    // Functions from C are declared by `declare_c_functions`, and globals by `define_globals`.
+    ExpressionKind::Declare(_) => {}
+    ExpressionKind::Function {
       name,
       ret,
       args,
@@ -120,11 +591,11 @@ unsafe fn compile_expression(
     } => {
       let typed_args: Vec<(String, LLVMTypeRef)> = args
         .iter()
-        .map(|(name, ty)| (name.clone(), get_type(ty)))
+        .map(|(name, ty)| (name.clone(), get_type(ctx.context, ty)))
         .collect();
-      let mut args_type: Vec<LLVMTypeRef> = typed_args.iter().map(|(_, ty)| ty.clone()).collect();
+      let mut args_type: Vec<LLVMTypeRef> = typed_args.iter().map(|(_, ty)| *ty).collect();
       let fn_type = LLVMFunctionType(
-        get_type(ret),
+        get_type(ctx.context, ret),
         args_type.as_mut_ptr(),
         args.len() as u32,
         LLVM_FALSE,
@@ -134,21 +605,38 @@ unsafe fn compile_expression(
         ctx.module.new_string_ptr(name.as_ref()),
         fn_type,
       );
-      if ext {
-        LLVMSetLinkage(func, LLVMLinkage::LLVMExternalLinkage)
-      }
-      if exported {}
+      // A function without a body is only declared, and another object file defines it, e.g. the C library.
+      // The validator makes sure `declare`d functions have no body.
       if let Some(body) = body {
-        let func_ref = FunctionRef {
+        let mut func_ref = FunctionRef {
           fn_ref: func,
           args: typed_args,
           name: name.to_string(),
           exported,
           external: ext,
           vars: vec![],
+          loops: vec![],
         };
-        let bb = LLVMAppendBasicBlock(func, ctx.module.new_string_ptr("entry"));
-        build_body(ctx, bb, Some(&func_ref), body);
+        LLVMSetLinkage(func, func_ref.linkage());
+        LLVMSetVisibility(func, LLVMVisibility::LLVMDefaultVisibility);
+        let bb =
+          LLVMAppendBasicBlockInContext(ctx.context, func, ctx.module.new_string_ptr("entry"));
+        ctx.builder.position_at_end(bb);
+        // Arguments are spilled to the stack, so that they can be reassigned like any other variable.
+        for (idx, (name, ty)) in func_ref.args.iter().enumerate() {
+          let ptr = build_alloca(ctx, func, *ty, name);
+          LLVMBuildStore(ctx.builder.builder, LLVMGetParam(func, idx as c_uint), ptr);
+          func_ref.vars.push((name.clone(), true, ptr));
+        }
+        build_body(ctx, Some(&mut func_ref), body);
+        // The end of a function that doesn't return a value can be reached without a `return`.
+        if !is_terminated(ctx) {
+          if *ret == Type::Void {
+            LLVMBuildRetVoid(ctx.builder.builder);
+          } else {
+            LLVMBuildUnreachable(ctx.builder.builder);
+          }
+        }
         ctx.functions.push(func_ref);
       }
     }
@@ -158,117 +646,358 @@ unsafe fn compile_expression(
 
 unsafe fn build_body(
   ctx: &mut CompileContext,
-  bb: LLVMBasicBlockRef,
-  func_ref: Option<&FunctionRef>,
+  mut func_ref: Option<&mut FunctionRef>,
   expression: &Expression,
 ) -> Option<LLVMValueRef> {
   // println!("build body expr: {:?}", expression);
-  match expression {
+  match &expression.kind {
     // Expression::FuncCall(name, args) => {
     //   build_func_call(module, bb, name, args, "");
     // }
-    Expression::Number(value) => Some(get_value(&String::from("i8"), value)),
-    Expression::Char(value) => Some(get_value(&String::from("char"), &value.to_string())),
-    Expression::FuncCall(name, args) => {
-      Some(build_func_call(ctx, func_ref, bb, &name.as_str(), args, ""))
-    }
-    Expression::VariableRef(name) => {
-      if let Some(fn_ref) = func_ref {
-        if let Some(pos) = fn_ref.args.iter().position(|(x, _)| x == name) {
-          return Some(LLVMGetParam(fn_ref.fn_ref, pos as c_uint));
-        } else if let Some(var) = fn_ref.vars.iter().find(|(x, _, _)| x == name) {
-          return Some(var.2);
-        } else {
-          let global = LLVMGetNamedGlobal(
-            ctx.module.module,
-            ctx.module.new_mut_string_ptr(name.as_str()),
-          );
-          if global == ptr::null_mut() {
+    ExpressionKind::Number(value) | ExpressionKind::Decimal(value) => {
+      Some(get_value(ctx, &value_type(expression), value))
+    }
+    ExpressionKind::String(value) => Some(build_string(ctx, value)),
+    ExpressionKind::Char(value) => Some(LLVMConstInt(
+      LLVMInt8TypeInContext(ctx.context),
+      *value as c_char as c_ulonglong,
+      LLVM_FALSE,
+    )),
+    ExpressionKind::Bool(value) => Some(LLVMConstInt(
+      LLVMInt1TypeInContext(ctx.context),
+      *value as c_ulonglong,
+      LLVM_FALSE,
+    )),
+    ExpressionKind::FuncCall(name, args) => {
+      Some(build_func_call(ctx, func_ref, name.as_str(), args, ""))
+    }
+    ExpressionKind::VariableRef(name) => {
+      let ptr = match get_variable(ctx, func_ref.as_deref(), name) {
+        Some(ptr) => ptr,
+        // A function used as a value is a pointer to it.
+        None => {
+          let function = LLVMGetNamedFunction(ctx.module.module, ctx.module.new_string_ptr(name));
+          if function.is_null() {
             return None;
-          } else {
-            return Some(global);
           }
+          let ty = get_type(ctx.context, &value_type(expression));
+          return Some(LLVMBuildBitCast(
+            ctx.builder.builder,
+            function,
+            ty,
+            c_str!(""),
+          ));
         }
-      }
+      };
+      Some(LLVMBuildLoad(
+        ctx.builder.builder,
+        ptr,
+        ctx.module.new_string_ptr(name),
+      ))
+    }
+    ExpressionKind::VariableDeclaration { name, ty, mutable } => {
+      let func_ref = func_ref?;
+      let ptr = build_alloca(ctx, func_ref.fn_ref, get_type(ctx.context, ty), name);
+      func_ref.vars.push((name.clone(), *mutable, ptr));
       None
     }
-    // Expression::UnaryOperation { expr, operator, position } => {
-
-    // },
-    Expression::BinaryOperation { lhs, rhs, operator } => {
-      let lhs = build_body(ctx, bb, func_ref, lhs).expect("");
-      let rhs = build_body(ctx, bb, func_ref, rhs).expect("");
-      let builder = Builder::new();
-      builder.position_at_end(bb);
-      match operator {
-        BinaryOperator::Add => Some(LLVMBuildAdd(builder.builder, lhs, rhs, c_str!(""))),
-        BinaryOperator::Subtract => Some(LLVMBuildSub(builder.builder, lhs, rhs, c_str!(""))),
-        BinaryOperator::Multiply => Some(LLVMBuildMul(builder.builder, lhs, rhs, c_str!(""))),
-        BinaryOperator::Divide => Some(LLVMBuildFDiv(builder.builder, lhs, rhs, c_str!(""))),
-        BinaryOperator::BitAND => Some(LLVMBuildAnd(builder.builder, lhs, rhs, c_str!(""))),
-        BinaryOperator::BitOR => Some(LLVMBuildOr(builder.builder, lhs, rhs, c_str!(""))),
-        BinaryOperator::BitXOR => Some(LLVMBuildXor(builder.builder, lhs, rhs, c_str!(""))),
-        BinaryOperator::Eq => Some(LLVMBuildICmp(
-          builder.builder,
-          LLVMIntPredicate::LLVMIntEQ,
-          lhs,
-          rhs,
-          c_str!(""),
-        )),
-        BinaryOperator::Ne => Some(LLVMBuildICmp(
-          builder.builder,
-          LLVMIntPredicate::LLVMIntNE,
-          lhs,
-          rhs,
-          c_str!(""),
-        )),
-        BinaryOperator::Ge => Some(LLVMBuildICmp(
-          builder.builder,
-          LLVMIntPredicate::LLVMIntSGE,
-          lhs,
-          rhs,
-          c_str!(""),
-        )),
-        BinaryOperator::Gt => Some(LLVMBuildICmp(
-          builder.builder,
-          LLVMIntPredicate::LLVMIntSGE,
-          lhs,
-          rhs,
-          c_str!(""),
-        )),
-        BinaryOperator::Le => Some(LLVMBuildICmp(
-          builder.builder,
-          LLVMIntPredicate::LLVMIntSLT,
-          lhs,
-          rhs,
-          c_str!(""),
-        )),
-        BinaryOperator::Lt => Some(LLVMBuildICmp(
-          builder.builder,
-          LLVMIntPredicate::LLVMIntSLE,
-          lhs,
-          rhs,
+    ExpressionKind::UnaryOperation {
+      expr,
+      operator: UnaryOperator::Not,
+      ..
+    } => {
+      let value = build_body(ctx, func_ref, expr).expect("operand of `!` without a value");
+      let cond = build_condition(ctx, value);
+      Some(LLVMBuildNot(ctx.builder.builder, cond, c_str!("")))
+    }
+    ExpressionKind::UnaryOperation {
+      expr,
+      operator: UnaryOperator::Ref,
+      ..
+    } => build_place(ctx, func_ref, expr),
+    ExpressionKind::StructLiteral { name, fields } => {
+      let ty = get_type(ctx.context, &Type::Named(name.clone()));
+      let mut value = LLVMGetUndef(ty);
+      for (field, expr) in fields {
+        let idx = field_index(ctx, name, field);
+        let field_value = build_body(ctx, func_ref.as_deref_mut(), expr)?;
+        let field_value = build_conversion(
+          ctx,
+          field_value,
+          &value_type(expr),
+          LLVMStructGetTypeAtIndex(ty, idx),
+        );
+        value = LLVMBuildInsertValue(ctx.builder.builder, value, field_value, idx, c_str!(""));
+      }
+      Some(value)
+    }
+    ExpressionKind::EnumVariant {
+      name,
+      variant,
+      args,
+    } => Some(build_enum_variant(ctx, func_ref, name, variant, args)),
+    ExpressionKind::Match { value, arms } => {
+      build_match(ctx, func_ref, value, arms, &value_type(expression))
+    }
+    ExpressionKind::Cast { expr, ty } => {
+      let value = build_body(ctx, func_ref, expr)?;
+      Some(build_cast(ctx, value, &value_type(expr), ty))
+    }
+    ExpressionKind::ArrayLiteral(elements) => {
+      let ty = get_type(ctx.context, &value_type(expression));
+      let mut value = LLVMGetUndef(ty);
+      for (idx, element) in elements.iter().enumerate() {
+        let element_value = build_body(ctx, func_ref.as_deref_mut(), element)?;
+        let element_value = build_conversion(
+          ctx,
+          element_value,
+          &value_type(element),
+          LLVMGetElementType(ty),
+        );
+        value = LLVMBuildInsertValue(
+          ctx.builder.builder,
+          value,
+          element_value,
+          idx as c_uint,
           c_str!(""),
-        )),
-        _ => None,
+        );
+      }
+      Some(value)
+    }
+    ExpressionKind::FieldAccess { .. } | ExpressionKind::Index { .. } => {
+      let ptr = build_place(ctx, func_ref, expression)?;
+      Some(LLVMBuildLoad(ctx.builder.builder, ptr, c_str!("")))
+    }
+    ExpressionKind::UnaryOperation {
+      expr,
+      operator: UnaryOperator::Deref,
+      ..
+    } => {
+      let ptr = build_body(ctx, func_ref, expr)?;
+      Some(LLVMBuildLoad(ctx.builder.builder, ptr, c_str!("")))
+    }
+    ExpressionKind::UnaryOperation {
+      expr,
+      operator: operator @ (UnaryOperator::Increment | UnaryOperator::Decrement),
+      position,
+    } => {
+      let ptr = build_place(ctx, func_ref, expr)?;
+      let current = LLVMBuildLoad(ctx.builder.builder, ptr, c_str!(""));
+      let step = if *operator == UnaryOperator::Increment {
+        1
+      } else {
+        -1
+      };
+      let updated = build_step(ctx, current, &value_type(expr), step);
+      LLVMBuildStore(ctx.builder.builder, updated, ptr);
+      // `x++` gives the value from before the step, and `++x` the one after it.
+      match position {
+        OperatorPosition::Prefix => Some(updated),
+        OperatorPosition::Postfix => Some(current),
       }
     }
-    Expression::Return(expr) => {
-      let builder = Builder::new();
-      builder.position_at_end(bb);
+    ExpressionKind::UnaryOperation {
+      expr,
+      operator: UnaryOperator::Negation,
+      ..
+    } => {
+      let value = build_body(ctx, func_ref, expr)?;
+      Some(match value_type(expr) {
+        Type::Float { .. } => LLVMBuildFNeg(ctx.builder.builder, value, c_str!("")),
+        _ => LLVMBuildNeg(ctx.builder.builder, value, c_str!("")),
+      })
+    }
+    ExpressionKind::UnaryOperation {
+      expr,
+      operator: UnaryOperator::BitNOT,
+      ..
+    } => {
+      let value = build_body(ctx, func_ref, expr)?;
+      Some(LLVMBuildNot(ctx.builder.builder, value, c_str!("")))
+    }
+    ExpressionKind::BinaryOperation {
+      lhs,
+      rhs,
+      operator: operator @ (BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr),
+    } => Some(build_short_circuit(ctx, func_ref, operator, lhs, rhs)),
+    ExpressionKind::BinaryOperation {
+      operator: BinaryOperator::Assign,
+      lhs,
+      rhs,
+    } => {
+      // The value is built first, so that a declaration can use the variable it shadows.
+      let value = build_body(ctx, func_ref.as_deref_mut(), rhs)?;
+      let ptr = if let ExpressionKind::VariableDeclaration { name, .. } = &lhs.kind {
+        build_body(ctx, func_ref.as_deref_mut(), lhs);
+        get_variable(ctx, func_ref.as_deref(), name)?
+      } else {
+        build_place(ctx, func_ref, lhs)?
+      };
+      let value = build_conversion(
+        ctx,
+        value,
+        &value_type(rhs),
+        LLVMGetElementType(LLVMTypeOf(ptr)),
+      );
+      LLVMBuildStore(ctx.builder.builder, value, ptr);
+      Some(value)
+    }
+    ExpressionKind::BinaryOperation { lhs, rhs, operator }
+      if operator.base_operator().is_some() =>
+    {
+      let ptr = build_place(ctx, func_ref.as_deref_mut(), lhs)?;
+      let current = LLVMBuildLoad(ctx.builder.builder, ptr, c_str!(""));
+      let value = build_body(ctx, func_ref, rhs)?;
+      let value = build_conversion(ctx, value, &value_type(rhs), LLVMTypeOf(current));
+      let base = operator.base_operator().unwrap();
+      let result = build_operation(ctx, &base, current, value, &value_type(lhs))?;
+      LLVMBuildStore(ctx.builder.builder, result, ptr);
+      Some(result)
+    }
+    ExpressionKind::BinaryOperation { lhs, rhs, operator } => {
+      let operand_type = operand_type(&value_type(lhs), &value_type(rhs));
+      let llvm_type = get_type(ctx.context, &operand_type);
+      let lhs_value = build_body(ctx, func_ref.as_deref_mut(), lhs)
+        .expect("operand of a binary operation without a value");
+      let lhs_value = build_conversion(ctx, lhs_value, &value_type(lhs), llvm_type);
+      let rhs_value =
+        build_body(ctx, func_ref, rhs).expect("operand of a binary operation without a value");
+      let rhs_value = build_conversion(ctx, rhs_value, &value_type(rhs), llvm_type);
+      build_operation(ctx, operator, lhs_value, rhs_value, &operand_type)
+    }
+    ExpressionKind::Return(expr) => {
       if let Some(expr) = expr {
-        if let Some(value) = build_body(ctx, bb, func_ref, expr) {
-          Some(LLVMBuildRet(builder.builder, value))
-        } else {
-          None
-        }
+        let value = build_body(ctx, func_ref, expr)?;
+        let func = LLVMGetBasicBlockParent(ctx.builder.insert_block());
+        let ret = LLVMGetReturnType(LLVMGetElementType(LLVMTypeOf(func)));
+        let value = build_conversion(ctx, value, &value_type(expr), ret);
+        Some(LLVMBuildRet(ctx.builder.builder, value))
       } else {
-        Some(LLVMBuildRetVoid(builder.builder))
+        Some(LLVMBuildRetVoid(ctx.builder.builder))
       }
     }
-    Expression::Block { expressions } => {
+    ExpressionKind::Block { expressions } => {
+      // Variables declared in the block go out of scope at its end.
+      let var_count = func_ref.as_ref().map(|x| x.vars.len());
       for expr in expressions {
-        build_body(ctx, bb, func_ref, expr);
+        // Code after a `return` can't run, and LLVM doesn't allow anything after a block's terminator.
+        if is_terminated(ctx) {
+          break;
+        }
+        build_body(ctx, func_ref.as_deref_mut(), expr);
+      }
+      if let (Some(func_ref), Some(var_count)) = (func_ref, var_count) {
+        func_ref.vars.truncate(var_count);
+      }
+      None
+    }
+    ExpressionKind::If {
+      condition,
+      body,
+      else_body,
+    } => {
+      let func = LLVMGetBasicBlockParent(ctx.builder.insert_block());
+      let then_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("then"));
+      let else_bb = else_body
+        .as_ref()
+        .map(|_| LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("else")));
+      let end_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("endif"));
+      let value =
+        build_body(ctx, func_ref.as_deref_mut(), condition).expect("if condition without a value");
+      let cond = build_condition(ctx, value);
+      LLVMBuildCondBr(
+        ctx.builder.builder,
+        cond,
+        then_bb,
+        else_bb.unwrap_or(end_bb),
+      );
+
+      ctx.builder.position_at_end(then_bb);
+      build_body(ctx, func_ref.as_deref_mut(), body);
+      build_branch(ctx, end_bb);
+      if let (Some(else_bb), Some(else_body)) = (else_bb, else_body) {
+        position_at_new_end(ctx, else_bb);
+        build_body(ctx, func_ref, else_body);
+        build_branch(ctx, end_bb);
+      }
+      position_at_new_end(ctx, end_bb);
+      None
+    }
+    ExpressionKind::Break(label) | ExpressionKind::Continue(label) => {
+      let func_ref = func_ref.expect("loop jump outside of a function");
+      let (_, continue_bb, break_bb) = func_ref
+        .loops
+        .iter()
+        .rev()
+        .find(|(name, _, _)| label.is_none() || name == label)
+        .expect("loop jump outside of a loop");
+      let target = if matches!(expression.kind, ExpressionKind::Break(_)) {
+        *break_bb
+      } else {
+        *continue_bb
+      };
+      Some(LLVMBuildBr(ctx.builder.builder, target))
+    }
+    ExpressionKind::While {
+      condition,
+      body,
+      label,
+    } => {
+      let func = LLVMGetBasicBlockParent(ctx.builder.insert_block());
+      let cond_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("while.cond"));
+      let body_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("while.body"));
+      let end_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("while.end"));
+      build_branch(ctx, cond_bb);
+
+      ctx.builder.position_at_end(cond_bb);
+      let value = build_body(ctx, func_ref.as_deref_mut(), condition)
+        .expect("while condition without a value");
+      let cond = build_condition(ctx, value);
+      LLVMBuildCondBr(ctx.builder.builder, cond, body_bb, end_bb);
+
+      ctx.builder.position_at_end(body_bb);
+      build_loop_body(ctx, func_ref, body, (label.clone(), cond_bb, end_bb));
+      build_branch(ctx, cond_bb);
+      position_at_new_end(ctx, end_bb);
+      None
+    }
+    ExpressionKind::For {
+      conditions,
+      body,
+      label,
+    } => {
+      let [init, condition, step] = conditions;
+      let func = LLVMGetBasicBlockParent(ctx.builder.insert_block());
+      let cond_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("for.cond"));
+      let body_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("for.body"));
+      let step_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("for.step"));
+      let end_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("for.end"));
+      // Variables declared by the loop are only in scope inside of it.
+      let var_count = func_ref.as_ref().map(|x| x.vars.len());
+      build_body(ctx, func_ref.as_deref_mut(), init);
+      build_branch(ctx, cond_bb);
+
+      ctx.builder.position_at_end(cond_bb);
+      let value =
+        build_body(ctx, func_ref.as_deref_mut(), condition).expect("for condition without a value");
+      let cond = build_condition(ctx, value);
+      LLVMBuildCondBr(ctx.builder.builder, cond, body_bb, end_bb);
+
+      ctx.builder.position_at_end(body_bb);
+      build_loop_body(
+        ctx,
+        func_ref.as_deref_mut(),
+        body,
+        (label.clone(), step_bb, end_bb),
+      );
+      build_branch(ctx, step_bb);
+
+      position_at_new_end(ctx, step_bb);
+      build_body(ctx, func_ref.as_deref_mut(), step);
+      build_branch(ctx, cond_bb);
+      position_at_new_end(ctx, end_bb);
+      if let (Some(func_ref), Some(var_count)) = (func_ref, var_count) {
+        func_ref.vars.truncate(var_count);
       }
       None
     }
@@ -276,62 +1005,929 @@ unsafe fn build_body(
   }
 }
 
-unsafe fn build_func_call(
+/// Builds the body of a loop, with `break` and `continue` jumping to the blocks of `target`.
+unsafe fn build_loop_body(
+  ctx: &mut CompileContext,
+  func_ref: Option<&mut FunctionRef>,
+  body: &Expression,
+  target: (Option<String>, LLVMBasicBlockRef, LLVMBasicBlockRef),
+) {
+  match func_ref {
+    Some(func_ref) => {
+      func_ref.loops.push(target);
+      build_body(ctx, Some(&mut *func_ref), body);
+      func_ref.loops.pop();
+    }
+    None => {
+      build_body(ctx, None, body);
+    }
+  }
+}
+
+/// Converts a value of type `from` to the LLVM type `to`, for the conversions the type checker allows implicitly.
+unsafe fn build_conversion(
+  ctx: &mut CompileContext,
+  value: LLVMValueRef,
+  from: &Type,
+  to: LLVMTypeRef,
+) -> LLVMValueRef {
+  let builder = ctx.builder.builder;
+  match (LLVMGetTypeKind(LLVMTypeOf(value)), LLVMGetTypeKind(to)) {
+    (LLVMTypeKind::LLVMIntegerTypeKind, LLVMTypeKind::LLVMFloatTypeKind)
+    | (LLVMTypeKind::LLVMIntegerTypeKind, LLVMTypeKind::LLVMDoubleTypeKind) => {
+      if matches!(from, Type::Int { signed: false, .. }) {
+        LLVMBuildUIToFP(builder, value, to, c_str!(""))
+      } else {
+        LLVMBuildSIToFP(builder, value, to, c_str!(""))
+      }
+    }
+    (LLVMTypeKind::LLVMIntegerTypeKind, LLVMTypeKind::LLVMIntegerTypeKind) => {
+      let signed = matches!(from, Type::Int { signed: true, .. } | Type::Char);
+      LLVMBuildIntCast2(builder, value, to, llvm_bool(signed), c_str!(""))
+    }
+    (LLVMTypeKind::LLVMFloatTypeKind, LLVMTypeKind::LLVMDoubleTypeKind) => {
+      LLVMBuildFPExt(builder, value, to, c_str!(""))
+    }
+    _ => value,
+  }
+}
+
+/// Builds an `as` cast, which unlike implicit conversions can also truncate values and reinterpret pointers.
+unsafe fn build_cast(
+  ctx: &mut CompileContext,
+  value: LLVMValueRef,
+  from: &Type,
+  to: &Type,
+) -> LLVMValueRef {
+  let builder = ctx.builder.builder;
+  // C-like enums are cast through their tag.
+  if let Type::Named(name) = from {
+    if ctx.enums.contains_key(name) {
+      let tag = LLVMBuildExtractValue(builder, value, 0, c_str!(""));
+      let tag_type = Type::Int {
+        bits: 32,
+        signed: true,
+      };
+      return build_cast(ctx, tag, &tag_type, to);
+    }
+  }
+  let to_type = get_type(ctx.context, to);
+  let signed = matches!(to, Type::Int { signed: true, .. } | Type::Char);
+  match (LLVMGetTypeKind(LLVMTypeOf(value)), LLVMGetTypeKind(to_type)) {
+    // Floats saturate to the range of the integer, and NaN becomes 0, so no cast is undefined.
+    (
+      from_kind @ (LLVMTypeKind::LLVMFloatTypeKind | LLVMTypeKind::LLVMDoubleTypeKind),
+      LLVMTypeKind::LLVMIntegerTypeKind,
+    ) => {
+      // LLVM doesn't fold the intrinsic, but constants have to stay constant, e.g. to initialize globals.
+      if !LLVMIsAConstantFP(value).is_null() {
+        return const_float_to_int(value, to_type, signed);
+      }
+      let name = format!(
+        "llvm.fpto{}i.sat.i{}.{}",
+        if signed { "s" } else { "u" },
+        LLVMGetIntTypeWidth(to_type),
+        if from_kind == LLVMTypeKind::LLVMFloatTypeKind {
+          "f32"
+        } else {
+          "f64"
+        }
+      );
+      let mut arg_types = [LLVMTypeOf(value)];
+      let func_type = LLVMFunctionType(to_type, arg_types.as_mut_ptr(), 1, LLVM_FALSE);
+      let func = get_intrinsic(ctx, &name, func_type);
+      let mut args = [value];
+      LLVMBuildCall(ctx.builder.builder, func, args.as_mut_ptr(), 1, c_str!(""))
+    }
+    (LLVMTypeKind::LLVMDoubleTypeKind, LLVMTypeKind::LLVMFloatTypeKind) => {
+      LLVMBuildFPTrunc(builder, value, to_type, c_str!(""))
+    }
+    (LLVMTypeKind::LLVMPointerTypeKind, LLVMTypeKind::LLVMPointerTypeKind) => {
+      LLVMBuildBitCast(builder, value, to_type, c_str!(""))
+    }
+    (LLVMTypeKind::LLVMPointerTypeKind, LLVMTypeKind::LLVMIntegerTypeKind) => {
+      LLVMBuildPtrToInt(builder, value, to_type, c_str!(""))
+    }
+    (LLVMTypeKind::LLVMIntegerTypeKind, LLVMTypeKind::LLVMPointerTypeKind) => {
+      LLVMBuildIntToPtr(builder, value, to_type, c_str!(""))
+    }
+    // Integers are truncated or extended by their own signedness, and the rest converts implicitly.
+    _ => build_conversion(ctx, value, from, to_type),
+  }
+}
+
+/// Converts a constant float to an integer the way `llvm.fpto*i.sat` does, saturating to the integer's range.
+unsafe fn const_float_to_int(value: LLVMValueRef, to: LLVMTypeRef, signed: bool) -> LLVMValueRef {
+  let mut loses_info = 0;
+  let float = LLVMConstRealGetDouble(value, &mut loses_info);
+  let unused_bits = 128 - LLVMGetIntTypeWidth(to);
+  // Rust's casts saturate to the range of the type too, and turn NaN into 0.
+  let int = if signed {
+    let max = i128::MAX >> unused_bits;
+    (float as i128).clamp(-max - 1, max) as u128
+  } else {
+    (float as u128).min(u128::MAX >> unused_bits)
+  };
+  let words = [int as u64, (int >> 64) as u64];
+  LLVMConstIntOfArbitraryPrecision(to, words.len() as c_uint, words.as_ptr())
+}
+
+/// Whether the current block already ends in a terminator, e.g. a `return`.
+unsafe fn is_terminated(ctx: &CompileContext) -> bool {
+  !LLVMGetBasicBlockTerminator(ctx.builder.insert_block()).is_null()
+}
+
+/// Branches to `bb`, unless the current block already ended.
+unsafe fn build_branch(ctx: &mut CompileContext, bb: LLVMBasicBlockRef) {
+  if !is_terminated(ctx) {
+    LLVMBuildBr(ctx.builder.builder, bb);
+  }
+}
+
+/// Continues building in `bb`, moving it after the blocks that were added while building the code before it.
+/// This keeps the blocks in the order their code appears in.
+unsafe fn position_at_new_end(ctx: &mut CompileContext, bb: LLVMBasicBlockRef) {
+  LLVMMoveBasicBlockAfter(bb, ctx.builder.insert_block());
+  ctx.builder.position_at_end(bb);
+}
+
+/// Builds a binary operation on two values of `operand_type`.
+unsafe fn build_operation(
+  ctx: &mut CompileContext,
+  operator: &BinaryOperator,
+  lhs: LLVMValueRef,
+  rhs: LLVMValueRef,
+  operand_type: &Type,
+) -> Option<LLVMValueRef> {
+  let builder = &ctx.builder;
+  // Pointers are compared as unsigned addresses.
+  let signed = matches!(operand_type, Type::Int { signed: true, .. } | Type::Char);
+  if let Type::Float { .. } = operand_type {
+    return build_float_operation(ctx, operator, lhs, rhs);
+  }
+  match operator {
+    BinaryOperator::Add => Some(LLVMBuildAdd(builder.builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::Subtract => Some(LLVMBuildSub(builder.builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::Multiply => Some(LLVMBuildMul(builder.builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::Divide if signed => Some(LLVMBuildSDiv(builder.builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::Divide => Some(LLVMBuildUDiv(builder.builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::Modulo if signed => Some(LLVMBuildSRem(builder.builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::Modulo => Some(LLVMBuildURem(builder.builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::LeftShift => Some(LLVMBuildShl(builder.builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::RightShift if signed => {
+      Some(LLVMBuildAShr(builder.builder, lhs, rhs, c_str!("")))
+    }
+    BinaryOperator::RightShift => Some(LLVMBuildLShr(builder.builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::BitAND => Some(LLVMBuildAnd(builder.builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::BitOR => Some(LLVMBuildOr(builder.builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::BitXOR => Some(LLVMBuildXor(builder.builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::Eq
+    | BinaryOperator::Ne
+    | BinaryOperator::Gt
+    | BinaryOperator::Lt
+    | BinaryOperator::Ge
+    | BinaryOperator::Le => {
+      let predicate = int_predicate(operator, signed)?;
+      Some(LLVMBuildICmp(
+        builder.builder,
+        predicate,
+        lhs,
+        rhs,
+        c_str!(""),
+      ))
+    }
+    _ => None,
+  }
+}
+
+/// Gets the predicate of an integer comparison.
+fn int_predicate(operator: &BinaryOperator, signed: bool) -> Option<LLVMIntPredicate> {
+  Some(match (operator, signed) {
+    (BinaryOperator::Eq, _) => LLVMIntPredicate::LLVMIntEQ,
+    (BinaryOperator::Ne, _) => LLVMIntPredicate::LLVMIntNE,
+    (BinaryOperator::Gt, true) => LLVMIntPredicate::LLVMIntSGT,
+    (BinaryOperator::Gt, false) => LLVMIntPredicate::LLVMIntUGT,
+    (BinaryOperator::Lt, true) => LLVMIntPredicate::LLVMIntSLT,
+    (BinaryOperator::Lt, false) => LLVMIntPredicate::LLVMIntULT,
+    (BinaryOperator::Ge, true) => LLVMIntPredicate::LLVMIntSGE,
+    (BinaryOperator::Ge, false) => LLVMIntPredicate::LLVMIntUGE,
+    (BinaryOperator::Le, true) => LLVMIntPredicate::LLVMIntSLE,
+    (BinaryOperator::Le, false) => LLVMIntPredicate::LLVMIntULE,
+    _ => return None,
+  })
+}
+
+/// Builds a binary operation on two floats of the same type.
+unsafe fn build_float_operation(
+  ctx: &mut CompileContext,
+  operator: &BinaryOperator,
+  lhs: LLVMValueRef,
+  rhs: LLVMValueRef,
+) -> Option<LLVMValueRef> {
+  let builder = ctx.builder.builder;
+  // Comparisons are false when either side is NaN, except for `!=`, which is true like in C.
+  let predicate = match operator {
+    BinaryOperator::Add => return Some(LLVMBuildFAdd(builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::Subtract => return Some(LLVMBuildFSub(builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::Multiply => return Some(LLVMBuildFMul(builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::Divide => return Some(LLVMBuildFDiv(builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::Modulo => return Some(LLVMBuildFRem(builder, lhs, rhs, c_str!(""))),
+    BinaryOperator::Eq => LLVMRealPredicate::LLVMRealOEQ,
+    BinaryOperator::Ne => LLVMRealPredicate::LLVMRealUNE,
+    BinaryOperator::Gt => LLVMRealPredicate::LLVMRealOGT,
+    BinaryOperator::Lt => LLVMRealPredicate::LLVMRealOLT,
+    BinaryOperator::Ge => LLVMRealPredicate::LLVMRealOGE,
+    BinaryOperator::Le => LLVMRealPredicate::LLVMRealOLE,
+    _ => return None,
+  };
+  Some(LLVMBuildFCmp(builder, predicate, lhs, rhs, c_str!("")))
+}
+
+/// Allocates stack space for a variable at the start of the function, where LLVM can promote it to a register.
+unsafe fn build_alloca(
+  ctx: &mut CompileContext,
+  func: LLVMValueRef,
+  ty: LLVMTypeRef,
+  name: &str,
+) -> LLVMValueRef {
+  let builder = Builder::new(ctx.context);
+  let entry = LLVMGetEntryBasicBlock(func);
+  let first = LLVMGetFirstInstruction(entry);
+  if first.is_null() {
+    builder.position_at_end(entry);
+  } else {
+    builder.position_before(first);
+  }
+  LLVMBuildAlloca(builder.builder, ty, ctx.module.new_string_ptr(name))
+}
+
+/// Gets a pointer to a variable, looking through the function's locals before the module's globals.
+unsafe fn get_variable(
   ctx: &mut CompileContext,
   func_ref: Option<&FunctionRef>,
-  bb: LLVMBasicBlockRef,
-  fn_name: &str,
-  args: &Vec<Expression>,
   name: &str,
+) -> Option<LLVMValueRef> {
+  let local = func_ref.and_then(|x| x.vars.iter().rev().find(|(x, _, _)| x == name));
+  if let Some((_, _, ptr)) = local {
+    return Some(*ptr);
+  }
+  let global = LLVMGetNamedGlobal(ctx.module.module, ctx.module.new_mut_string_ptr(name));
+  if global.is_null() {
+    None
+  } else {
+    Some(global)
+  }
+}
+
+/// Gets a pointer to what an expression refers to, so that it can be assigned to.
+unsafe fn build_place(
+  ctx: &mut CompileContext,
+  mut func_ref: Option<&mut FunctionRef>,
+  expression: &Expression,
+) -> Option<LLVMValueRef> {
+  match &expression.kind {
+    ExpressionKind::VariableRef(name) => get_variable(ctx, func_ref.as_deref(), name),
+    ExpressionKind::UnaryOperation {
+      operator: UnaryOperator::Deref,
+      expr,
+      ..
+    } => build_body(ctx, func_ref, expr),
+    ExpressionKind::FieldAccess { expr, field } => {
+      let (ptr, name) = match value_type(expr) {
+        // Fields are reached through pointers to structs as if they were dereferenced first.
+        Type::Pointer(ty) => (build_body(ctx, func_ref, expr)?, struct_name(&ty)),
+        ty => (build_stored(ctx, func_ref, expr)?, struct_name(&ty)),
+      };
+      let idx = field_index(ctx, &name, field);
+      Some(LLVMBuildStructGEP(
+        ctx.builder.builder,
+        ptr,
+        idx,
+        ctx.module.new_string_ptr(field),
+      ))
+    }
+    ExpressionKind::Index { expr, index } => {
+      let ty = value_type(expr);
+      let base = match ty {
+        Type::Array(_, Some(_)) => build_stored(ctx, func_ref.as_deref_mut(), expr)?,
+        // Pointers, and the strings and unsized arrays that are pointers too, point at the elements themselves.
+        _ => build_body(ctx, func_ref.as_deref_mut(), expr)?,
+      };
+      let index_value = build_body(ctx, func_ref, index)?;
+      let index_value = build_conversion(
+        ctx,
+        index_value,
+        &value_type(index),
+        LLVMInt64TypeInContext(ctx.context),
+      );
+      let mut indices = match ty {
+        Type::Array(_, Some(len)) => {
+          if ctx.bounds_checks {
+            build_bounds_check(ctx, index_value, len);
+          }
+          vec![
+            LLVMConstInt(LLVMInt64TypeInContext(ctx.context), 0, LLVM_FALSE),
+            index_value,
+          ]
+        }
+        _ => vec![index_value],
+      };
+      Some(LLVMBuildInBoundsGEP(
+        ctx.builder.builder,
+        base,
+        indices.as_mut_ptr(),
+        indices.len() as c_uint,
+        c_str!(""),
+      ))
+    }
+    _ => None,
+  }
+}
+
+/// Builds a value of an enum, storing the values its variant holds in its payload.
+unsafe fn build_enum_variant(
+  ctx: &mut CompileContext,
+  mut func_ref: Option<&mut FunctionRef>,
+  name: &str,
+  variant: &str,
+  args: &[Expression],
 ) -> LLVMValueRef {
-  let builder = Builder::new();
-  builder.position_at_end(bb);
+  let ty = get_type(ctx.context, &Type::Named(name.to_string()));
+  let variants = &ctx.enums[name];
+  let idx = variants
+    .iter()
+    .position(|x| x.name == variant)
+    .expect("variant missing from its enum");
+  let discriminant = Variant::discriminants(variants)[idx];
+  let tag = LLVMConstInt(
+    LLVMInt32TypeInContext(ctx.context),
+    discriminant as c_ulonglong,
+    LLVM_TRUE,
+  );
+  if args.is_empty() {
+    return LLVMBuildInsertValue(ctx.builder.builder, LLVMGetUndef(ty), tag, 0, c_str!(""));
+  }
+  let payload_type = variant_type(ctx, &variants[idx]);
+  let func = LLVMGetBasicBlockParent(ctx.builder.insert_block());
+  let ptr = build_alloca(ctx, func, ty, "");
+  let builder = ctx.builder.builder;
+  LLVMBuildStore(
+    builder,
+    tag,
+    LLVMBuildStructGEP(builder, ptr, 0, c_str!("")),
+  );
+  let payload = LLVMBuildBitCast(
+    builder,
+    LLVMBuildStructGEP(builder, ptr, 1, c_str!("")),
+    LLVMPointerType(payload_type, 0),
+    c_str!(""),
+  );
+  for (idx, arg) in args.iter().enumerate() {
+    let value =
+      build_body(ctx, func_ref.as_deref_mut(), arg).expect("variant argument without a value");
+    let value = build_conversion(
+      ctx,
+      value,
+      &value_type(arg),
+      LLVMStructGetTypeAtIndex(payload_type, idx as c_uint),
+    );
+    let field = LLVMBuildStructGEP(ctx.builder.builder, payload, idx as c_uint, c_str!(""));
+    LLVMBuildStore(ctx.builder.builder, value, field);
+  }
+  LLVMBuildLoad(ctx.builder.builder, ptr, c_str!(""))
+}
 
-  let function = LLVMGetNamedFunction(ctx.module.module, ctx.module.new_string_ptr(fn_name));
-  let mut args_value: Vec<LLVMValueRef> = args
+/// Builds a `match` as a `switch` over the value, or over the tag of an enum, with a block for each arm.
+/// The arms' values are merged into the value of the match, unless its type is `void`.
+unsafe fn build_match(
+  ctx: &mut CompileContext,
+  mut func_ref: Option<&mut FunctionRef>,
+  value: &Expression,
+  arms: &[MatchArm],
+  ty: &Type,
+) -> Option<LLVMValueRef> {
+  let variants = match value_type(value) {
+    Type::Named(name) => ctx.enums.get(&name).cloned(),
+    _ => None,
+  };
+  // Enums are matched on their tag, and kept in memory so the values of their variants can be read.
+  let (switch_value, stored) = match &variants {
+    Some(_) => {
+      let ptr = build_stored(ctx, func_ref.as_deref_mut(), value)?;
+      let tag = LLVMBuildStructGEP(ctx.builder.builder, ptr, 0, c_str!(""));
+      (LLVMBuildLoad(ctx.builder.builder, tag, c_str!("")), ptr)
+    }
+    None => (
+      build_body(ctx, func_ref.as_deref_mut(), value)?,
+      ptr::null_mut(),
+    ),
+  };
+
+  // Arms after a `_` are never run, and neither are ones whose pattern an earlier arm already matches.
+  let mut cases: Vec<(LLVMValueRef, &MatchArm)> = vec![];
+  let mut default = None;
+  for arm in arms {
+    let case = match &arm.pattern {
+      Pattern::Wildcard => {
+        default = Some(arm);
+        break;
+      }
+      Pattern::Literal(literal) => {
+        let case = build_body(ctx, func_ref.as_deref_mut(), literal)?;
+        build_conversion(ctx, case, &value_type(literal), LLVMTypeOf(switch_value))
+      }
+      Pattern::Variant { variant, .. } => {
+        let variants = variants.as_ref()?;
+        let idx = variants.iter().position(|x| x.name == *variant)?;
+        let discriminant = Variant::discriminants(variants)[idx];
+        LLVMConstInt(
+          LLVMInt32TypeInContext(ctx.context),
+          discriminant as c_ulonglong,
+          LLVM_TRUE,
+        )
+      }
+    };
+    // Constants are unique, so equal cases are the same value.
+    if !cases.iter().any(|(x, _)| *x == case) {
+      cases.push((case, arm));
+    }
+  }
+
+  let func = LLVMGetBasicBlockParent(ctx.builder.insert_block());
+  let default_bb = LLVMAppendBasicBlockInContext(
+    ctx.context,
+    func,
+    if default.is_some() {
+      c_str!("match.arm")
+    } else {
+      c_str!("match.unreachable")
+    },
+  );
+  let end_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("match.end"));
+  let switch = LLVMBuildSwitch(
+    ctx.builder.builder,
+    switch_value,
+    default_bb,
+    cases.len() as c_uint,
+  );
+  let mut blocks: Vec<(&MatchArm, LLVMBasicBlockRef)> = vec![];
+  for (case, arm) in cases {
+    let bb = LLVMInsertBasicBlockInContext(ctx.context, default_bb, c_str!("match.arm"));
+    LLVMAddCase(switch, case, bb);
+    blocks.push((arm, bb));
+  }
+  match default {
+    Some(arm) => blocks.push((arm, default_bb)),
+    // The type checker makes sure every value has an arm without a `_`.
+    None => {
+      LLVMMoveBasicBlockBefore(default_bb, end_bb);
+      position_at_new_end(ctx, default_bb);
+      LLVMBuildUnreachable(ctx.builder.builder);
+    }
+  }
+
+  let result_type = if *ty == Type::Void {
+    None
+  } else {
+    Some(get_type(ctx.context, ty))
+  };
+  let mut incoming: Vec<(LLVMValueRef, LLVMBasicBlockRef)> = vec![];
+  for (arm, bb) in blocks {
+    position_at_new_end(ctx, bb);
+    // The names bound by the arm are only in scope inside of it.
+    let var_count = func_ref.as_ref().map(|x| x.vars.len());
+    // Variants that don't hold values have no bindings, and C-like enums have no payload to read.
+    if let (
+      Pattern::Variant {
+        variant, bindings, ..
+      },
+      Some(variants),
+      Some(func_ref),
+    ) = (&arm.pattern, &variants, func_ref.as_deref_mut())
+    {
+      let variant = variants.iter().find(|x| x.name == *variant)?;
+      if !bindings.is_empty() {
+        let payload = LLVMBuildBitCast(
+          ctx.builder.builder,
+          LLVMBuildStructGEP(ctx.builder.builder, stored, 1, c_str!("")),
+          LLVMPointerType(variant_type(ctx, variant), 0),
+          c_str!(""),
+        );
+        for (idx, (binding, field_type)) in bindings.iter().zip(&variant.fields).enumerate() {
+          if binding == "_" {
+            continue;
+          }
+          let field = LLVMBuildStructGEP(ctx.builder.builder, payload, idx as c_uint, c_str!(""));
+          let field_value = LLVMBuildLoad(ctx.builder.builder, field, c_str!(""));
+          let ptr = build_alloca(ctx, func, get_type(ctx.context, field_type), binding);
+          LLVMBuildStore(ctx.builder.builder, field_value, ptr);
+          func_ref.vars.push((binding.clone(), false, ptr));
+        }
+      }
+    }
+    let arm_value = build_body(ctx, func_ref.as_deref_mut(), &arm.body);
+    if let (Some(result_type), Some(arm_value)) = (result_type, arm_value) {
+      if !is_terminated(ctx) {
+        let arm_value = build_conversion(ctx, arm_value, &value_type(&arm.body), result_type);
+        incoming.push((arm_value, ctx.builder.insert_block()));
+      }
+    }
+    build_branch(ctx, end_bb);
+    if let (Some(func_ref), Some(var_count)) = (func_ref.as_deref_mut(), var_count) {
+      func_ref.vars.truncate(var_count);
+    }
+  }
+  position_at_new_end(ctx, end_bb);
+
+  let result_type = result_type.filter(|_| !incoming.is_empty())?;
+  let phi = LLVMBuildPhi(ctx.builder.builder, result_type, c_str!(""));
+  let (mut values, mut blocks): (Vec<LLVMValueRef>, Vec<LLVMBasicBlockRef>) =
+    incoming.into_iter().unzip();
+  LLVMAddIncoming(
+    phi,
+    values.as_mut_ptr(),
+    blocks.as_mut_ptr(),
+    values.len() as c_uint,
+  );
+  Some(phi)
+}
+
+/// Gets a pointer to a value, storing it first if it isn't stored anywhere, e.g. when it's returned by a call.
+unsafe fn build_stored(
+  ctx: &mut CompileContext,
+  mut func_ref: Option<&mut FunctionRef>,
+  expression: &Expression,
+) -> Option<LLVMValueRef> {
+  if let Some(ptr) = build_place(ctx, func_ref.as_deref_mut(), expression) {
+    return Some(ptr);
+  }
+  let value = build_body(ctx, func_ref, expression)?;
+  let func = LLVMGetBasicBlockParent(ctx.builder.insert_block());
+  let ptr = build_alloca(ctx, func, LLVMTypeOf(value), "");
+  LLVMBuildStore(ctx.builder.builder, value, ptr);
+  Some(ptr)
+}
+
+/// Traps unless `index` is less than `len`. The index is compared unsigned, so negative ones trap too.
+unsafe fn build_bounds_check(ctx: &mut CompileContext, index: LLVMValueRef, len: u32) {
+  let func = LLVMGetBasicBlockParent(ctx.builder.insert_block());
+  let fail_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("bounds.fail"));
+  let ok_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("bounds.ok"));
+  let in_bounds = LLVMBuildICmp(
+    ctx.builder.builder,
+    LLVMIntPredicate::LLVMIntULT,
+    index,
+    LLVMConstInt(
+      LLVMInt64TypeInContext(ctx.context),
+      len as c_ulonglong,
+      LLVM_FALSE,
+    ),
+    c_str!(""),
+  );
+  LLVMBuildCondBr(ctx.builder.builder, in_bounds, ok_bb, fail_bb);
+
+  position_at_new_end(ctx, fail_bb);
+  let trap_type = LLVMFunctionType(
+    LLVMVoidTypeInContext(ctx.context),
+    ptr::null_mut(),
+    0,
+    LLVM_FALSE,
+  );
+  let trap = get_intrinsic(ctx, "llvm.trap", trap_type);
+  LLVMBuildCall(ctx.builder.builder, trap, ptr::null_mut(), 0, c_str!(""));
+  LLVMBuildUnreachable(ctx.builder.builder);
+  position_at_new_end(ctx, ok_bb);
+}
+
+/// Gets an LLVM intrinsic, declaring it the first time it is used.
+unsafe fn get_intrinsic(ctx: &mut CompileContext, name: &str, ty: LLVMTypeRef) -> LLVMValueRef {
+  let name = ctx.module.new_string_ptr(name);
+  let intrinsic = LLVMGetNamedFunction(ctx.module.module, name);
+  if intrinsic.is_null() {
+    LLVMAddFunction(ctx.module.module, name, ty)
+  } else {
+    intrinsic
+  }
+}
+
+/// Gets the name of a struct type.
+fn struct_name(ty: &Type) -> String {
+  match ty {
+    Type::Named(name) | Type::Struct { name, .. } => name.clone(),
+    _ => String::new(),
+  }
+}
+
+/// Gets the position of a field in the layout of a struct.
+fn field_index(ctx: &CompileContext, name: &str, field: &str) -> c_uint {
+  ctx.structs[name]
     .iter()
-    .map(|value| build_body(ctx, bb, func_ref, value))
-    .map(|x| x.unwrap())
-    .collect();
-  let resp = LLVMBuildCall(
-    builder.builder,
+    .position(|(x, _)| x == field)
+    .expect("field missing from its struct") as c_uint
+}
+
+/// Adds `step` to an integer, or moves a pointer by `step` elements.
+unsafe fn build_step(
+  ctx: &mut CompileContext,
+  value: LLVMValueRef,
+  ty: &Type,
+  step: i64,
+) -> LLVMValueRef {
+  let builder = ctx.builder.builder;
+  if let Type::Pointer(_) | Type::String | Type::Array(_, None) = ty {
+    let mut offset = [LLVMConstInt(
+      LLVMInt64TypeInContext(ctx.context),
+      step as c_ulonglong,
+      LLVM_TRUE,
+    )];
+    LLVMBuildInBoundsGEP(builder, value, offset.as_mut_ptr(), 1, c_str!(""))
+  } else {
+    let step = LLVMConstInt(LLVMTypeOf(value), step as c_ulonglong, LLVM_TRUE);
+    LLVMBuildAdd(builder, value, step, c_str!(""))
+  }
+}
+
+/// Builds `&&` and `||`, only evaluating the right-hand side when the left one doesn't decide the result.
+unsafe fn build_short_circuit(
+  ctx: &mut CompileContext,
+  mut func_ref: Option<&mut FunctionRef>,
+  operator: &BinaryOperator,
+  lhs: &Expression,
+  rhs: &Expression,
+) -> LLVMValueRef {
+  let lhs = build_body(ctx, func_ref.as_deref_mut(), lhs)
+    .expect("operand of a logical operation without a value");
+  let lhs = build_condition(ctx, lhs);
+  let lhs_bb = ctx.builder.insert_block();
+  let func = LLVMGetBasicBlockParent(lhs_bb);
+  let rhs_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("rhs"));
+  let merge_bb = LLVMAppendBasicBlockInContext(ctx.context, func, c_str!("merge"));
+  if *operator == BinaryOperator::LogicalAnd {
+    LLVMBuildCondBr(ctx.builder.builder, lhs, rhs_bb, merge_bb);
+  } else {
+    LLVMBuildCondBr(ctx.builder.builder, lhs, merge_bb, rhs_bb);
+  }
+
+  ctx.builder.position_at_end(rhs_bb);
+  let rhs = build_body(ctx, func_ref, rhs).expect("operand of a logical operation without a value");
+  let rhs = build_condition(ctx, rhs);
+  // The right-hand side may have added blocks of its own, e.g. `a && (b || c)`.
+  let rhs_bb = ctx.builder.insert_block();
+  LLVMBuildBr(ctx.builder.builder, merge_bb);
+
+  ctx.builder.position_at_end(merge_bb);
+  let phi = LLVMBuildPhi(
+    ctx.builder.builder,
+    LLVMInt1TypeInContext(ctx.context),
+    c_str!(""),
+  );
+  let short_circuited = llvm_bool(*operator == BinaryOperator::LogicalOr);
+  let mut values = [
+    LLVMConstInt(
+      LLVMInt1TypeInContext(ctx.context),
+      short_circuited as c_ulonglong,
+      LLVM_FALSE,
+    ),
+    rhs,
+  ];
+  let mut blocks = [lhs_bb, rhs_bb];
+  LLVMAddIncoming(phi, values.as_mut_ptr(), blocks.as_mut_ptr(), 2);
+  phi
+}
+
+/// Converts a value to an `i1` that is true when the value is non-zero.
+unsafe fn build_condition(ctx: &mut CompileContext, value: LLVMValueRef) -> LLVMValueRef {
+  let ty = LLVMTypeOf(value);
+  if ty == LLVMInt1TypeInContext(ctx.context) {
+    return value;
+  }
+  LLVMBuildICmp(
+    ctx.builder.builder,
+    LLVMIntPredicate::LLVMIntNE,
+    value,
+    LLVMConstNull(ty),
+    c_str!(""),
+  )
+}
+
+unsafe fn build_func_call(
+  ctx: &mut CompileContext,
+  mut func_ref: Option<&mut FunctionRef>,
+  fn_name: &str,
+  args: &[Expression],
+  name: &str,
+) -> LLVMValueRef {
+  let function = match get_variable(ctx, func_ref.as_deref(), fn_name) {
+    // Variables of a function type, or pointers to one, are loaded until there's a pointer to the function to call.
+    Some(mut function) => {
+      while LLVMGetTypeKind(LLVMGetElementType(LLVMTypeOf(function)))
+        != LLVMTypeKind::LLVMFunctionTypeKind
+      {
+        function = LLVMBuildLoad(ctx.builder.builder, function, c_str!(""));
+      }
+      function
+    }
+    None => {
+      if let Some(signature) = ctx.c_functions.get(fn_name).cloned() {
+        return build_c_call(ctx, func_ref, fn_name, &signature, args);
+      }
+      LLVMGetNamedFunction(ctx.module.module, ctx.module.new_string_ptr(fn_name))
+    }
+  };
+  let fn_type = LLVMGetElementType(LLVMTypeOf(function));
+  let mut param_types = vec![ptr::null_mut(); LLVMCountParamTypes(fn_type) as usize];
+  LLVMGetParamTypes(fn_type, param_types.as_mut_ptr());
+  let mut args_value: Vec<LLVMValueRef> = vec![];
+  for (idx, arg) in args.iter().enumerate() {
+    let value =
+      build_body(ctx, func_ref.as_deref_mut(), arg).expect("function argument without a value");
+    args_value.push(match param_types.get(idx) {
+      Some(param_type) => build_conversion(ctx, value, &value_type(arg), *param_type),
+      None => value,
+    });
+  }
+  LLVMBuildCall(
+    ctx.builder.builder,
     function,
     args_value.as_mut_ptr(),
     args_value.len() as c_uint,
     ctx.module.new_string_ptr(name),
+  )
+}
+
+/// Calls a `declare`d C function, passing its arguments and getting its return value the way its signature says to.
+unsafe fn build_c_call(
+  ctx: &mut CompileContext,
+  mut func_ref: Option<&mut FunctionRef>,
+  fn_name: &str,
+  signature: &Signature,
+  args: &[Expression],
+) -> LLVMValueRef {
+  let function = LLVMGetNamedFunction(ctx.module.module, ctx.module.new_string_ptr(fn_name));
+  let func = func_ref
+    .as_ref()
+    .expect("function call outside of a function")
+    .fn_ref;
+  let (ret_type, ret_mode) = signature.ret;
+  let mut values = vec![];
+  let ret_slot = match ret_mode {
+    PassMode::Indirect { .. } => {
+      let slot = build_alloca(ctx, func, ret_type, "sret");
+      values.push(slot);
+      Some(slot)
+    }
+    _ => None,
+  };
+  for (idx, arg) in args.iter().enumerate() {
+    let value =
+      build_body(ctx, func_ref.as_deref_mut(), arg).expect("function argument without a value");
+    values.push(match signature.args.get(idx) {
+      Some((ty, mode)) => {
+        let value = build_conversion(ctx, value, &value_type(arg), *ty);
+        match mode {
+          PassMode::Direct => value,
+          PassMode::Coerce(coerced) => build_reinterpret(ctx, func, value, *coerced),
+          // The C function gets its own copy, which it's free to change.
+          PassMode::Indirect { .. } => {
+            let copy = build_alloca(ctx, func, *ty, "byval");
+            LLVMBuildStore(ctx.builder.builder, value, copy);
+            copy
+          }
+        }
+      }
+      None => value,
+    });
+  }
+  let call = LLVMBuildCall(
+    ctx.builder.builder,
+    function,
+    values.as_mut_ptr(),
+    values.len() as c_uint,
+    c_str!(""),
+  );
+  for (index, attribute) in signature.attributes(ctx.context) {
+    LLVMAddCallSiteAttribute(call, index, attribute);
+  }
+  match ret_mode {
+    PassMode::Direct => call,
+    PassMode::Coerce(_) => build_reinterpret(ctx, func, call, ret_type),
+    PassMode::Indirect { .. } => LLVMBuildLoad(ctx.builder.builder, ret_slot.unwrap(), c_str!("")),
+  }
+}
+
+/// Reinterprets the bytes of a value as another type, through stack space big enough for either.
+unsafe fn build_reinterpret(
+  ctx: &mut CompileContext,
+  func: LLVMValueRef,
+  value: LLVMValueRef,
+  to: LLVMTypeRef,
+) -> LLVMValueRef {
+  let from = LLVMTypeOf(value);
+  let larger = if LLVMABISizeOfType(ctx.target_data, to) > LLVMABISizeOfType(ctx.target_data, from)
+  {
+    to
+  } else {
+    from
+  };
+  let slot = build_alloca(ctx, func, larger, "coerce");
+  let align =
+    LLVMABIAlignmentOfType(ctx.target_data, from).max(LLVMABIAlignmentOfType(ctx.target_data, to));
+  LLVMSetAlignment(slot, align);
+  let builder = ctx.builder.builder;
+  let from_ptr = LLVMBuildBitCast(builder, slot, LLVMPointerType(from, 0), c_str!(""));
+  LLVMBuildStore(builder, value, from_ptr);
+  let to_ptr = LLVMBuildBitCast(builder, slot, LLVMPointerType(to, 0), c_str!(""));
+  LLVMBuildLoad(builder, to_ptr, c_str!(""))
+}
+
+/// Emits a string literal as a private, null-terminated global, returning a `char*` to its first char.
+unsafe fn build_string(ctx: &mut CompileContext, value: &str) -> LLVMValueRef {
+  let init = LLVMConstStringInContext(
+    ctx.context,
+    value.as_ptr() as *const c_char,
+    value.len() as c_uint,
+    LLVM_FALSE,
   );
-  return resp;
+  let global = LLVMAddGlobal(ctx.module.module, LLVMTypeOf(init), c_str!(".str"));
+  LLVMSetInitializer(global, init);
+  LLVMSetGlobalConstant(global, LLVM_TRUE);
+  LLVMSetLinkage(global, LLVMLinkage::LLVMPrivateLinkage);
+  LLVMSetUnnamedAddress(global, LLVMUnnamedAddr::LLVMGlobalUnnamedAddr);
+  let mut indices = [
+    LLVMConstInt(LLVMInt32TypeInContext(ctx.context), 0, LLVM_FALSE),
+    LLVMConstInt(LLVMInt32TypeInContext(ctx.context), 0, LLVM_FALSE),
+  ];
+  LLVMConstInBoundsGEP(global, indices.as_mut_ptr(), indices.len() as c_uint)
 }
 
-unsafe fn get_type(ty: &String) -> LLVMTypeRef {
-  // println!("get_type: {}", ty);
-  match ty.as_str() {
-    _ if ty.ends_with("*") => {
-      LLVMPointerType(get_type(&ty.strip_suffix("*").unwrap().to_string()), 0)
+unsafe fn get_type(context: LLVMContextRef, ty: &Type) -> LLVMTypeRef {
+  // println!("get_type: {:?}", ty);
+  match ty {
+    Type::Void => LLVMVoidTypeInContext(context),
+    Type::Bool => LLVMInt1TypeInContext(context),
+    Type::Char => LLVMInt8TypeInContext(context),
+    Type::String => LLVMPointerType(LLVMInt8TypeInContext(context), 0),
+    Type::Int { bits, .. } => LLVMIntTypeInContext(context, *bits),
+    Type::Float { bits: 32 } => LLVMFloatTypeInContext(context),
+    Type::Float { .. } => LLVMDoubleTypeInContext(context),
+    // LLVM has no void pointers, so they are treated as byte pointers like in C.
+    Type::Pointer(ty) if **ty == Type::Void => LLVMPointerType(LLVMInt8TypeInContext(context), 0),
+    Type::Pointer(ty) => LLVMPointerType(get_type(context, ty), 0),
+    Type::Array(ty, Some(len)) => LLVMArrayType(get_type(context, ty), *len),
+    // Unsized arrays decay to a pointer to their first element.
+    Type::Array(ty, None) => LLVMPointerType(get_type(context, ty), 0),
+    // Values of a function type are pointers to the function.
+    Type::Function { args, ret } => {
+      let mut args_type: Vec<LLVMTypeRef> = args.iter().map(|x| get_type(context, x)).collect();
+      let fn_type = LLVMFunctionType(
+        get_type(context, ret),
+        args_type.as_mut_ptr(),
+        args_type.len() as c_uint,
+        LLVM_FALSE,
+      );
+      LLVMPointerType(fn_type, 0)
+    }
+    // Structs and enums are declared by name before they are used.
+    Type::Named(name) | Type::Struct { name, .. } | Type::Enum { name, .. } => {
+      let name = CString::new(name.as_str()).unwrap();
+      LLVMGetTypeByName2(context, name.as_ptr())
     }
-    "u128" | "i128" => LLVMInt64Type(),
-    "u64" | "i64" => LLVMInt64Type(),
-    "u32" | "i32" => LLVMInt32Type(),
-    "u16" | "i16" => LLVMInt16Type(),
-    "u8" | "i8" | "char" => LLVMInt8Type(),
-    "string" => get_type(&String::from("char*")),
-    _ => LLVMVoidType(),
   }
 }
 
-unsafe fn get_value(ty: &String, value: &String) -> LLVMValueRef {
-  // println!("get_value: {} {}", ty, value);
-  match ty.as_str() {
-    "u128" | "i128" | "u64" | "i64" | "u32" | "i32" | "u16" | "i16" | "u8" | "i8" => LLVMConstInt(
-      get_type(ty),
-      value.parse().unwrap(),
-      llvm_bool(ty.starts_with("u")),
+/// Gets the type the type checker gave an expression.
+/// Expressions it couldn't type are only compiled when checking is skipped, and are treated as `i32`.
+fn value_type(expression: &Expression) -> Type {
+  expression.ty.clone().unwrap_or(Type::Int {
+    bits: 32,
+    signed: true,
+  })
+}
+
+/// Gets the type both operands of a binary operation are converted to, which is the wider of the two.
+fn operand_type(lhs: &Type, rhs: &Type) -> Type {
+  match (lhs, rhs) {
+    (Type::Float { bits: a }, Type::Float { bits: b }) if b > a => rhs.clone(),
+    (Type::Int { .. } | Type::Char, Type::Float { .. }) => rhs.clone(),
+    (Type::Int { bits: a, .. }, Type::Int { bits: b, .. }) if b > a => rhs.clone(),
+    _ => lhs.clone(),
+  }
+}
+
+/// Gets the constant for a number or decimal literal of the given type.
+unsafe fn get_value(ctx: &CompileContext, ty: &Type, value: &str) -> LLVMValueRef {
+  // println!("get_value: {:?} {}", ty, value);
+  match ty {
+    // Parsed by LLVM, as 128-bit literals don't fit in the `u64` that `LLVMConstInt` takes.
+    Type::Int { .. } | Type::Char => LLVMConstIntOfStringAndSize(
+      get_type(ctx.context, ty),
+      value.as_ptr() as *const c_char,
+      value.len() as c_uint,
+      10,
     ),
-    "char" => {
-      let char = value.chars().nth(0).unwrap() as c_char as c_ulonglong;
-      LLVMConstInt(LLVMInt8Type(), char, LLVM_FALSE)
-    }
-    "string" => LLVMConstInt(get_type(ty), value.parse().unwrap(), LLVM_FALSE),
+    Type::Float { .. } => LLVMConstReal(get_type(ctx.context, ty), value.parse().unwrap()),
+    // `0` is the only number that can be a pointer.
+    Type::Pointer(_) | Type::String => LLVMConstNull(get_type(ctx.context, ty)),
     _ => ptr::null_mut() as LLVMValueRef,
   }
 }
@@ -343,3 +1939,120 @@ fn llvm_bool(from: bool) -> i32 {
     LLVM_FALSE
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::parser::{files_from, Program};
+
+  /// Builds every file of a program, returning the LLVM IR of each by path.
+  fn program_ir(sources: &[(&str, &str)]) -> HashMap<String, String> {
+    let mut program = Program {
+      files: files_from(sources),
+    };
+    program.validate();
+    assert!(!program.report_diagnostics());
+    init_llvm();
+    let exports = program.exports();
+    let options = BuildOptions::default();
+    let mut ir = HashMap::new();
+    for path in program.dependency_order() {
+      let file = &program.files[&path];
+      let text = build_module(file, &options, &file.imports(&exports), |module| unsafe {
+        let text = LLVMPrintModuleToString(module.module);
+        let string = std::ffi::CStr::from_ptr(text)
+          .to_string_lossy()
+          .into_owned();
+        LLVMDisposeMessage(text);
+        Ok(string)
+      });
+      ir.insert(path, text.unwrap());
+    }
+    ir
+  }
+
+  #[test]
+  fn private_types_per_file() {
+    let ir = program_ir(&[
+      (
+        "main.plume",
+        "import { Shape, area } from \"shapes.plume\";\nstruct Box { a: i8 }\nfunction main() -> i32 { let b: Box = Box { a: 2 }; let s: Shape = Shape { w: 3, h: 4 }; return area(s) + b.a as i32; }",
+      ),
+      (
+        "shapes.plume",
+        "struct Box { x: i64, y: i64 }\nexport struct Shape { w: i32, h: i32 }\nexport function area(s: Shape) -> i32 { let b: Box = Box { x: 1, y: 2 }; return s.w * s.h + b.y as i32; }",
+      ),
+    ]);
+    assert!(ir["main.plume"].contains("%Box = type { i8 }"));
+    assert!(ir["main.plume"].contains("%Shape = type { i32, i32 }"));
+    assert!(ir["shapes.plume"].contains("%Box = type { i64, i64 }"));
+    assert!(ir["shapes.plume"].contains("%Shape = type { i32, i32 }"));
+  }
+
+  #[test]
+  fn linkage() {
+    let ir = program_ir(&[(
+      "main.plume",
+      "declare function puts(s: string) -> i32;\n\
+       declare let errno: i32;\n\
+       let count: i32 = 1;\n\
+       export const limit: i64 = 3;\n\
+       function helper() -> i32 { return count + errno; }\n\
+       export function api() -> i32 { return helper(); }\n\
+       function main() -> i32 { puts(\"hi\"); return api(); }",
+    )]);
+    let ir = &ir["main.plume"];
+    assert!(ir.contains("declare i32 @puts(i8*)"));
+    assert!(ir.contains("define internal i32 @helper()"));
+    assert!(ir.contains("define i32 @api()"));
+    assert!(ir.contains("define i32 @main()"));
+    assert!(ir.contains("@errno = external global i32"));
+    assert!(ir.contains("@count = internal global i32 1"));
+    assert!(ir.contains("@limit = constant i64 3"));
+  }
+
+  #[test]
+  fn c_abi() {
+    let ir = program_ir(&[
+      (
+        "main.plume",
+        "import { Point, sum } from \"point.plume\";\n\
+         struct Pair { a: f64, b: f64 }\n\
+         struct Big { a: i64, b: i64, c: i64 }\n\
+         declare function swap(p: Pair) -> Pair;\n\
+         declare function grow(b: Big, d: i64) -> Big;\n\
+         function main() -> i32 { let q: Pair = swap(Pair { a: 1.0, b: 2.0 }); let b: Big = grow(Big { a: 1, b: 2, c: 3 }, 1); return sum(Point { x: 1, y: 2 }); }",
+      ),
+      (
+        "point.plume",
+        "export struct Point { x: i32, y: i32 }\nexport declare function sum(p: Point) -> i32;",
+      ),
+    ]);
+    assert!(ir["point.plume"].contains("declare i32 @sum(i64)"));
+    let ir = &ir["main.plume"];
+    // Small structs go in registers, and big ones through pointers to copies.
+    assert!(ir.contains("declare i32 @sum(i64)"));
+    assert!(ir.contains("declare { double, double } @swap({ double, double })"));
+    assert!(ir.contains("declare void @grow(%Big* sret(%Big), %Big* byval(%Big), i64)"));
+    assert!(ir.contains("call void @grow(%Big* sret(%Big) %sret, %Big* byval(%Big) %byval, i64 1)"));
+    assert!(ir.contains("call i32 @sum(i64 %"));
+  }
+
+  #[test]
+  fn indirect_calls() {
+    let ir = program_ir(&[(
+      "main.plume",
+      "function double(x: i32) -> i32 { return x * 2; }\n\
+       function apply(f: function(i32) -> i32, x: i32) -> i32 { return f(x); }\n\
+       function main() -> i32 { let g: function(i32) -> i32 = double; return apply(double, 5) + g(1); }",
+    )]);
+    let ir = &ir["main.plume"];
+    assert!(ir.contains("store i32 (i32)* @double, i32 (i32)** %g"));
+    assert!(ir.contains("call i32 @apply(i32 (i32)* @double, i32 5)"));
+    // Parameters and locals of a function type are loaded before they're called.
+    assert!(ir.contains("%2 = load i32 (i32)*, i32 (i32)** %f"));
+    assert!(ir.contains("call i32 %2(i32 %x1)"));
+    assert!(ir.contains("%1 = load i32 (i32)*, i32 (i32)** %g"));
+    assert!(ir.contains("call i32 %1(i32 1)"));
+  }
+}
@@ -1,4 +1,5 @@
 use super::target_machine::TargetMachine;
+use llvm_sys::bit_writer::LLVMWriteBitcodeToFile;
 use llvm_sys::core::*;
 use llvm_sys::target_machine::*;
 use llvm_sys::LLVMModule;
@@ -49,17 +50,47 @@ impl Module {
     Ok(())
   }
 
+  pub fn write_bitcode_file(&mut self, path: &Path) -> Result<(), String> {
+    unsafe {
+      let path_ptr = self.new_string_ptr(path.to_str().unwrap());
+      if LLVMWriteBitcodeToFile(self.module, path_ptr) != 0 {
+        return Err("Writing LLVM bitcode file failed.".to_string());
+      }
+    }
+    Ok(())
+  }
+
+  pub fn write_assembly_file(&mut self, path: &Path) -> Result<(), String> {
+    // Code generation changes the module it runs on, so the assembly is generated from a copy, leaving the module
+    // as it was for the object file.
+    unsafe {
+      let module = LLVMCloneModule(self.module);
+      let result = self.emit_to_file(module, path, LLVMCodeGenFileType::LLVMAssemblyFile);
+      LLVMDisposeModule(module);
+      result
+    }
+  }
+
   pub fn write_object_file(&mut self, path: &Path) -> Result<(), String> {
+    self.emit_to_file(self.module, path, LLVMCodeGenFileType::LLVMObjectFile)
+  }
+
+  fn emit_to_file(
+    &mut self,
+    module: *mut LLVMModule,
+    path: &Path,
+    file_type: LLVMCodeGenFileType,
+  ) -> Result<(), String> {
     unsafe {
-      let target_triple = LLVMGetTarget(self.module);
+      let target_triple = LLVMGetTarget(module);
       let target_machine = TargetMachine::new(target_triple)?;
 
-      let mut obj_error = self.new_mut_string_ptr("Writing object file failed.");
+      let mut obj_error = self.new_mut_string_ptr("Writing output file failed.");
       let result = LLVMTargetMachineEmitToFile(
         target_machine.tm,
-        self.module,
+        module,
         self.new_string_ptr(path.to_str().unwrap()) as *mut i8,
-        LLVMCodeGenFileType::LLVMObjectFile,
+        file_type,
         &mut obj_error,
       );
 
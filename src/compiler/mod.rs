@@ -1,21 +1,181 @@
+mod linker;
 mod llvm;
+use crate::parser::Item;
 use crate::{Program, SourceFile};
+pub use linker::link;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+/// Settings that change how a program is compiled.
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+  /// The target to compile for, or the host when `None`.
+  pub target_triple: Option<String>,
+  /// Leaves out the checks of debug builds, such as array bounds checks.
+  pub release: bool,
+  /// The directory build output goes in. Each target and profile gets its own subdirectory of it.
+  pub output_dir: PathBuf,
+  /// The kinds of output written for each file. Files are only linked when object files are emitted.
+  pub emit: Vec<Emit>,
+}
+
+/// A kind of output the compiler can write for each file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+  Object,
+  Assembly,
+  LlvmIr,
+  LlvmBitcode,
+}
+
+impl Emit {
+  /// Every kind of output, in the order they're written. Code generation runs last, after the module is exported.
+  pub const ALL: [Emit; 4] = [
+    Emit::LlvmIr,
+    Emit::LlvmBitcode,
+    Emit::Assembly,
+    Emit::Object,
+  ];
+
+  pub fn extension(self) -> &'static str {
+    match self {
+      Emit::Object => "o",
+      Emit::Assembly => "s",
+      Emit::LlvmIr => "ll",
+      Emit::LlvmBitcode => "bc",
+    }
+  }
+
+  pub fn description(self) -> &'static str {
+    match self {
+      Emit::Object => "object file",
+      Emit::Assembly => "assembly",
+      Emit::LlvmIr => "LLVM IR",
+      Emit::LlvmBitcode => "LLVM bitcode",
+    }
+  }
+}
+
+impl FromStr for Emit {
+  type Err = String;
+
+  /// Parses the names used by `--emit`.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "obj" => Ok(Emit::Object),
+      "asm" => Ok(Emit::Assembly),
+      "llvm-ir" => Ok(Emit::LlvmIr),
+      "llvm-bc" => Ok(Emit::LlvmBitcode),
+      _ => Err(format!("unknown kind of output `{}`", s)),
+    }
+  }
+}
+
+impl BuildOptions {
+  /// Gets the target triple to compile for, which is the host's unless another target was picked.
+  pub fn target_triple(&self) -> String {
+    match &self.target_triple {
+      Some(target_triple) => target_triple.clone(),
+      None => llvm::default_target_triple(),
+    }
+  }
+
+  /// Gets the directory output for this target and profile goes in, such as `build/x86_64-pc-linux-gnu/debug`.
+  pub fn target_dir(&self) -> PathBuf {
+    let profile = if self.release { "release" } else { "debug" };
+    self.output_dir.join(self.target_triple()).join(profile)
+  }
+
+  /// Gets where output compiled from a source file goes, mirroring the layout of the sources.
+  pub fn output_path(&self, source_path: &str, extension: &str) -> PathBuf {
+    let mut path = self.target_dir();
+    for component in Path::new(source_path).components() {
+      match component {
+        Component::Normal(name) => path.push(name),
+        // Keeps files imported from outside the project inside the output directory.
+        Component::ParentDir => path.push("__"),
+        Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+      }
+    }
+    path.set_extension(extension);
+    path
+  }
+}
 
 impl Program {
-  pub fn compile(&self, target_triple: Option<String>) {
+  /// Compiles every file of the program, returning the paths of the object files in the order they were compiled, if
+  /// object files are emitted.
+  pub fn compile(&self, options: &BuildOptions) -> Result<Vec<PathBuf>, String> {
     llvm::init_llvm();
-    for (path, file) in &self.files {
+    let mut objects = vec![];
+    let exports = self.exports();
+    // Files are compiled after the files they import, so the types they share are laid out first.
+    for path in self.dependency_order() {
+      let file = &self.files[&path];
       println!("[plume] compiling \"{}\"", path);
-      match file.compile(target_triple.clone()) {
-        Ok(_) => println!("[plume] compiled \"{}\"", path),
-        Err(err) => panic!("[plume] failed to compile \"{}\": {}", path, err),
+      match file.compile(options, &file.imports(&exports)) {
+        Ok(object) => {
+          println!("[plume] compiled \"{}\"", path);
+          objects.extend(object);
+        }
+        Err(err) => return Err(format!("[plume] failed to compile \"{}\": {}", path, err)),
       }
     }
+    Ok(objects)
   }
 }
 
 impl SourceFile {
-  pub fn compile(&self, target_triple: Option<String>) -> Result<(), String> {
-    llvm::compile(self, target_triple)
+  /// Compiles the file to the emitted kinds of output, returning the path of the object file if there is one.
+  ///
+  /// The items the file imports are declared, so they're linked with the files defining them.
+  pub fn compile(
+    &self,
+    options: &BuildOptions,
+    imports: &HashMap<String, Item>,
+  ) -> Result<Option<PathBuf>, String> {
+    llvm::compile(self, options, imports)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn output_paths() {
+    let mut options = BuildOptions {
+      target_triple: Some("wasm32-unknown-unknown".to_string()),
+      release: false,
+      output_dir: PathBuf::from("build"),
+      emit: vec![Emit::Object],
+    };
+    assert_eq!(
+      options.output_path("./main.plume", "o"),
+      Path::new("build/wasm32-unknown-unknown/debug/main.o")
+    );
+    assert_eq!(
+      options.output_path("src/../../lib/list.plume", "ll"),
+      Path::new("build/wasm32-unknown-unknown/debug/src/__/__/lib/list.ll")
+    );
+    options.release = true;
+    assert_eq!(
+      options.target_dir(),
+      Path::new("build/wasm32-unknown-unknown/release")
+    );
+  }
+
+  #[test]
+  fn emit_kinds() {
+    for (name, emit) in [
+      ("obj", Emit::Object),
+      ("asm", Emit::Assembly),
+      ("llvm-ir", Emit::LlvmIr),
+      ("llvm-bc", Emit::LlvmBitcode),
+    ] {
+      assert_eq!(name.parse::<Emit>(), Ok(emit));
+    }
+    assert!("exe".parse::<Emit>().is_err());
   }
 }
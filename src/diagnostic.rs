@@ -0,0 +1,171 @@
+use crate::span::Span;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+  Error,
+  Warning,
+}
+
+/// Points at a span of source with an optional message explaining it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Label {
+  pub span: Span,
+  pub message: String,
+}
+
+/// A problem found in a source file, such as a syntax error.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub message: String,
+  /// The label pointing at the code that caused the diagnostic.
+  pub primary: Label,
+  /// Labels pointing at related code, e.g. the opening brace of an unclosed block.
+  pub secondary: Vec<Label>,
+  pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+  /// Creates an error diagnostic pointing at the given span.
+  pub fn error<S: AsRef<str>>(message: S, span: Span) -> Self {
+    Self {
+      severity: Severity::Error,
+      message: message.as_ref().to_string(),
+      primary: Label {
+        span,
+        message: String::new(),
+      },
+      secondary: vec![],
+      notes: vec![],
+    }
+  }
+
+  /// Creates a warning diagnostic pointing at the given span.
+  pub fn warning<S: AsRef<str>>(message: S, span: Span) -> Self {
+    Self {
+      severity: Severity::Warning,
+      ..Self::error(message, span)
+    }
+  }
+
+  /// Sets the message shown under the primary span.
+  pub fn with_label<S: AsRef<str>>(mut self, message: S) -> Self {
+    self.primary.message = message.as_ref().to_string();
+    self
+  }
+
+  /// Adds a label pointing at related code.
+  pub fn with_secondary<S: AsRef<str>>(mut self, span: Span, message: S) -> Self {
+    self.secondary.push(Label {
+      span,
+      message: message.as_ref().to_string(),
+    });
+    self
+  }
+
+  /// Adds a note shown below the source snippet.
+  pub fn with_note<S: AsRef<str>>(mut self, note: S) -> Self {
+    self.notes.push(note.as_ref().to_string());
+    self
+  }
+
+  pub fn is_error(&self) -> bool {
+    self.severity == Severity::Error
+  }
+
+  /// Renders the diagnostic for the terminal, quoting the lines of `source` its labels point at.
+  pub fn render(&self, path: &str, source: &str) -> String {
+    let (line, col) = line_col(source, self.primary.span.start);
+    let mut labels: Vec<(&Label, char)> = vec![(&self.primary, '^')];
+    labels.extend(
+      self
+        .secondary
+        .iter()
+        .filter(|x| x.span.file == self.primary.span.file)
+        .map(|x| (x, '-')),
+    );
+    labels.sort_by_key(|(label, _)| label.span.start);
+    let gutter = labels
+      .iter()
+      .map(|(label, _)| line_col(source, label.span.start).0.to_string().len())
+      .max()
+      .unwrap_or(1);
+    let pad = " ".repeat(gutter);
+
+    let mut out = format!("{}: {}\n", self.severity, self.message);
+    out.push_str(&format!("{}--> {}:{}:{}\n", pad, path, line, col));
+    out.push_str(&format!("{} |\n", pad));
+    let mut last_line = 0;
+    for (label, marker) in labels {
+      let (line, col) = line_col(source, label.span.start);
+      if line != last_line {
+        let text = source.lines().nth(line - 1).unwrap_or("");
+        out.push_str(&format!("{:>w$} | {}\n", line, text, w = gutter));
+        last_line = line;
+      }
+      let width = source[label.span.start.min(source.len())..label.span.end.min(source.len())]
+        .lines()
+        .next()
+        .map(|x| x.chars().count())
+        .unwrap_or(0)
+        .max(1);
+      let underline = format!(
+        "{} | {}{} {}",
+        pad,
+        " ".repeat(col - 1),
+        marker.to_string().repeat(width),
+        label.message
+      );
+      out.push_str(underline.trim_end());
+      out.push('\n');
+    }
+    for note in &self.notes {
+      out.push_str(&format!("{} = note: {}\n", pad, note));
+    }
+    out
+  }
+}
+
+impl fmt::Display for Severity {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Severity::Error => write!(f, "error"),
+      Severity::Warning => write!(f, "warning"),
+    }
+  }
+}
+
+/// Gets the one-based line and column of a byte offset within the source.
+fn line_col(source: &str, offset: usize) -> (usize, usize) {
+  let offset = offset.min(source.len());
+  let before = &source[..offset];
+  let line = before.matches('\n').count() + 1;
+  let line_start = before.rfind('\n').map(|x| x + 1).unwrap_or(0);
+  (line, source[line_start..offset].chars().count() + 1)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn render() {
+    let source = "function main() {\n  let x = 1;\n}";
+    let diagnostic = Diagnostic::error("missing type", Span::new(0, 26, 27))
+      .with_label("expected `:` here")
+      .with_secondary(Span::new(0, 24, 25), "variable declared here")
+      .with_note("variables must have a type");
+    assert_eq!(
+      diagnostic.render("main.plume", source),
+      "error: missing type
+ --> main.plume:2:9
+  |
+2 |   let x = 1;
+  |       - variable declared here
+  |         ^ expected `:` here
+  = note: variables must have a type
+"
+    );
+  }
+}
@@ -1,56 +1,87 @@
 mod test;
 mod token;
+use crate::diagnostic::Diagnostic;
+use crate::span::{FileId, Span};
 pub use token::{Token, TokenKind};
 
 #[derive(Clone)]
 pub struct Lexer {
   source: Vec<char>,
+  file: FileId,
+  /// Index of the current char within `source`.
   current: usize,
-  next: usize,
+  /// Byte offset of the current char within the original input.
+  offset: usize,
+  /// Byte offset where the token being matched begins.
+  start: usize,
   char: char,
-  line: u32,
-  column: u32,
+  /// Problems found while lexing, such as unknown escape sequences.
+  diagnostics: Vec<Diagnostic>,
 }
 
 impl Lexer {
   /// Creates a new lexer from a string
-  pub fn new(input: String) -> Self {
-    let mut s = Self {
-      source: input.chars().collect(),
+  pub fn new(input: String, file: FileId) -> Self {
+    let source: Vec<char> = input.chars().collect();
+    let char = source.first().copied().unwrap_or('\0');
+    Self {
+      source,
+      file,
       current: 0,
-      next: 1,
-      char: '\0',
-      line: 1,
-      column: 1,
-    };
-    s.char = s.source[s.current];
-    s
+      offset: 0,
+      start: 0,
+      char,
+      diagnostics: vec![],
+    }
+  }
+
+  /// The file the source being lexed belongs to.
+  pub fn file(&self) -> FileId {
+    self.file
+  }
+
+  /// Takes the problems found in the tokens lexed so far.
+  pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+    std::mem::take(&mut self.diagnostics)
   }
 
   /// Determines if a given string is a keyword
-  fn is_keyword(string: &String) -> bool {
-    match string.as_str() {
-      "declare" | "import" | "export" | "from" | "function" | "return" | "let" | "const" | "if"
-      | "else" | "match" | "for" | "while" | "as" => true,
-      _ => false,
-    }
+  fn is_keyword(string: &str) -> bool {
+    matches!(
+      string,
+      "declare"
+        | "import"
+        | "export"
+        | "from"
+        | "function"
+        | "struct"
+        | "enum"
+        | "return"
+        | "let"
+        | "const"
+        | "if"
+        | "else"
+        | "match"
+        | "for"
+        | "while"
+        | "break"
+        | "continue"
+        | "as"
+    )
   }
 
   /// Shifts the cursor over the source up by one, consuming a single char.
   fn read(&mut self) {
-    if self.next >= self.source.len() {
-      self.char = '\0'
-    } else {
-      self.char = self.source[self.next];
+    if self.current < self.source.len() {
+      self.offset += self.char.len_utf8();
+      self.current += 1;
     }
-    if self.char == '\n' {
-      self.line += 1;
-      self.column = 0;
-    } else {
-      self.column += 1
-    };
-    self.current = self.next;
-    self.next = self.current + 1;
+    self.char = self.source.get(self.current).copied().unwrap_or('\0');
+  }
+
+  /// Checks if the cursor has consumed the whole source.
+  fn is_at_end(&self) -> bool {
+    self.current >= self.source.len()
   }
 
   /// Iterates over all whitespace, as Plume does not care about whitespace.
@@ -60,9 +91,9 @@ impl Lexer {
     }
   }
 
-  /// Creates a token for the given kind and literal with position data.
+  /// Creates a token for the given kind and literal, spanning from the start of the token to the cursor.
   fn token_str<S: AsRef<str>>(&self, kind: TokenKind, string: S) -> Token {
-    Token::new(kind, string, self.line, self.column)
+    Token::new(kind, string, Span::new(self.file, self.start, self.offset))
   }
 
   /// Creates a token for the given kind and char with position data.
@@ -70,19 +101,25 @@ impl Lexer {
     self.token_str(kind, ch.to_string())
   }
 
+  /// Consumes a single char and creates a token of the given kind for it.
+  fn single_char(&mut self, kind: TokenKind) -> Token {
+    let ch = self.char;
+    self.read();
+    self.token_char(kind, ch)
+  }
+
   /// Finds the next available token.
   fn match_token(&mut self) -> Token {
     self.skip_whitespace();
+    self.start = self.offset;
     match self.char {
-      '/'
-        if self.source.get(self.next) == Some(&'/') || self.source.get(self.next) == Some(&'*') =>
-      {
+      '/' if self.peek_char() == Some('/') || self.peek_char() == Some('*') => {
         let mut buffer = String::new();
-        let is_multiline = self.source.get(self.next) == Some(&'*');
+        let is_multiline = self.peek_char() == Some('*');
         self.read();
         self.read();
-        while self.current < self.source.len() {
-          if is_multiline && (self.char == '*' && self.source.get(self.next) == Some(&'/')) {
+        while !self.is_at_end() {
+          if is_multiline && (self.char == '*' && self.peek_char() == Some('/')) {
             self.read();
             self.read();
             break;
@@ -97,11 +134,30 @@ impl Lexer {
       }
       '\'' | '"' => {
         let mut buffer = String::new();
-        let is_char = self.char == '\'';
+        let quote = self.char;
+        let is_char = quote == '\'';
         self.read();
-        while self.current < self.source.len() && self.char != '"' && self.char != '\'' {
-          buffer.push(self.char);
-          self.read();
+        while !self.is_at_end() && self.char != quote {
+          if self.char == '\\' {
+            if let Some(ch) = self.read_escape() {
+              buffer.push(ch);
+            }
+          } else {
+            buffer.push(self.char);
+            self.read();
+          }
+        }
+        if self.is_at_end() {
+          self.diagnostics.push(
+            Diagnostic::error(
+              format!(
+                "unterminated {} literal",
+                if is_char { "char" } else { "string" }
+              ),
+              Span::new(self.file, self.start, self.offset),
+            )
+            .with_label(format!("missing closing `{}`", quote)),
+          );
         }
         self.read();
         self.token_str(
@@ -113,11 +169,11 @@ impl Lexer {
           buffer,
         )
       }
-      _ if self.char.is_alphabetic() => {
+      _ if self.char.is_alphabetic() || self.char == '_' => {
         let mut buffer = String::new();
         buffer.push(self.char);
         self.read();
-        while self.current < self.source.len() && self.char.is_alphanumeric() || self.char == '_' {
+        while !self.is_at_end() && (self.char.is_alphanumeric() || self.char == '_') {
           buffer.push(self.char);
           self.read();
         }
@@ -136,7 +192,7 @@ impl Lexer {
         self.read();
         let mut has_decimal = false;
         loop {
-          if self.current >= self.source.len() {
+          if self.is_at_end() {
             break;
           }
           // Skip over number separators.
@@ -146,7 +202,7 @@ impl Lexer {
           if !self.char.is_numeric() && self.char != '.' {
             break;
           }
-          if has_decimal == true && self.char == '.' {
+          if has_decimal && self.char == '.' {
             break;
           }
           if self.char == '.' {
@@ -157,182 +213,202 @@ impl Lexer {
         }
         self.token_str(TokenKind::Number, buffer)
       }
-      '-' if self.source.get(self.next) == Some(&'>') => {
-        self.read();
-        self.read();
-        self.token_str(TokenKind::ReturnArrow, "->".to_owned())
-      }
-      '~' => {
-        let resp = self.token_char(TokenKind::UnaryOperator, self.char);
+      '-' if self.peek_char() == Some('>') => {
         self.read();
-        resp
-      }
-      '&' => {
-        let resp = self.token_char(TokenKind::SomeOperator, self.char);
         self.read();
-        resp
+        self.token_str(TokenKind::ReturnArrow, "->")
       }
+      '~' => self.single_char(TokenKind::UnaryOperator),
       ',' => {
         self.read();
         self.token_str(TokenKind::Comma, ",")
       }
-      ':' => {
+      ':' if self.peek_char() == Some(':') => {
         self.read();
-        self.token_str(TokenKind::Colon, ":")
-      }
-      ';' => {
         self.read();
-        self.token_str(TokenKind::Semicolon, ";")
+        self.token_str(TokenKind::DoubleColon, "::")
       }
-      '(' | ')' => {
-        let resp = self.token_char(TokenKind::Parens, self.char);
+      ':' => {
         self.read();
-        resp
+        self.token_str(TokenKind::Colon, ":")
       }
-      '[' | ']' => {
-        let resp = self.token_char(TokenKind::Brackets, self.char);
+      '.' => {
         self.read();
-        resp
+        self.token_str(TokenKind::Dot, ".")
       }
-      '{' | '}' => {
-        let resp = self.token_char(TokenKind::Braces, self.char);
+      ';' => {
         self.read();
-        resp
+        self.token_str(TokenKind::Semicolon, ";")
       }
+      '(' | ')' => self.single_char(TokenKind::Parens),
+      '[' | ']' => self.single_char(TokenKind::Brackets),
+      '{' | '}' => self.single_char(TokenKind::Braces),
       _ => {
-        let resp: Token = match self.char {
+        let (kind, literal) = match self.char {
           // +=, -=, ++, --, +, and -
           '+' | '-' => {
-            if self.peek_char() == Some(self.char) {
+            let ch = self.char;
+            if self.peek_char() == Some(ch) {
               self.read();
-              self.token_str(
-                TokenKind::UnaryOperator,
-                format!("{}{}", self.char, self.char),
-              )
+              (TokenKind::UnaryOperator, format!("{}{}", ch, ch))
             } else if self.peek_char() == Some('=') {
-              let resp = self.token_str(TokenKind::BinaryOperator, format!("{}=", self.char));
               self.read();
-              resp
+              (TokenKind::BinaryOperator, format!("{}=", ch))
+            } else if ch == '-' {
+              (TokenKind::SomeOperator, ch.to_string())
             } else {
-              self.token_char(
-                if self.char == '-' {
-                  TokenKind::SomeOperator
-                } else {
-                  TokenKind::BinaryOperator
-                },
-                self.char,
-              )
+              (TokenKind::BinaryOperator, ch.to_string())
             }
           }
           // *=, /=, %=, *, /, and %
           '*' | '/' | '%' => {
+            let ch = self.char;
             if self.peek_char() == Some('=') {
-              let resp = self.token_str(TokenKind::BinaryOperator, format!("{}=", self.char));
               self.read();
-              resp
+              (TokenKind::BinaryOperator, format!("{}=", ch))
+            } else if ch == '*' {
+              (TokenKind::SomeOperator, ch.to_string())
             } else {
-              self.token_char(
-                if self.char == '*' {
-                  TokenKind::SomeOperator
-                } else {
-                  TokenKind::BinaryOperator
-                },
-                self.char,
-              )
+              (TokenKind::BinaryOperator, ch.to_string())
             }
           }
-          // = and ==
+          // &&, ||, &=, ^=, |=, &, ^, and |
+          '&' | '^' | '|' => {
+            let ch = self.char;
+            if ch != '^' && self.peek_char() == Some(ch) {
+              self.read();
+              (TokenKind::BinaryOperator, format!("{}{}", ch, ch))
+            } else if self.peek_char() == Some('=') {
+              self.read();
+              (TokenKind::BinaryOperator, format!("{}=", ch))
+            } else if ch == '&' {
+              (TokenKind::SomeOperator, ch.to_string())
+            } else {
+              (TokenKind::BinaryOperator, ch.to_string())
+            }
+          }
+          // =, ==, and =>
           '=' => {
             if self.peek_char() == Some('=') {
-              let resp = self.token_str(TokenKind::BinaryOperator, "==");
               self.read();
-              resp
+              (TokenKind::BinaryOperator, "==".to_owned())
+            } else if self.peek_char() == Some('>') {
+              self.read();
+              (TokenKind::FatArrow, "=>".to_owned())
             } else {
-              self.token_char(TokenKind::BinaryOperator, self.char)
+              (TokenKind::BinaryOperator, "=".to_owned())
             }
           }
           // ! and !=
           '!' => {
             if self.peek_char() == Some('=') {
               self.read();
-              self.token_str(TokenKind::BinaryOperator, "!=")
+              (TokenKind::BinaryOperator, "!=".to_owned())
             } else {
-              self.token_char(TokenKind::UnaryOperator, self.char)
+              (TokenKind::UnaryOperator, "!".to_owned())
             }
           }
           // <<=, >>=, <<, >>, <, >, <=, and >=
           '>' | '<' => {
-            if self.peek_char() == Some(self.char) {
+            let ch = self.char;
+            if self.peek_char() == Some(ch) {
               self.read();
               if self.peek_char() == Some('=') {
-                let resp = self.token_str(
-                  TokenKind::BinaryOperator,
-                  format!("{}{}=", self.char, self.char),
-                );
                 self.read();
-                resp
+                (TokenKind::BinaryOperator, format!("{}{}=", ch, ch))
               } else {
-                self.token_str(
-                  TokenKind::BinaryOperator,
-                  format!("{}{}", self.char, self.char),
-                )
+                (TokenKind::BinaryOperator, format!("{}{}", ch, ch))
               }
             } else if self.peek_char() == Some('=') {
-              let resp = self.token_str(TokenKind::BinaryOperator, format!("{}=", self.char));
               self.read();
-              resp
+              (TokenKind::BinaryOperator, format!("{}=", ch))
             } else {
-              self.token_char(TokenKind::BinaryOperator, self.char)
+              (TokenKind::BinaryOperator, ch.to_string())
             }
           }
-          _ => self.token_char(TokenKind::Whitespace, self.char),
+          _ => (TokenKind::Whitespace, self.char.to_string()),
         };
         self.read();
-        resp
+        self.token_str(kind, literal)
       }
     }
   }
 
+  /// Consumes an escape sequence such as `\\n` or `\\x41`, returning the char it stands for.
+  /// Unknown escapes are reported and skipped.
+  fn read_escape(&mut self) -> Option<char> {
+    let start = self.offset;
+    self.read(); // \
+    let escaped = self.char;
+    self.read();
+    let ch = match escaped {
+      'n' => '\n',
+      't' => '\t',
+      'r' => '\r',
+      '0' => '\0',
+      '\\' | '"' | '\'' => escaped,
+      'x' => {
+        let mut digits = String::new();
+        while digits.len() < 2 && self.char.is_ascii_hexdigit() {
+          digits.push(self.char);
+          self.read();
+        }
+        let span = Span::new(self.file, start, self.offset);
+        return match u8::from_str_radix(&digits, 16) {
+          Ok(value) if digits.len() == 2 && value.is_ascii() => Some(value as char),
+          Ok(_) if digits.len() == 2 => {
+            self.diagnostics.push(
+              Diagnostic::error("out of range hex escape", span)
+                .with_label("must be a character in the range [\\x00-\\x7f]"),
+            );
+            None
+          }
+          _ => {
+            self.diagnostics.push(
+              Diagnostic::error("invalid hex escape", span)
+                .with_label("expected two hexadecimal digits, e.g. `\\x41`"),
+            );
+            None
+          }
+        };
+      }
+      _ => {
+        self.diagnostics.push(
+          Diagnostic::error(
+            format!("unknown character escape: `{}`", escaped.escape_default()),
+            Span::new(self.file, start, self.offset),
+          )
+          .with_label("unknown character escape")
+          .with_note("the supported escapes are \\n, \\t, \\r, \\0, \\\\, \\\", \\' and \\x.."),
+        );
+        return None;
+      }
+    };
+    Some(ch)
+  }
+
   /// Returns the next token (if present) without modifying positioning, allowing you to peek at the next available token.
   pub fn peek(&mut self) -> Option<Token> {
-    if self.next >= self.source.len() {
-      return None;
-    }
-
     let old_current = self.current;
-    let old_next = self.next;
+    let old_offset = self.offset;
+    let old_start = self.start;
     let old_char = self.char;
-    let old_line = self.line;
-    let old_col = self.column;
+    let old_diagnostics = self.diagnostics.len();
 
-    let token = self.match_token();
+    let token = self.next();
 
     self.current = old_current;
-    self.next = old_next;
+    self.offset = old_offset;
+    self.start = old_start;
     self.char = old_char;
-    self.line = old_line;
-    self.column = old_col;
-    Some(token)
+    // The token is lexed again when it is consumed, so its problems would be reported twice.
+    self.diagnostics.truncate(old_diagnostics);
+    token
   }
 
-  fn peek_char(&mut self) -> Option<char> {
-    if self.next >= self.source.len() {
-      return None;
-    }
-
-    let old_current = self.current;
-    let old_next = self.next;
-    let old_char = self.char;
-    let old_line = self.line;
-    let old_col = self.column;
-    self.current = old_current;
-    self.read();
-    let resp = self.char;
-    self.next = old_next;
-    self.char = old_char;
-    self.line = old_line;
-    self.column = old_col;
-    Some(resp)
+  /// Returns the char after the current one without consuming anything.
+  fn peek_char(&self) -> Option<char> {
+    self.source.get(self.current + 1).copied()
   }
 }
 
@@ -341,7 +417,8 @@ impl Iterator for Lexer {
 
   /// Iterate over available tokens.
   fn next(&mut self) -> Option<Token> {
-    if self.next >= self.source.len() {
+    self.skip_whitespace();
+    if self.is_at_end() {
       return None;
     }
 
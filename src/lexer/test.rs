@@ -4,7 +4,10 @@ use super::*;
 // use std::assert;
 #[test]
 fn peek() {
-  let mut lexer = Lexer::new("function print_two(a: string, b: char*) -> char*;".to_owned());
+  let mut lexer = Lexer::new(
+    "function print_two(a: string, b: char*) -> char*;".to_owned(),
+    0,
+  );
   assert_eq!(lexer.peek(), lexer.next()); // keyword 'function'
   assert_eq!(lexer.peek(), lexer.next()); // ident 'print_two'
   assert_eq!(lexer.peek(), lexer.next()); // parens '('
@@ -38,8 +41,11 @@ fn operators() {
     < <= << <<=
     == !=
     ! & ~
+    &= ^ ^=
+    && || | |=
     "
     .to_owned(),
+    0,
   );
   assert!(next_cmp_token(&mut lexer, BinaryOperator, "+"));
   assert!(next_cmp_token(&mut lexer, UnaryOperator, "++"));
@@ -66,6 +72,85 @@ fn operators() {
   assert!(next_cmp_token(&mut lexer, UnaryOperator, "!"));
   assert!(next_cmp_token(&mut lexer, SomeOperator, "&"));
   assert!(next_cmp_token(&mut lexer, UnaryOperator, "~"));
+  assert!(next_cmp_token(&mut lexer, BinaryOperator, "&="));
+  assert!(next_cmp_token(&mut lexer, BinaryOperator, "^"));
+  assert!(next_cmp_token(&mut lexer, BinaryOperator, "^="));
+  assert!(next_cmp_token(&mut lexer, BinaryOperator, "&&"));
+  assert!(next_cmp_token(&mut lexer, BinaryOperator, "||"));
+  assert!(next_cmp_token(&mut lexer, BinaryOperator, "|"));
+  assert!(next_cmp_token(&mut lexer, BinaryOperator, "|="));
+}
+
+#[test]
+fn spans() {
+  let mut lexer = Lexer::new("let ünï = 'a';\n  a <<= 2".to_owned(), 3);
+  let spans: Vec<(usize, usize)> = lexer
+    .by_ref()
+    .map(|x| {
+      assert_eq!(x.span.file, 3);
+      (x.span.start, x.span.end)
+    })
+    .collect();
+  assert_eq!(
+    spans,
+    vec![
+      (0, 3),
+      (4, 9),
+      (10, 11),
+      (12, 15),
+      (15, 16),
+      (19, 20),
+      (21, 24),
+      (25, 26)
+    ]
+  );
+}
+
+#[test]
+fn peek_keeps_position() {
+  let mut lexer = Lexer::new("a b".to_owned(), 0);
+  let peeked = lexer.peek().unwrap();
+  assert_eq!(peeked.span, Span::new(0, 0, 1));
+  assert_eq!(lexer.next().unwrap().span, peeked.span);
+  assert_eq!(lexer.peek().unwrap().span, Span::new(0, 2, 3));
+}
+
+#[test]
+fn escapes() {
+  let mut lexer = Lexer::new(r#""a\n\t\"\\\x41" '\'' "\q \x9" "open"#.to_owned(), 0);
+  assert_eq!(lexer.peek().unwrap().literal, "a\n\t\"\\A");
+  assert!(lexer.take_diagnostics().is_empty());
+  assert!(next_cmp_token(&mut lexer, String, "a\n\t\"\\A"));
+  assert!(next_cmp_token(&mut lexer, Char, "'"));
+  assert!(next_cmp_token(&mut lexer, String, " "));
+  assert!(next_cmp_token(&mut lexer, String, "open"));
+  let messages: Vec<std::string::String> = lexer
+    .take_diagnostics()
+    .into_iter()
+    .map(|x| x.message)
+    .collect();
+  assert_eq!(
+    messages,
+    vec![
+      "unknown character escape: `q`",
+      "invalid hex escape",
+      "unterminated string literal"
+    ]
+  );
+}
+
+#[test]
+fn patterns() {
+  let mut lexer = Lexer::new("Shape::Circle(_r) => _".to_owned(), 0);
+  assert!(next_cmp_token(&mut lexer, Ident, "Shape"));
+  assert!(next_cmp_token(&mut lexer, DoubleColon, "::"));
+  assert!(next_cmp_token(&mut lexer, Ident, "Circle"));
+  assert!(next_cmp_token(&mut lexer, Parens, "("));
+  assert!(next_cmp_token(&mut lexer, Ident, "_r"));
+  assert!(next_cmp_token(&mut lexer, Parens, ")"));
+  assert!(next_cmp_token(&mut lexer, FatArrow, "=>"));
+  assert!(next_cmp_token(&mut lexer, Ident, "_"));
+  assert_eq!(lexer.next(), None);
 }
 
 #[allow(dead_code)]
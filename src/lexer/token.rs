@@ -1,19 +1,19 @@
-#[derive(Debug, PartialEq)]
+use crate::span::Span;
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Token {
   pub kind: TokenKind,
   pub literal: String,
-  pub line: u32,
-  pub col: u32,
+  pub span: Span,
 }
 
 impl Token {
-  pub fn new<S: AsRef<str>>(kind: TokenKind, literal: S, line: u32, col: u32) -> Self {
+  pub fn new<S: AsRef<str>>(kind: TokenKind, literal: S, span: Span) -> Self {
     let str_ref = literal.as_ref().to_string();
     Self {
       kind,
       literal: str_ref,
-      line,
-      col,
+      span,
     }
   }
 
@@ -44,6 +44,8 @@ pub enum TokenKind {
   Brackets,       // [ ]
   Comma,          // ,
   Colon,          // :
+  DoubleColon,    // ::
+  Dot,            // .
   Semicolon,      // ;
   Keyword,        // Some keyword
   Comment,        // A comment
@@ -52,5 +54,6 @@ pub enum TokenKind {
   Number,         // Some number, with or without decimal, float/double/int determined later
   Bool,           // true false
   ReturnArrow,    // ->
+  FatArrow,       // =>
   Whitespace,     // Self explanatory
 }
@@ -1,16 +1,20 @@
 mod ast;
 mod compiler;
+mod diagnostic;
 mod lexer;
 mod parser;
 mod project;
+mod span;
 use clap::{App, Arg, SubCommand};
+use compiler::{BuildOptions, Emit};
 use parser::{Program, SourceFile};
 use project::Project;
+use std::process;
 
-const NAME: &'static str = env!("CARGO_BIN_NAME");
-const AUTHOR: &'static str = env!("CARGO_PKG_AUTHORS");
-const ABOUT: &'static str = env!("CARGO_PKG_DESCRIPTION");
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+const NAME: &str = env!("CARGO_BIN_NAME");
+const AUTHOR: &str = env!("CARGO_PKG_AUTHORS");
+const ABOUT: &str = env!("CARGO_PKG_DESCRIPTION");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 fn main() {
     // Parse args
@@ -27,9 +31,26 @@ fn main() {
                         .long("target")
                         .short("t")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("release")
+                        .long("release")
+                        .help("Build without debug checks, such as array bounds checks"),
+                )
+                .arg(
+                    Arg::with_name("emit")
+                        .long("emit")
+                        .takes_value(true)
+                        .use_delimiter(true)
+                        .possible_values(&["obj", "asm", "llvm-ir", "llvm-bc"])
+                        .default_value("obj")
+                        .help("The kinds of output to write, only linking when obj is included"),
                 ),
         )
-        .subcommand(SubCommand::with_name("validate").about("Build a plume project"))
+        .subcommand(SubCommand::with_name("validate").about("Validate a plume project"))
+        .subcommand(
+            SubCommand::with_name("clean").about("Remove the build output of a plume project"),
+        )
         .subcommand(
             SubCommand::with_name("ast").about("View the abstract syntax tree of the project"),
         )
@@ -39,14 +60,63 @@ fn main() {
     if let Some(command) = matches.subcommand_name() {
         // Load the project.json file from the cwd
         let project = Project::new();
-        let program = Program::new(project.entry);
+        if command == "clean" {
+            match project.clean() {
+                Ok(true) => println!("[plume] removed \"{}\"", project.output_dir().display()),
+                Ok(false) => println!("[plume] nothing to clean"),
+                Err(err) => {
+                    eprintln!("[plume] failed to clean: {}", err);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        let mut program = match Program::new(project.entry.clone()) {
+            Ok(program) => program,
+            Err(err) => {
+                eprintln!("[plume] {}", err);
+                process::exit(1);
+            }
+        };
+        if command == "build" || command == "validate" {
+            program.validate();
+        }
+        if program.report_diagnostics() {
+            process::exit(1);
+        }
         match command {
             "build" => {
                 let build_matches = matches.subcommand_matches("build").unwrap();
-                let target = build_matches.value_of("target").map(|x| String::from(x));
-                program.compile(target)
+                let options = BuildOptions {
+                    target_triple: build_matches.value_of("target").map(String::from),
+                    release: build_matches.is_present("release"),
+                    output_dir: project.output_dir().to_path_buf(),
+                    emit: build_matches
+                        .values_of("emit")
+                        .unwrap()
+                        .map(|emit| emit.parse::<Emit>().unwrap())
+                        .collect(),
+                };
+                let objects = match program.compile(&options) {
+                    Ok(objects) => objects,
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        process::exit(1);
+                    }
+                };
+                if !options.emit.contains(&Emit::Object) {
+                    return;
+                }
+                println!("[plume] linking \"{}\"", project.name);
+                match compiler::link(&project, &options, &objects) {
+                    Ok(output) => println!("[plume] linked \"{}\"", output.display()),
+                    Err(err) => {
+                        eprintln!("[plume] failed to link \"{}\": {}", project.name, err);
+                        process::exit(1);
+                    }
+                }
             }
-            "validate" => program.validate(),
+            "validate" => println!("[plume] validated {} files", program.files.len()),
             "ast" => {
                 for (path, source) in program.files {
                     println!("{}\n{:?}\n", path, source.expressions);
@@ -0,0 +1,1610 @@
+// Handles the type checking of parsed files.
+use super::validator::{defined_item, Exports, Item};
+use super::SourceFile;
+use crate::ast::{
+  BinaryOperator, Expression, ExpressionKind, MatchArm, Pattern, Type, UnaryOperator, Variant,
+};
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
+use std::collections::HashMap;
+
+/// Works out the type of every expression in a file, reporting the ones that don't fit together.
+/// Names are expected to have been resolved by the validator already.
+pub struct TypeChecker<'a> {
+  exports: &'a Exports,
+  /// The scopes that are currently open, the first of which holds the file's top-level definitions.
+  scopes: Vec<HashMap<String, Item>>,
+  /// The return type of the function being checked.
+  ret: Option<Type>,
+  diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> TypeChecker<'a> {
+  pub fn new(exports: &'a Exports) -> Self {
+    Self {
+      exports,
+      scopes: vec![],
+      ret: None,
+      diagnostics: vec![],
+    }
+  }
+
+  /// Checks the file, annotating its expressions with their types and returning the problems that were found.
+  pub fn check_file(mut self, file: &mut SourceFile) -> Vec<Diagnostic> {
+    let mut globals = file.imports(self.exports);
+    for expr in &file.expressions {
+      match &expr.kind {
+        ExpressionKind::Export(inner) => globals.extend(defined_item(inner)),
+        _ => globals.extend(defined_item(expr)),
+      }
+    }
+    self.scopes.push(globals);
+    for expr in &mut file.expressions {
+      self.check_item(expr);
+    }
+    self.diagnostics
+  }
+
+  fn check_item(&mut self, expr: &mut Expression) {
+    match &mut expr.kind {
+      ExpressionKind::Export(inner) | ExpressionKind::Declare(inner) => self.check_item(inner),
+      ExpressionKind::Function { .. } => self.check_function(expr),
+      ExpressionKind::Import { .. } | ExpressionKind::ExportFromFile { .. } => {}
+      ExpressionKind::BinaryOperation {
+        operator: BinaryOperator::Assign,
+        lhs,
+        rhs,
+      } if matches!(lhs.kind, ExpressionKind::VariableDeclaration { .. }) => {
+        // Globals are initialized when the program is loaded, before any code runs.
+        if !is_constant(rhs) {
+          self.diagnostics.push(
+            Diagnostic::error("global initializer is not a constant expression", rhs.span)
+              .with_label("this is only known when the program runs")
+              .with_note("globals can only be initialized with literals and operations on them"),
+          );
+        }
+        self.check(expr, None);
+      }
+      _ => {
+        self.check(expr, None);
+      }
+    }
+  }
+
+  fn check_function(&mut self, expr: &mut Expression) {
+    let span = expr.span;
+    if let ExpressionKind::Function {
+      args,
+      ret,
+      body: Some(body),
+      ..
+    } = &mut expr.kind
+    {
+      let scope = args
+        .iter()
+        .map(|(name, ty)| {
+          let item = Item {
+            ty: ty.clone(),
+            mutable: true,
+            span,
+            declared: false,
+          };
+          (name.clone(), item)
+        })
+        .collect();
+      self.scopes.push(scope);
+      let outer_ret = self.ret.replace(ret.clone());
+      match &mut body.kind {
+        ExpressionKind::Block { expressions } => {
+          for expr in expressions {
+            self.check(expr, None);
+          }
+        }
+        _ => {
+          self.check(body, None);
+        }
+      }
+      self.ret = outer_ret;
+      self.scopes.pop();
+    }
+  }
+
+  /// Works out the type of an expression and stores it on the expression.
+  /// Literals take on the `expected` type if they can, but it's up to the caller to check that the types match.
+  /// Returns `None` if the type can't be known, in which case the problem was already reported.
+  fn check(&mut self, expr: &mut Expression, expected: Option<&Type>) -> Option<Type> {
+    let ty = self.infer(expr, expected);
+    expr.ty = ty.clone();
+    ty
+  }
+
+  /// Checks that an expression can be stored as the given type, e.g. when it is assigned or passed to a function.
+  /// Integers are widened and converted to floats, and `f32` to `f64`, but never the other way around.
+  fn coerce(&mut self, expr: &mut Expression, expected: &Type) -> Option<Type> {
+    let found = self.check(expr, Some(expected))?;
+    if same_type(&found, expected) {
+      Some(found)
+    } else if converts_to(&found, expected) {
+      Some(expected.clone())
+    } else {
+      self.mismatch(expr.span, expected, &found);
+      None
+    }
+  }
+
+  fn mismatch(&mut self, span: Span, expected: &Type, found: &Type) {
+    self.diagnostics.push(
+      Diagnostic::error("mismatched types", span).with_label(format!(
+        "expected `{}`, found `{}`",
+        expected.as_string(),
+        found.as_string()
+      )),
+    );
+  }
+
+  fn check_condition(&mut self, expr: &mut Expression) {
+    if let Some(ty) = self.check(expr, Some(&Type::Bool)) {
+      if !is_condition(&ty) {
+        self.diagnostics.push(
+          Diagnostic::error(
+            format!("`{}` cannot be used as a condition", ty.as_string()),
+            expr.span,
+          )
+          .with_label("expected a `bool`, number, or pointer"),
+        );
+      }
+    }
+  }
+
+  fn infer(&mut self, expr: &mut Expression, expected: Option<&Type>) -> Option<Type> {
+    let span = expr.span;
+    match &mut expr.kind {
+      ExpressionKind::Number(value) => Some(self.check_number(value, expected, false, span)),
+      ExpressionKind::Decimal(_) => Some(match expected {
+        Some(ty @ Type::Float { .. }) => ty.clone(),
+        _ => Type::Float { bits: 64 },
+      }),
+      ExpressionKind::Char(_) => Some(Type::Char),
+      ExpressionKind::Bool(_) => Some(Type::Bool),
+      ExpressionKind::String(_) => Some(Type::String),
+      ExpressionKind::VariableRef(name) => self.lookup(name).map(|x| x.ty.clone()),
+      ExpressionKind::FuncCall(name, args) => self.check_call(name, args, span),
+      ExpressionKind::StructLiteral { name, fields } => {
+        self.check_struct_literal(name, fields, span)
+      }
+      ExpressionKind::FieldAccess { expr, field } => self.check_field(expr, field, span),
+      ExpressionKind::EnumVariant {
+        name,
+        variant,
+        args,
+      } => self.check_enum_variant(name, variant, args, span),
+      ExpressionKind::Match { value, arms } => self.check_match(value, arms, expected),
+      ExpressionKind::ArrayLiteral(elements) => self.check_array_literal(elements, expected, span),
+      ExpressionKind::Index { expr, index } => self.check_index(expr, index, span),
+      ExpressionKind::Cast { expr, ty } => self.check_cast(expr, ty, span),
+      ExpressionKind::VariableDeclaration { name, ty, mutable } => {
+        let item = Item {
+          ty: ty.clone(),
+          mutable: *mutable,
+          span,
+          declared: false,
+        };
+        self.define(name.clone(), item);
+        Some(ty.clone())
+      }
+      ExpressionKind::BinaryOperation { operator, lhs, rhs } => {
+        self.check_binary(operator, lhs, rhs, expected, span)
+      }
+      ExpressionKind::UnaryOperation { operator, expr, .. } => {
+        self.check_unary(operator, expr, expected, span)
+      }
+      ExpressionKind::Return(value) => {
+        self.check_return(value, span);
+        None
+      }
+      ExpressionKind::Block { expressions } => {
+        self.scopes.push(HashMap::new());
+        for expr in expressions {
+          self.check(expr, None);
+        }
+        self.scopes.pop();
+        None
+      }
+      ExpressionKind::For {
+        conditions, body, ..
+      } => {
+        self.scopes.push(HashMap::new());
+        let [init, condition, step] = conditions;
+        self.check(init, None);
+        self.check_condition(condition);
+        self.check(step, None);
+        self.check(body, None);
+        self.scopes.pop();
+        None
+      }
+      ExpressionKind::While {
+        condition, body, ..
+      } => {
+        self.check_condition(condition);
+        self.check(body, None);
+        None
+      }
+      ExpressionKind::If {
+        condition,
+        body,
+        else_body,
+      } => {
+        self.check_condition(condition);
+        self.check(body, None);
+        if let Some(else_body) = else_body {
+          self.check(else_body, None);
+        }
+        None
+      }
+      _ => None,
+    }
+  }
+
+  fn check_call(&mut self, name: &str, args: &mut [Expression], span: Span) -> Option<Type> {
+    let function = self.lookup(name)?.clone();
+    let (params, ret) = match function.ty {
+      Type::Function { args, ret } => (args, ret),
+      Type::Pointer(ty) => match *ty {
+        Type::Function { args, ret } => (args, ret),
+        _ => return None,
+      },
+      // The validator reports calls to things that aren't functions.
+      _ => return None,
+    };
+    if params.len() != args.len() {
+      self.diagnostics.push(
+        Diagnostic::error(
+          format!(
+            "`{}` takes {} but {} supplied",
+            name,
+            count(params.len(), "argument"),
+            if args.len() == 1 {
+              "1 was".to_owned()
+            } else {
+              format!("{} were", args.len())
+            }
+          ),
+          span,
+        )
+        .with_label(format!("expected {}", count(params.len(), "argument")))
+        .with_secondary(function.span, format!("`{}` defined here", name)),
+      );
+      for arg in args {
+        self.check(arg, None);
+      }
+    } else {
+      for (arg, param) in args.iter_mut().zip(&params) {
+        self.coerce(arg, param);
+      }
+    }
+    Some(*ret)
+  }
+
+  fn check_return(&mut self, value: &mut Option<Box<Expression>>, span: Span) {
+    let ret = match &self.ret {
+      Some(ret) => ret.clone(),
+      None => {
+        self.diagnostics.push(
+          Diagnostic::error("`return` outside of a function", span)
+            .with_label("can only be used inside of a function"),
+        );
+        return;
+      }
+    };
+    match value {
+      Some(value) if ret == Type::Void => {
+        self.check(value, None);
+        self.diagnostics.push(
+          Diagnostic::error("mismatched types", value.span)
+            .with_label("expected nothing, the function returns `void`"),
+        );
+      }
+      Some(value) => {
+        self.coerce(value, &ret);
+      }
+      None if ret != Type::Void => self.diagnostics.push(
+        Diagnostic::error("mismatched types", span)
+          .with_label(format!("expected a `{}` to be returned", ret.as_string())),
+      ),
+      None => {}
+    }
+  }
+
+  fn check_binary(
+    &mut self,
+    operator: &BinaryOperator,
+    lhs: &mut Expression,
+    rhs: &mut Expression,
+    expected: Option<&Type>,
+    span: Span,
+  ) -> Option<Type> {
+    let ty = match operator {
+      // The value is checked before the variable exists, in case it refers to a variable it shadows.
+      BinaryOperator::Assign => {
+        if let ExpressionKind::VariableDeclaration { ty, .. } = &lhs.kind {
+          let ty = ty.clone();
+          self.coerce(rhs, &ty);
+          self.check(lhs, None);
+          return Some(ty);
+        }
+        let ty = self.check_place(lhs)?;
+        self.coerce(rhs, &ty)?;
+        return Some(ty);
+      }
+      BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => {
+        self.check_condition(lhs);
+        self.check_condition(rhs);
+        return Some(Type::Bool);
+      }
+      _ if operator.base_operator().is_some() => {
+        let ty = self.check_place(lhs)?;
+        self.coerce(rhs, &ty)?
+      }
+      BinaryOperator::Eq
+      | BinaryOperator::Ne
+      | BinaryOperator::Gt
+      | BinaryOperator::Lt
+      | BinaryOperator::Ge
+      | BinaryOperator::Le => self.check_operands(lhs, rhs, None)?,
+      _ => self.check_operands(lhs, rhs, expected)?,
+    };
+    let applied = operator.base_operator().unwrap_or_else(|| operator.clone());
+    if !supports(&applied, &ty) {
+      self.diagnostics.push(
+        Diagnostic::error(
+          format!(
+            "binary operation `{}` cannot be applied to type `{}`",
+            operator.as_string(),
+            ty.as_string()
+          ),
+          span,
+        )
+        .with_label(format!("both operands are `{}`", ty.as_string())),
+      );
+      return None;
+    }
+    match operator {
+      BinaryOperator::Eq
+      | BinaryOperator::Ne
+      | BinaryOperator::Gt
+      | BinaryOperator::Lt
+      | BinaryOperator::Ge
+      | BinaryOperator::Le => Some(Type::Bool),
+      _ => Some(ty),
+    }
+  }
+
+  /// Checks the operands of a binary operation, returning the type they are both converted to.
+  /// The narrower operand is widened to the other's type, e.g. an `i32` is added to an `i64` as an `i64`.
+  fn check_operands(
+    &mut self,
+    lhs: &mut Expression,
+    rhs: &mut Expression,
+    expected: Option<&Type>,
+  ) -> Option<Type> {
+    // A literal takes on the type of the other operand, so `1 + x` works for any integer type `x`.
+    let (first, second) = if is_literal(lhs) && !is_literal(rhs) {
+      (rhs, lhs)
+    } else {
+      (lhs, rhs)
+    };
+    let first_ty = self.check(first, expected)?;
+    let second_ty = self.check(second, Some(&first_ty))?;
+    if converts_to(&second_ty, &first_ty) {
+      Some(first_ty)
+    } else if converts_to(&first_ty, &second_ty) {
+      Some(second_ty)
+    } else {
+      self.mismatch(second.span, &first_ty, &second_ty);
+      None
+    }
+  }
+
+  fn check_unary(
+    &mut self,
+    operator: &UnaryOperator,
+    operand: &mut Expression,
+    expected: Option<&Type>,
+    span: Span,
+  ) -> Option<Type> {
+    let ty = match operator {
+      UnaryOperator::Not => {
+        self.check_condition(operand);
+        return Some(Type::Bool);
+      }
+      UnaryOperator::Ref => {
+        let expected = match expected {
+          Some(Type::Pointer(ty)) => Some(&**ty),
+          _ => None,
+        };
+        let ty = self.check(operand, expected)?;
+        if !is_place(operand) {
+          self.diagnostics.push(
+            Diagnostic::error("cannot take the address of this expression", span).with_label(
+              "only variables, fields, elements, and dereferenced pointers have an address",
+            ),
+          );
+          return None;
+        }
+        return Some(Type::Pointer(Box::new(ty)));
+      }
+      UnaryOperator::Deref => match normalize(&self.check(operand, None)?) {
+        Type::Pointer(ty) if *ty != Type::Void => return Some(*ty),
+        ty => ty,
+      },
+      UnaryOperator::Increment | UnaryOperator::Decrement => {
+        let ty = self.check_place(operand)?;
+        if is_integer(&ty) || is_pointer(&ty) {
+          return Some(ty);
+        }
+        ty
+      }
+      UnaryOperator::Negation => {
+        let ty = match &operand.kind {
+          // The sign belongs to the literal, so that `-128` fits in an `i8`.
+          ExpressionKind::Number(value) => {
+            let ty = self.check_number(value, expected, true, span);
+            operand.ty = Some(ty.clone());
+            ty
+          }
+          _ => self.check(operand, expected)?,
+        };
+        if is_number(&ty) {
+          return Some(ty);
+        }
+        ty
+      }
+      UnaryOperator::BitNOT => {
+        let ty = self.check(operand, expected)?;
+        if is_integer(&ty) {
+          return Some(ty);
+        }
+        ty
+      }
+    };
+    self.diagnostics.push(
+      Diagnostic::error(
+        format!(
+          "unary operation `{}` cannot be applied to type `{}`",
+          operator.as_string(),
+          ty.as_string()
+        ),
+        span,
+      )
+      .with_label(format!("the operand is `{}`", ty.as_string())),
+    );
+    None
+  }
+
+  /// Works out the type of a number literal, reporting it if it doesn't fit in that type.
+  fn check_number(
+    &mut self,
+    value: &str,
+    expected: Option<&Type>,
+    negative: bool,
+    span: Span,
+  ) -> Type {
+    let ty = match expected {
+      Some(ty @ (Type::Int { .. } | Type::Float { .. } | Type::Char)) => ty.clone(),
+      // `0` doubles as the null pointer.
+      Some(ty @ (Type::Pointer(_) | Type::String)) if value == "0" && !negative => ty.clone(),
+      _ => Type::Int {
+        bits: 32,
+        signed: true,
+      },
+    };
+    if let Some((min, max)) = int_range(&ty) {
+      let limit = if negative { min.unsigned_abs() } else { max };
+      if !value.parse::<u128>().is_ok_and(|x| x <= limit) {
+        self.diagnostics.push(
+          Diagnostic::error(
+            format!("literal out of range for `{}`", ty.as_string()),
+            span,
+          )
+          .with_label(format!(
+            "`{}` ranges from {} to {}",
+            ty.as_string(),
+            min,
+            max
+          )),
+        );
+      }
+    }
+    ty
+  }
+
+  /// Checks an expression that is being assigned to, returning its type.
+  fn check_place(&mut self, expr: &mut Expression) -> Option<Type> {
+    let ty = self.check(expr, None);
+    if self.check_assignable(expr) {
+      ty
+    } else {
+      None
+    }
+  }
+
+  /// Reports it if an expression can't be assigned to, e.g. because it's a constant.
+  fn check_assignable(&mut self, expr: &Expression) -> bool {
+    match &expr.kind {
+      ExpressionKind::VariableRef(name) => {
+        if let Some(item) = self.lookup(name).filter(|x| !x.mutable).cloned() {
+          self.diagnostics.push(
+            Diagnostic::error(
+              format!("cannot assign to `{}`, which is not mutable", name),
+              expr.span,
+            )
+            .with_label("cannot be assigned to")
+            .with_secondary(item.span, format!("`{}` defined here", name)),
+          );
+          return false;
+        }
+        true
+      }
+      ExpressionKind::UnaryOperation {
+        operator: UnaryOperator::Deref,
+        ..
+      } => true,
+      // Fields and elements are assigned through a pointer, or as part of the value they belong to.
+      ExpressionKind::FieldAccess { expr, .. } | ExpressionKind::Index { expr, .. } => {
+        expr.ty.as_ref().is_some_and(is_pointer) || self.check_assignable(expr)
+      }
+      _ => {
+        self.diagnostics.push(
+          Diagnostic::error("invalid left-hand side of assignment", expr.span)
+            .with_label("cannot be assigned to"),
+        );
+        false
+      }
+    }
+  }
+
+  fn check_struct_literal(
+    &mut self,
+    name: &str,
+    fields: &mut [(String, Expression)],
+    span: Span,
+  ) -> Option<Type> {
+    let definition = self.struct_fields(name)?;
+    let mut initialized: Vec<String> = vec![];
+    for (field, value) in fields {
+      match definition.iter().find(|(x, _)| x == field) {
+        None => {
+          self.diagnostics.push(
+            Diagnostic::error(
+              format!("struct `{}` has no field named `{}`", name, field),
+              value.span,
+            )
+            .with_label("unknown field"),
+          );
+          self.check(value, None);
+        }
+        Some((_, ty)) => {
+          if initialized.contains(field) {
+            self.diagnostics.push(
+              Diagnostic::error(
+                format!("field `{}` specified more than once", field),
+                value.span,
+              )
+              .with_label("used more than once"),
+            );
+          }
+          self.coerce(value, ty);
+          initialized.push(field.clone());
+        }
+      }
+    }
+    let missing: Vec<String> = definition
+      .iter()
+      .filter(|(x, _)| !initialized.contains(x))
+      .map(|(x, _)| format!("`{}`", x))
+      .collect();
+    if !missing.is_empty() {
+      self.diagnostics.push(
+        Diagnostic::error(
+          format!(
+            "missing {} {} in initializer of `{}`",
+            if missing.len() == 1 {
+              "field"
+            } else {
+              "fields"
+            },
+            missing.join(", "),
+            name
+          ),
+          span,
+        )
+        .with_label(format!("missing {}", missing.join(", "))),
+      );
+    }
+    Some(Type::Named(name.to_string()))
+  }
+
+  fn check_field(&mut self, expr: &mut Expression, field: &str, span: Span) -> Option<Type> {
+    let ty = self.check(expr, None)?;
+    // Fields are accessed through pointers to structs as well.
+    let name = match &ty {
+      Type::Named(name) => name,
+      Type::Pointer(ty) => match &**ty {
+        Type::Named(name) => name,
+        _ => "",
+      },
+      _ => "",
+    };
+    let found = self
+      .struct_fields(name)
+      .and_then(|fields| fields.into_iter().find(|(x, _)| x == field));
+    if found.is_none() {
+      self.diagnostics.push(
+        Diagnostic::error(
+          format!("no field `{}` on type `{}`", field, ty.as_string()),
+          span,
+        )
+        .with_label("unknown field"),
+      );
+    }
+    found.map(|(_, ty)| ty)
+  }
+
+  /// Checks the elements of an array literal, which all have the element type of the expected array.
+  /// Without an expected type, the first element decides the type of the others.
+  fn check_array_literal(
+    &mut self,
+    elements: &mut [Expression],
+    expected: Option<&Type>,
+    span: Span,
+  ) -> Option<Type> {
+    let (mut element_ty, len) = match expected {
+      Some(Type::Array(ty, len)) => (Some((**ty).clone()), *len),
+      _ => (None, None),
+    };
+    let mut valid = true;
+    if let Some(len) = len.filter(|x| *x as usize != elements.len()) {
+      self.diagnostics.push(
+        Diagnostic::error("mismatched types", span).with_label(format!(
+          "expected an array of {}, found {}",
+          count(len as usize, "element"),
+          count(elements.len(), "element")
+        )),
+      );
+      valid = false;
+    }
+    for element in elements.iter_mut() {
+      match &element_ty {
+        Some(ty) => valid &= self.coerce(element, ty).is_some(),
+        None => {
+          element_ty = self.check(element, None);
+          valid &= element_ty.is_some();
+        }
+      }
+    }
+    if elements.is_empty() && element_ty.is_none() {
+      self.diagnostics.push(
+        Diagnostic::error("cannot infer the type of an empty array", span)
+          .with_label("the element type is unknown")
+          .with_note("give the array a type, e.g. `let a: i32[0] = [];`"),
+      );
+    }
+    match element_ty {
+      Some(ty) if valid => Some(Type::Array(Box::new(ty), Some(elements.len() as u32))),
+      _ => None,
+    }
+  }
+
+  /// Checks indexing into an array, or into the elements a pointer points to, returning the element type.
+  fn check_index(
+    &mut self,
+    expr: &mut Expression,
+    index: &mut Expression,
+    span: Span,
+  ) -> Option<Type> {
+    let ty = self.check(expr, None);
+    let index_ty = self.check(
+      index,
+      Some(&Type::Int {
+        bits: 64,
+        signed: true,
+      }),
+    );
+    if let Some(index_ty) = index_ty.filter(|x| !is_integer(x)) {
+      self.diagnostics.push(
+        Diagnostic::error(
+          format!(
+            "cannot index with a value of type `{}`",
+            index_ty.as_string()
+          ),
+          index.span,
+        )
+        .with_label("expected an integer"),
+      );
+    }
+    let ty = ty?;
+    let element = match &ty {
+      Type::Array(element, _) => (**element).clone(),
+      Type::Pointer(element) if **element != Type::Void => (**element).clone(),
+      Type::String => Type::Char,
+      _ => {
+        self.diagnostics.push(
+          Diagnostic::error(
+            format!("cannot index into a value of type `{}`", ty.as_string()),
+            span,
+          )
+          .with_label("expected an array or a pointer"),
+        );
+        return None;
+      }
+    };
+    // Constant indices are known to be out of bounds before the program runs.
+    if let (Type::Array(_, Some(len)), ExpressionKind::Number(value)) = (&ty, &index.kind) {
+      if value.parse::<u128>().is_ok_and(|x| x >= *len as u128) {
+        self.diagnostics.push(
+          Diagnostic::error(
+            format!(
+              "index out of bounds: the length is {} but the index is {}",
+              len, value
+            ),
+            index.span,
+          )
+          .with_label("out of bounds"),
+        );
+      }
+    }
+    Some(element)
+  }
+
+  /// Checks an `as` cast, which is the type it casts to even when the cast is invalid.
+  fn check_cast(&mut self, expr: &mut Expression, ty: &Type, span: Span) -> Option<Type> {
+    let found = self.check(expr, Some(ty))?;
+    // Enums whose variants hold no values are cast through their discriminant, like C enums.
+    let c_like = match &found {
+      Type::Named(name) => self
+        .enum_variants(name)
+        .is_some_and(|variants| variants.iter().all(|x| x.fields.is_empty())),
+      _ => false,
+    };
+    if !(casts_to(&found, ty) || c_like && is_integer(ty)) {
+      let mut diagnostic = Diagnostic::error(
+        format!(
+          "cannot cast `{}` as `{}`",
+          found.as_string(),
+          ty.as_string()
+        ),
+        span,
+      )
+      .with_label("only numbers, pointers and C-like enums can be cast");
+      if *ty == Type::Bool && is_number(&found) {
+        diagnostic = diagnostic.with_note("compare with zero instead, e.g. `x != 0`");
+      }
+      self.diagnostics.push(diagnostic);
+    }
+    Some(ty.clone())
+  }
+
+  fn check_enum_variant(
+    &mut self,
+    name: &str,
+    variant: &str,
+    args: &mut [Expression],
+    span: Span,
+  ) -> Option<Type> {
+    let fields = self.variant_fields(name, variant, span);
+    match fields {
+      Some(fields) if fields.len() == args.len() => {
+        for (arg, ty) in args.iter_mut().zip(&fields) {
+          self.coerce(arg, ty);
+        }
+      }
+      _ => {
+        if let Some(fields) = fields {
+          self.diagnostics.push(
+            Diagnostic::error(
+              format!(
+                "`{}::{}` holds {} but {} supplied",
+                name,
+                variant,
+                count(fields.len(), "value"),
+                if args.len() == 1 {
+                  "1 was".to_owned()
+                } else {
+                  format!("{} were", args.len())
+                }
+              ),
+              span,
+            )
+            .with_label(format!("expected {}", count(fields.len(), "value"))),
+          );
+        }
+        for arg in args {
+          self.check(arg, None);
+        }
+      }
+    }
+    Some(Type::Named(name.to_string()))
+  }
+
+  /// Checks a `match`, whose type is the type of its arms when they all have a value, and `void` otherwise.
+  fn check_match(
+    &mut self,
+    value: &mut Expression,
+    arms: &mut [MatchArm],
+    expected: Option<&Type>,
+  ) -> Option<Type> {
+    let mut ty = self.check(value, None);
+    if let Some(found) = &ty {
+      let is_enum = matches!(found, Type::Named(name) if self.enum_variants(name).is_some());
+      if !is_integer(found) && *found != Type::Bool && !is_enum {
+        self.diagnostics.push(
+          Diagnostic::error(
+            format!("cannot match on a value of type `{}`", found.as_string()),
+            value.span,
+          )
+          .with_label("expected an integer, char, bool, or enum"),
+        );
+        ty = None;
+      }
+    }
+    // The patterns matched so far, to find the arms that can never be reached.
+    let mut covered: Vec<String> = vec![];
+    let mut result: Option<Type> = None;
+    let mut has_value = true;
+    let mut valid = true;
+    for arm in arms.iter_mut() {
+      self.scopes.push(HashMap::new());
+      let pattern = self.check_pattern(&mut arm.pattern, ty.as_ref(), arm.span);
+      if covered.iter().any(|x| *x == pattern || x == "_") {
+        self.diagnostics.push(
+          Diagnostic::warning("unreachable pattern", arm.span)
+            .with_label("no value can reach this arm"),
+        );
+      }
+      covered.push(pattern);
+      match &arm.body.kind {
+        // Arms that leave the match don't need a value.
+        ExpressionKind::Return(_) | ExpressionKind::Break(_) | ExpressionKind::Continue(_) => {
+          self.check(&mut arm.body, None);
+        }
+        ExpressionKind::Block { .. }
+        | ExpressionKind::If { .. }
+        | ExpressionKind::While { .. }
+        | ExpressionKind::For { .. } => {
+          self.check(&mut arm.body, None);
+          has_value = false;
+        }
+        _ => match self.check(&mut arm.body, expected) {
+          Some(Type::Void) => has_value = false,
+          Some(found) => {
+            result = Some(match result {
+              None => found,
+              Some(ty) if converts_to(&found, &ty) => ty,
+              Some(ty) if converts_to(&ty, &found) => found,
+              // Arms of unrelated types can still be run for their effects.
+              Some(ty) => {
+                has_value = false;
+                ty
+              }
+            });
+          }
+          None => valid = false,
+        },
+      }
+      self.scopes.pop();
+    }
+    if let Some(ty) = ty.filter(|_| !covered.iter().any(|x| x == "_")) {
+      let missing: Vec<String> = match &ty {
+        Type::Bool => vec!["true".to_owned(), "false".to_owned()],
+        Type::Named(name) => self
+          .enum_variants(name)
+          .unwrap_or_default()
+          .iter()
+          .map(|x| format!("{}::{}", name, x.name))
+          .collect(),
+        _ => vec!["_".to_owned()],
+      }
+      .into_iter()
+      .filter(|x| !covered.contains(x))
+      .map(|x| format!("`{}`", x))
+      .collect();
+      if !missing.is_empty() {
+        self.diagnostics.push(
+          Diagnostic::error(
+            format!(
+              "non-exhaustive patterns: {} not covered",
+              missing.join(", ")
+            ),
+            value.span,
+          )
+          .with_label(format!(
+            "{} not covered",
+            if missing.len() == 1 {
+              "pattern"
+            } else {
+              "patterns"
+            }
+          ))
+          .with_note("add an arm for each of them, or a `_` arm to match anything else"),
+        );
+      }
+    }
+    if !valid {
+      return None;
+    }
+    match result {
+      Some(ty) if has_value => Some(ty),
+      _ => Some(Type::Void),
+    }
+  }
+
+  /// Checks that a pattern can match a value of type `ty`, defining the names it binds in the current scope.
+  /// Returns the pattern as a string, which is the same for patterns that match the same values.
+  fn check_pattern(&mut self, pattern: &mut Pattern, ty: Option<&Type>, span: Span) -> String {
+    match pattern {
+      Pattern::Wildcard => "_".to_owned(),
+      Pattern::Literal(literal) => {
+        match ty {
+          Some(ty) => self.coerce(literal, ty),
+          None => self.check(literal, None),
+        };
+        literal.as_string()
+      }
+      Pattern::Variant {
+        name,
+        variant,
+        bindings,
+      } => {
+        let enum_type = Type::Named(name.clone());
+        match ty {
+          Some(ty) if *ty != enum_type && self.enum_variants(name).is_some() => {
+            self.mismatch(span, ty, &enum_type)
+          }
+          _ => {}
+        }
+        if let Some(fields) = self.variant_fields(name, variant, span) {
+          if fields.len() != bindings.len() {
+            self.diagnostics.push(
+              Diagnostic::error(
+                format!(
+                  "this pattern has {}, but `{}::{}` has {}",
+                  count(bindings.len(), "field"),
+                  name,
+                  variant,
+                  count(fields.len(), "field")
+                ),
+                span,
+              )
+              .with_label(format!("expected {}", count(fields.len(), "field"))),
+            );
+          }
+          for (binding, ty) in bindings.iter().zip(fields) {
+            let item = Item {
+              ty,
+              mutable: false,
+              span,
+              declared: false,
+            };
+            self.define(binding.clone(), item);
+          }
+        }
+        format!("{}::{}", name, variant)
+      }
+    }
+  }
+
+  /// Gets the types of the values held by a variant of an enum, reporting it if the enum has no such variant.
+  fn variant_fields(&mut self, name: &str, variant: &str, span: Span) -> Option<Vec<Type>> {
+    // Names that aren't enums were already reported by the validator.
+    let variants = self.enum_variants(name)?;
+    match variants.into_iter().find(|x| x.name == variant) {
+      Some(variant) => Some(variant.fields),
+      None => {
+        self.diagnostics.push(
+          Diagnostic::error(
+            format!("no variant named `{}` on enum `{}`", variant, name),
+            span,
+          )
+          .with_label("variant not found"),
+        );
+        None
+      }
+    }
+  }
+
+  /// Gets the variants of the enum with the given name.
+  fn enum_variants(&self, name: &str) -> Option<Vec<Variant>> {
+    match self.lookup(name) {
+      Some(Item {
+        ty: Type::Enum { variants, .. },
+        ..
+      }) => Some(variants.clone()),
+      _ => None,
+    }
+  }
+
+  /// Gets the fields of the struct with the given name.
+  fn struct_fields(&self, name: &str) -> Option<Vec<(String, Type)>> {
+    match self.lookup(name) {
+      Some(Item {
+        ty: Type::Struct { fields, .. },
+        ..
+      }) => Some(fields.clone()),
+      _ => None,
+    }
+  }
+
+  fn lookup(&self, name: &str) -> Option<&Item> {
+    self.scopes.iter().rev().find_map(|scope| scope.get(name))
+  }
+
+  fn define(&mut self, name: String, item: Item) {
+    if let Some(scope) = self.scopes.last_mut() {
+      scope.insert(name, item);
+    }
+  }
+}
+
+/// Formats a count of something, e.g. `1 argument` or `2 arguments`.
+fn count(n: usize, noun: &str) -> String {
+  format!("{} {}{}", n, noun, if n == 1 { "" } else { "s" })
+}
+
+/// Whether an expression is a number literal, whose type depends on where it's used.
+fn is_literal(expr: &Expression) -> bool {
+  match &expr.kind {
+    ExpressionKind::Number(_) | ExpressionKind::Decimal(_) => true,
+    ExpressionKind::UnaryOperation {
+      operator: UnaryOperator::Negation,
+      expr,
+      ..
+    } => is_literal(expr),
+    _ => false,
+  }
+}
+
+/// Whether an expression can be evaluated at compile time, which is needed to initialize a global.
+fn is_constant(expr: &Expression) -> bool {
+  match &expr.kind {
+    ExpressionKind::Number(_)
+    | ExpressionKind::Decimal(_)
+    | ExpressionKind::Char(_)
+    | ExpressionKind::Bool(_)
+    | ExpressionKind::String(_) => true,
+    ExpressionKind::UnaryOperation {
+      operator: UnaryOperator::Negation | UnaryOperator::Not | UnaryOperator::BitNOT,
+      expr,
+      ..
+    }
+    | ExpressionKind::Cast { expr, .. } => is_constant(expr),
+    // `&&` and `||` only evaluate their right-hand side when they need to, which takes branching.
+    ExpressionKind::BinaryOperation { operator, lhs, rhs } => {
+      !matches!(
+        operator,
+        BinaryOperator::Assign | BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr
+      ) && operator.base_operator().is_none()
+        && is_constant(lhs)
+        && is_constant(rhs)
+    }
+    ExpressionKind::ArrayLiteral(elements) => elements.iter().all(is_constant),
+    ExpressionKind::StructLiteral { fields, .. } => fields.iter().all(|(_, x)| is_constant(x)),
+    ExpressionKind::EnumVariant { args, .. } => args.is_empty(),
+    _ => false,
+  }
+}
+
+/// Whether an expression refers to a location in memory, which can be assigned to or referenced.
+fn is_place(expr: &Expression) -> bool {
+  match &expr.kind {
+    ExpressionKind::VariableRef(_)
+    | ExpressionKind::UnaryOperation {
+      operator: UnaryOperator::Deref,
+      ..
+    } => true,
+    ExpressionKind::FieldAccess { expr, .. } | ExpressionKind::Index { expr, .. } => {
+      is_place(expr) || expr.ty.as_ref().is_some_and(is_pointer)
+    }
+    _ => false,
+  }
+}
+
+/// Gets the canonical form of a type, in which types that are the same value to LLVM are equal.
+/// e.g. `string` is the same as `char*`, and `i32[]` as `i32*`.
+fn normalize(ty: &Type) -> Type {
+  match ty {
+    Type::String => Type::Pointer(Box::new(Type::Char)),
+    Type::Pointer(ty) | Type::Array(ty, None) => Type::Pointer(Box::new(normalize(ty))),
+    Type::Array(ty, Some(len)) => Type::Array(Box::new(normalize(ty)), Some(*len)),
+    Type::Function { args, ret } => Type::Function {
+      args: args.iter().map(normalize).collect(),
+      ret: Box::new(normalize(ret)),
+    },
+    _ => ty.clone(),
+  }
+}
+
+fn same_type(a: &Type, b: &Type) -> bool {
+  normalize(a) == normalize(b)
+}
+
+/// Whether a value of type `from` can be implicitly converted to `to`.
+fn converts_to(from: &Type, to: &Type) -> bool {
+  if same_type(from, to) {
+    return true;
+  }
+  match (from, to) {
+    (Type::Float { bits: from }, Type::Float { bits: to }) => from < to,
+    (from, Type::Float { .. }) => is_integer(from),
+    // Integers widen when every value fits, so a signed integer never becomes an unsigned one.
+    (
+      Type::Int {
+        bits: from,
+        signed: from_signed,
+      },
+      Type::Int { bits: to, signed },
+    ) => from < to && (*signed || !from_signed),
+    _ => false,
+  }
+}
+
+/// Gets the smallest and largest values of an integer type.
+fn int_range(ty: &Type) -> Option<(i128, u128)> {
+  match ty {
+    Type::Int { bits, signed: true } => Some((
+      i128::MIN >> (128 - bits),
+      (i128::MAX >> (128 - bits)) as u128,
+    )),
+    Type::Int {
+      bits,
+      signed: false,
+    } => Some((0, u128::MAX >> (128 - bits))),
+    // Chars are bytes, which C treats as either signed or unsigned.
+    Type::Char => Some((i8::MIN as i128, u8::MAX as u128)),
+    _ => None,
+  }
+}
+
+/// Whether a value of type `from` can be explicitly cast to `to`, which also allows conversions that lose information.
+fn casts_to(from: &Type, to: &Type) -> bool {
+  if converts_to(from, to) {
+    return true;
+  }
+  match (from, to) {
+    (from, to) if is_number(from) => is_number(to) || (is_pointer(to) && is_int(from)),
+    (Type::Bool, to) => is_integer(to),
+    // Pointers can be reinterpreted as any other pointer, or as their address.
+    (from, to) if is_pointer(from) => is_pointer(to) || is_int(to),
+    _ => false,
+  }
+}
+
+fn is_int(ty: &Type) -> bool {
+  matches!(ty, Type::Int { .. })
+}
+
+fn is_integer(ty: &Type) -> bool {
+  matches!(ty, Type::Int { .. } | Type::Char)
+}
+
+fn is_number(ty: &Type) -> bool {
+  is_integer(ty) || matches!(ty, Type::Float { .. })
+}
+
+fn is_pointer(ty: &Type) -> bool {
+  matches!(normalize(ty), Type::Pointer(_))
+}
+
+fn is_condition(ty: &Type) -> bool {
+  *ty == Type::Bool || is_integer(ty) || is_pointer(ty)
+}
+
+/// Whether a binary operator can be applied to two operands of the given type.
+fn supports(operator: &BinaryOperator, ty: &Type) -> bool {
+  match operator {
+    BinaryOperator::Add
+    | BinaryOperator::Subtract
+    | BinaryOperator::Multiply
+    | BinaryOperator::Divide
+    | BinaryOperator::Modulo => is_number(ty),
+    BinaryOperator::BitAND | BinaryOperator::BitOR | BinaryOperator::BitXOR => {
+      is_integer(ty) || *ty == Type::Bool
+    }
+    BinaryOperator::LeftShift | BinaryOperator::RightShift => is_integer(ty),
+    BinaryOperator::Eq | BinaryOperator::Ne => !matches!(
+      ty,
+      Type::Void | Type::Array(..) | Type::Named(_) | Type::Struct { .. } | Type::Enum { .. }
+    ),
+    BinaryOperator::Gt | BinaryOperator::Lt | BinaryOperator::Ge | BinaryOperator::Le => {
+      is_number(ty) || is_pointer(ty)
+    }
+    _ => true,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::parser::validator::{collect_exports, Validator};
+
+  /// Validates and type checks `source` as the only file of a program.
+  fn check(source: &str) -> (SourceFile, Vec<String>) {
+    let mut files = HashMap::new();
+    files.insert(
+      "main.plume".to_owned(),
+      SourceFile::from_source("main.plume", 0, source.to_owned()),
+    );
+    let exports = collect_exports(&files);
+    let mut file = files.remove("main.plume").unwrap();
+    assert_eq!(file.diagnostics, vec![]);
+    assert_eq!(Validator::new(&file, &exports).validate(), vec![]);
+    let messages = TypeChecker::new(&exports)
+      .check_file(&mut file)
+      .into_iter()
+      .map(|x| format!("{}: {}", x.message, x.primary.message))
+      .collect();
+    (file, messages)
+  }
+
+  /// Gets the body of the first function in the file.
+  fn body(file: &SourceFile) -> &[Expression] {
+    match &file.expressions[0].kind {
+      ExpressionKind::Function {
+        body: Some(body), ..
+      } => match &body.kind {
+        ExpressionKind::Block { expressions } => expressions,
+        kind => panic!("expected a block, found {:?}", kind),
+      },
+      kind => panic!("expected a function, found {:?}", kind),
+    }
+  }
+
+  #[test]
+  fn literals() {
+    let (file, errors) = check(
+      "function main(a: u64, p: char*) -> bool {
+        a = 1 + a;
+        let x: f32 = 1;
+        return p != 0 && *p == 'a';
+      }",
+    );
+    assert_eq!(errors, Vec::<String>::new());
+    let u64 = Type::Int {
+      bits: 64,
+      signed: false,
+    };
+    let body = body(&file);
+    match &body[0].kind {
+      ExpressionKind::BinaryOperation { rhs, .. } => match &rhs.kind {
+        ExpressionKind::BinaryOperation { lhs, .. } => assert_eq!(lhs.ty, Some(u64.clone())),
+        kind => panic!("expected an addition, found {:?}", kind),
+      },
+      kind => panic!("expected an assignment, found {:?}", kind),
+    }
+    assert_eq!(body[0].ty, Some(u64));
+    match &body[1].kind {
+      ExpressionKind::BinaryOperation { rhs, .. } => {
+        assert_eq!(rhs.ty, Some(Type::Float { bits: 32 }))
+      }
+      kind => panic!("expected an assignment, found {:?}", kind),
+    }
+  }
+
+  #[test]
+  fn calls() {
+    let (_, errors) = check(
+      "declare function putchar(ch: char) -> i32;
+      function main() -> i32 {
+        putchar(10);
+        putchar('a', 'b');
+        putchar(true);
+        let x: i16 = putchar('c');
+        return;
+      }",
+    );
+    assert_eq!(
+      errors,
+      vec![
+        "`putchar` takes 1 argument but 2 were supplied: expected 1 argument",
+        "mismatched types: expected `char`, found `bool`",
+        "mismatched types: expected `i16`, found `i32`",
+        "mismatched types: expected a `i32` to be returned",
+      ]
+    );
+  }
+
+  #[test]
+  fn operators() {
+    let (_, errors) = check(
+      "function main(a: i32, b: bool, s: string) {
+        const c: i32 = 1;
+        a + b;
+        b + b;
+        s << s;
+        -b;
+        *a;
+        &(a + 1);
+        c = 2;
+        1 = a;
+        if (s) { a *= 2; }
+        return a;
+      }",
+    );
+    assert_eq!(
+      errors,
+      vec![
+        "mismatched types: expected `i32`, found `bool`",
+        "binary operation `+` cannot be applied to type `bool`: both operands are `bool`",
+        "binary operation `<<` cannot be applied to type `string`: both operands are `string`",
+        "unary operation `-` cannot be applied to type `bool`: the operand is `bool`",
+        "unary operation `*` cannot be applied to type `i32`: the operand is `i32`",
+        "cannot take the address of this expression: only variables, fields, elements, and dereferenced pointers have an address",
+        "cannot assign to `c`, which is not mutable: cannot be assigned to",
+        "invalid left-hand side of assignment: cannot be assigned to",
+        "mismatched types: expected nothing, the function returns `void`",
+      ]
+    );
+  }
+
+  #[test]
+  fn conversions() {
+    let (file, errors) = check(
+      "function main(i: i32, f: f32) -> f64 {
+        let d: f64 = i;
+        d = f;
+        f = d;
+        i = f;
+        i + d;
+        return f;
+      }",
+    );
+    assert_eq!(
+      errors,
+      vec![
+        "mismatched types: expected `f32`, found `f64`",
+        "mismatched types: expected `i32`, found `f32`",
+      ]
+    );
+    // The value keeps its own type, and is converted when it's stored.
+    match &body(&file)[0].kind {
+      ExpressionKind::BinaryOperation { rhs, .. } => {
+        assert_eq!(
+          rhs.ty,
+          Some(Type::Int {
+            bits: 32,
+            signed: true
+          })
+        )
+      }
+      kind => panic!("expected an assignment, found {:?}", kind),
+    }
+  }
+
+  #[test]
+  fn integers() {
+    let (_, errors) = check(
+      "function main(a: u8, b: i16, c: i64) -> i64 {
+        let x: i8 = -128;
+        let y: i8 = 128;
+        let z: u32 = -1;
+        let w: u128 = 340282366920938463463374607431768211455;
+        b = a;
+        a = b;
+        c = b;
+        a + b;
+        c + 1;
+        let u: u64 = a;
+        u + b;
+        return b;
+      }",
+    );
+    assert_eq!(
+      errors,
+      vec![
+        "literal out of range for `i8`: `i8` ranges from -128 to 127",
+        "literal out of range for `u32`: `u32` ranges from 0 to 4294967295",
+        "mismatched types: expected `u8`, found `i16`",
+        "mismatched types: expected `u64`, found `i16`",
+      ]
+    );
+  }
+
+  #[test]
+  fn structs() {
+    let (_, errors) = check(
+      "struct Point { x: i32, y: i32 }
+      function main(p: Point*, q: Point) -> i64 {
+        const c: Point = Point { x: 1, y: 2 };
+        let a: Point = Point { x: 1, y: 2, z: 3 };
+        a = Point { x: 1, y: 2, x: 3 };
+        a = Point { x: 1 };
+        p.x = 3;
+        (*p).y += 1;
+        a.x = 'a';
+        c.x = 4;
+        q.x = 5;
+        c.x;
+        &c.y;
+        a.w;
+        a == c;
+        return p.x;
+      }",
+    );
+    assert_eq!(
+      errors,
+      vec![
+        "struct `Point` has no field named `z`: unknown field",
+        "field `x` specified more than once: used more than once",
+        "missing field `y` in initializer of `Point`: missing `y`",
+        "mismatched types: expected `i32`, found `char`",
+        "cannot assign to `c`, which is not mutable: cannot be assigned to",
+        "no field `w` on type `Point`: unknown field",
+        "binary operation `==` cannot be applied to type `Point`: both operands are `Point`",
+      ]
+    );
+  }
+
+  #[test]
+  fn arrays() {
+    let (file, errors) = check(
+      "function main(p: i32*, s: string, f: f32) -> i64 {
+        let a: i64[3] = [1, 2, 3];
+        const c: i8[2] = [1, 2];
+        let big: i8[2] = [1, 200];
+        a = [1, 2];
+        let inferred: i64 = [1, 2][0] + p[1];
+        let ch: char = s[0];
+        a[0] = p[0];
+        c[1] = 3;
+        a[3];
+        a[f];
+        f[0];
+        [];
+        &a[1];
+        return a[2];
+      }",
+    );
+    assert_eq!(
+      errors,
+      vec![
+        "literal out of range for `i8`: `i8` ranges from -128 to 127",
+        "mismatched types: expected an array of 3 elements, found 2 elements",
+        "cannot assign to `c`, which is not mutable: cannot be assigned to",
+        "index out of bounds: the length is 3 but the index is 3: out of bounds",
+        "cannot index with a value of type `f32`: expected an integer",
+        "cannot index into a value of type `f32`: expected an array or a pointer",
+        "cannot infer the type of an empty array: the element type is unknown",
+      ]
+    );
+    match &body(&file)[0].kind {
+      ExpressionKind::BinaryOperation { rhs, .. } => assert_eq!(
+        rhs.ty,
+        Some(Type::Array(
+          Box::new(Type::Int {
+            bits: 64,
+            signed: true
+          }),
+          Some(3)
+        ))
+      ),
+      kind => panic!("expected an assignment, found {:?}", kind),
+    }
+  }
+
+  #[test]
+  fn enums_and_match() {
+    let (file, errors) = check(
+      "function main(s: Shape, c: char) -> f64 {
+        let circle: Shape = Shape::Circle(1);
+        Shape::Rect(1.0);
+        Shape::Square;
+        match (s) {
+          Shape::Circle(r) => r,
+          Shape::Rect(w) => w,
+        };
+        match (c) {
+          'a' => 1,
+          _ => 2,
+          'b' => 3,
+        };
+        match (1.5) {
+          _ => 0,
+        };
+        return match (s) {
+          Shape::Circle(r) => r,
+          Shape::Rect(w, h) => w * h,
+          Shape::Empty => 0,
+        };
+      }
+      enum Shape { Circle(f64), Rect(f64, f64), Empty }",
+    );
+    assert_eq!(
+      errors,
+      vec![
+        "`Shape::Rect` holds 2 values but 1 was supplied: expected 2 values",
+        "no variant named `Square` on enum `Shape`: variant not found",
+        "this pattern has 1 field, but `Shape::Rect` has 2 fields: expected 2 fields",
+        "non-exhaustive patterns: `Shape::Empty` not covered: pattern not covered",
+        "unreachable pattern: no value can reach this arm",
+        "cannot match on a value of type `f64`: expected an integer, char, bool, or enum",
+      ]
+    );
+    match &body(&file)[6].kind {
+      ExpressionKind::Return(Some(value)) => assert_eq!(value.ty, Some(Type::Float { bits: 64 })),
+      kind => panic!("expected a return, found {:?}", kind),
+    }
+  }
+
+  #[test]
+  fn casts() {
+    let (file, errors) = check(
+      "function main(f: f64, p: Point*, s: Shape, c: Color) -> u8 {
+        let i: i64 = f as i32 + p as i64;
+        let b: i8* = 0 as i8*;
+        300 as u8;
+        -1 as u32;
+        f as bool;
+        *p as i32;
+        s as i32;
+        f as i8*;
+        c as u8;
+        true as i32;
+        return f as u8;
+      }
+      struct Point { x: i32 }
+      enum Shape { Circle(f64), Empty }
+      enum Color { Red, Green }",
+    );
+    assert_eq!(
+      errors,
+      vec![
+        "literal out of range for `u8`: `u8` ranges from 0 to 255",
+        "literal out of range for `u32`: `u32` ranges from 0 to 4294967295",
+        "cannot cast `f64` as `bool`: only numbers, pointers and C-like enums can be cast",
+        "cannot cast `Point` as `i32`: only numbers, pointers and C-like enums can be cast",
+        "cannot cast `Shape` as `i32`: only numbers, pointers and C-like enums can be cast",
+        "cannot cast `f64` as `i8*`: only numbers, pointers and C-like enums can be cast",
+      ]
+    );
+    match &body(&file)[0].kind {
+      ExpressionKind::BinaryOperation { rhs, .. } => assert_eq!(
+        rhs.ty,
+        Some(Type::Int {
+          bits: 64,
+          signed: true
+        })
+      ),
+      kind => panic!("expected an assignment, found {:?}", kind),
+    }
+  }
+
+  #[test]
+  fn globals() {
+    let (_, errors) = check(
+      "function main() -> i32 {
+        count += 1;
+        LIMIT = 2;
+        return count;
+      }
+      const LIMIT: i64 = -(1 << 10) as i64;
+      let count: i32 = 1 + 2 * 3;
+      let origin: Point = Point { x: 1.5 as i32, y: ~0 };
+      let copy: i32 = count;
+      let called: i32 = main();
+      let flag: bool = true && false;
+      let empty: Shape = Shape::Empty;
+      let circle: Shape = Shape::Circle(1.0);
+      struct Point { x: i32, y: i32 }
+      enum Shape { Circle(f64), Empty }",
+    );
+    assert_eq!(
+      errors,
+      vec![
+        "cannot assign to `LIMIT`, which is not mutable: cannot be assigned to",
+        "global initializer is not a constant expression: this is only known when the program runs",
+        "global initializer is not a constant expression: this is only known when the program runs",
+        "global initializer is not a constant expression: this is only known when the program runs",
+        "global initializer is not a constant expression: this is only known when the program runs",
+      ]
+    );
+  }
+}
@@ -3,15 +3,16 @@ use crate::ast::*;
 impl Expression {
   /// Converts the expression to a string.
   pub fn as_string(&self) -> String {
-    match self {
-      Expression::VariableRef(s) => format!("{}", s),
-      Expression::String(s) => format!("\"{}\"", s),
-      Expression::Char(s) => format!("'{}'", s),
-      Expression::Comment(s) if s.contains("\n") => format!("/* {} */", s),
-      Expression::Comment(s) => format!("// {}", s),
-      Expression::Bool(b) => format!("{}", b),
-      Expression::Declare(b) => format!("declare {}", b.as_string()),
-      Expression::FuncCall(name, args) => format!(
+    match &self.kind {
+      ExpressionKind::Error => "/* error */".to_string(),
+      ExpressionKind::VariableRef(s) => s.to_string(),
+      ExpressionKind::String(s) => format!("\"{}\"", escape(s, '"')),
+      ExpressionKind::Char(s) => format!("'{}'", escape(&s.to_string(), '\'')),
+      ExpressionKind::Comment(s) if s.contains("\n") => format!("/* {} */", s),
+      ExpressionKind::Comment(s) => format!("// {}", s),
+      ExpressionKind::Bool(b) => format!("{}", b),
+      ExpressionKind::Declare(b) => format!("declare {}", b.as_string()),
+      ExpressionKind::FuncCall(name, args) => format!(
         "{}({})",
         name,
         args
@@ -20,7 +21,7 @@ impl Expression {
           .collect::<Vec<String>>()
           .join(", ")
       ),
-      Expression::Number(s) | Expression::Decimal(s) => {
+      ExpressionKind::Number(s) | ExpressionKind::Decimal(s) => {
         let mut pre_decimal = String::new();
         let decimal_parts: Vec<&str> = s.split(".").collect();
         let enumerated = decimal_parts[0].chars().rev().enumerate();
@@ -36,7 +37,7 @@ impl Expression {
           format!("{}.{}", pre_decimal, decimal_parts[1])
         }
       }
-      Expression::Block { expressions } => {
+      ExpressionKind::Block { expressions } => {
         format!(
           "{{{}}}",
           expressions
@@ -46,7 +47,7 @@ impl Expression {
             .join("\n")
         )
       }
-      Expression::Function {
+      ExpressionKind::Function {
         name,
         ret,
         args,
@@ -55,96 +56,352 @@ impl Expression {
         let body_str = if let Some(bod) = body {
           format!(" {{{}}}", bod.as_string())
         } else {
-          format!(";")
+          ";".to_string()
         };
         format!(
           "function {}({}) -> {}{}",
           name,
           args
             .iter()
-            .map(|(arg_name, arg_type)| format!("{}: {}", arg_name, arg_type))
+            .map(|(arg_name, arg_type)| format!("{}: {}", arg_name, arg_type.as_string()))
             .collect::<Vec<String>>()
             .join(","),
-          ret,
+          ret.as_string(),
           body_str
         )
       }
-      Expression::Return(expr) => {
+      ExpressionKind::Struct { name, fields } => format!(
+        "struct {} {{{}}}",
+        name,
+        fields
+          .iter()
+          .map(|(field, ty)| format!("{}: {}", field, ty.as_string()))
+          .collect::<Vec<String>>()
+          .join(", ")
+      ),
+      ExpressionKind::Enum { name, variants } => format!(
+        "enum {} {{{}}}",
+        name,
+        variants
+          .iter()
+          .map(|x| x.as_string())
+          .collect::<Vec<String>>()
+          .join(", ")
+      ),
+      ExpressionKind::EnumVariant {
+        name,
+        variant,
+        args,
+      } => format!(
+        "{}::{}{}",
+        name,
+        variant,
+        values_string(args.iter().map(|x| x.as_string()).collect())
+      ),
+      ExpressionKind::Match { value, arms } => format!(
+        "match ({}) {{{}}}",
+        value.as_string(),
+        arms
+          .iter()
+          .map(|arm| format!("{} => {}", arm.pattern.as_string(), arm.body.as_string()))
+          .collect::<Vec<String>>()
+          .join(", ")
+      ),
+      ExpressionKind::StructLiteral { name, fields } => format!(
+        "{} {{{}}}",
+        name,
+        fields
+          .iter()
+          .map(|(field, value)| format!("{}: {}", field, value.as_string()))
+          .collect::<Vec<String>>()
+          .join(", ")
+      ),
+      ExpressionKind::FieldAccess { expr, field } => {
+        let needs_parens = matches!(
+          expr.kind,
+          ExpressionKind::BinaryOperation { .. }
+            | ExpressionKind::Cast { .. }
+            | ExpressionKind::UnaryOperation {
+              position: OperatorPosition::Prefix,
+              ..
+            }
+        );
+        format!("{}.{}", expr.as_operand_string(needs_parens), field)
+      }
+      ExpressionKind::ArrayLiteral(elements) => format!(
+        "[{}]",
+        elements
+          .iter()
+          .map(|x| x.as_string())
+          .collect::<Vec<String>>()
+          .join(", ")
+      ),
+      ExpressionKind::Cast { expr, ty } => {
+        let needs_parens = matches!(expr.kind, ExpressionKind::BinaryOperation { .. });
+        format!(
+          "{} as {}",
+          expr.as_operand_string(needs_parens),
+          ty.as_string()
+        )
+      }
+      ExpressionKind::Index { expr, index } => {
+        let needs_parens = matches!(
+          expr.kind,
+          ExpressionKind::BinaryOperation { .. }
+            | ExpressionKind::Cast { .. }
+            | ExpressionKind::UnaryOperation {
+              position: OperatorPosition::Prefix,
+              ..
+            }
+        );
+        format!(
+          "{}[{}]",
+          expr.as_operand_string(needs_parens),
+          index.as_string()
+        )
+      }
+      ExpressionKind::Return(expr) => {
         if let Some(ret) = expr {
           format!("return {};", ret.as_string())
         } else {
           "return;".to_owned()
         }
       }
-      Expression::VariableDeclaration { name, ty, mutable } => {
+      ExpressionKind::VariableDeclaration { name, ty, mutable } => {
         format!(
           "{} {}: {}",
           if *mutable { "let" } else { "const" },
           name,
-          ty
+          ty.as_string()
         )
       }
 
       // Loops
-      Expression::For { conditions, body } => {
+      ExpressionKind::For {
+        conditions,
+        body,
+        label,
+      } => {
         format!(
-          "for ({}; {}; {}) {}",
+          "{}for ({}; {}; {}) {}",
+          label_string(label),
           conditions[0].as_string(),
           conditions[1].as_string(),
           conditions[2].as_string(),
           body.as_string()
         )
       }
-      Expression::While { condition, body } => {
-        format!("while ({}) {}", condition.as_string(), body.as_string())
+      ExpressionKind::While {
+        condition,
+        body,
+        label,
+      } => {
+        format!(
+          "{}while ({}) {}",
+          label_string(label),
+          condition.as_string(),
+          body.as_string()
+        )
       }
+      ExpressionKind::Break(label) => match label {
+        Some(label) => format!("break {};", label),
+        None => "break;".to_owned(),
+      },
+      ExpressionKind::Continue(label) => match label {
+        Some(label) => format!("continue {};", label),
+        None => "continue;".to_owned(),
+      },
 
       // Control flow
-      Expression::If { condition, body } => {
-        format!("if ({}) {}", condition.as_string(), body.as_string())
-      }
-      Expression::Else { body } => {
-        format!("else {}", body.as_string())
+      ExpressionKind::If {
+        condition,
+        body,
+        else_body,
+      } => {
+        let else_str = if let Some(else_body) = else_body {
+          format!(" else {}", else_body.as_string())
+        } else {
+          String::new()
+        };
+        format!(
+          "if ({}) {}{}",
+          condition.as_string(),
+          body.as_string(),
+          else_str
+        )
       }
 
       // Module Logic
-      Expression::Import { path, idents, .. } => {
+      ExpressionKind::Import { path, idents, .. } => {
         let import_expr = if let Some(idents) = idents {
           format!("{{{}}}", idents.join(", "))
         } else {
-          format!("*")
+          "*".to_string()
         };
         format!("import {} from \"{}\"", import_expr, path)
       }
-      Expression::Export(expr) => format!("export {};", expr.as_string()),
-      Expression::ExportFromFile { path, idents, .. } => {
+      ExpressionKind::Export(expr) => format!("export {};", expr.as_string()),
+      ExpressionKind::ExportFromFile { path, idents, .. } => {
         let import_expr = if let Some(idents) = idents {
           format!("{{{}}}", idents.join(", "))
         } else {
-          format!("*")
+          "*".to_string()
         };
         format!("import {} from \"{}\"", import_expr, path)
       }
 
       // Operations
-      Expression::UnaryOperation {
+      ExpressionKind::UnaryOperation {
         operator,
         expr,
         position,
       } => {
         let op_str = operator.as_string();
-        let expr_str = expr.as_string();
+        let needs_parens = matches!(
+          (&expr.kind, position),
+          (
+            ExpressionKind::BinaryOperation { .. } | ExpressionKind::Cast { .. },
+            _
+          ) | (
+            ExpressionKind::UnaryOperation {
+              position: OperatorPosition::Prefix,
+              ..
+            },
+            OperatorPosition::Postfix,
+          )
+        );
+        let expr_str = expr.as_operand_string(needs_parens);
         match position {
           OperatorPosition::Prefix => format!("{}{}", op_str, expr_str),
           OperatorPosition::Postfix => format!("{}{}", expr_str, op_str),
         }
       }
-      Expression::BinaryOperation { operator, lhs, rhs } => {
+      ExpressionKind::BinaryOperation { operator, lhs, rhs } => {
         let op_str = operator.as_string();
-        format!("{} {} {}", lhs.as_string(), op_str, rhs.as_string())
+        let (l_bp, r_bp) = operator.bp();
+        // Operands that the parser would otherwise group differently need parentheses.
+        let rhs_parens = match &rhs.kind {
+          ExpressionKind::BinaryOperation { operator, .. } => operator.bp().0 < r_bp,
+          _ => false,
+        };
+        let rhs_str = rhs.as_operand_string(rhs_parens);
+        let lhs_parens = match &lhs.kind {
+          ExpressionKind::BinaryOperation { operator, .. } => l_bp >= operator.bp().1,
+          // The `*` would be read as part of the type when what follows it can't start an operand.
+          ExpressionKind::Cast { .. } => {
+            *operator == BinaryOperator::Multiply && rhs_str.starts_with(&['*', '['][..])
+          }
+          _ => false,
+        };
+        format!(
+          "{} {} {}",
+          lhs.as_operand_string(lhs_parens),
+          op_str,
+          rhs_str
+        )
       }
     }
   }
+
+  /// Converts the expression to a string, wrapping it in parentheses if needed.
+  fn as_operand_string(&self, parens: bool) -> String {
+    if parens {
+      format!("({})", self.as_string())
+    } else {
+      self.as_string()
+    }
+  }
+}
+
+/// Escapes the chars of a literal that can't appear in it as they are.
+fn escape(literal: &str, quote: char) -> String {
+  let mut escaped = String::new();
+  for ch in literal.chars() {
+    match ch {
+      '\n' => escaped.push_str("\\n"),
+      '\t' => escaped.push_str("\\t"),
+      '\r' => escaped.push_str("\\r"),
+      '\0' => escaped.push_str("\\0"),
+      '\\' => escaped.push_str("\\\\"),
+      _ if ch == quote => {
+        escaped.push('\\');
+        escaped.push(ch);
+      }
+      _ if ch.is_ascii_control() => escaped.push_str(&format!("\\x{:02x}", ch as u8)),
+      _ => escaped.push(ch),
+    }
+  }
+  escaped
+}
+
+/// Formats the values held by an enum variant, which are left out when there are none.
+fn values_string(values: Vec<String>) -> String {
+  if values.is_empty() {
+    String::new()
+  } else {
+    format!("({})", values.join(", "))
+  }
+}
+
+/// Formats the label in front of a loop, if it has one.
+fn label_string(label: &Option<String>) -> String {
+  match label {
+    Some(label) => format!("{}: ", label),
+    None => String::new(),
+  }
+}
+
+impl Variant {
+  /// Converts the variant to a string.
+  pub fn as_string(&self) -> String {
+    let fields = values_string(self.fields.iter().map(|x| x.as_string()).collect());
+    match self.value {
+      Some(value) => format!("{}{} = {}", self.name, fields, value),
+      None => format!("{}{}", self.name, fields),
+    }
+  }
+}
+
+impl Pattern {
+  /// Converts the pattern to a string.
+  pub fn as_string(&self) -> String {
+    match self {
+      Pattern::Wildcard => "_".to_string(),
+      Pattern::Literal(expr) => expr.as_string(),
+      Pattern::Variant {
+        name,
+        variant,
+        bindings,
+      } => format!("{}::{}{}", name, variant, values_string(bindings.clone())),
+    }
+  }
+}
+
+impl Type {
+  /// Converts the type to a string.
+  pub fn as_string(&self) -> String {
+    match self {
+      Type::Void => "void".to_string(),
+      Type::Bool => "bool".to_string(),
+      Type::Char => "char".to_string(),
+      Type::String => "string".to_string(),
+      Type::Int { bits, signed } => format!("{}{}", if *signed { "i" } else { "u" }, bits),
+      Type::Float { bits } => format!("f{}", bits),
+      Type::Pointer(ty) => format!("{}*", ty.as_string()),
+      Type::Array(ty, Some(len)) => format!("{}[{}]", ty.as_string(), len),
+      Type::Array(ty, None) => format!("{}[]", ty.as_string()),
+      Type::Function { args, ret } => format!(
+        "function({}) -> {}",
+        args
+          .iter()
+          .map(|x| x.as_string())
+          .collect::<Vec<String>>()
+          .join(", "),
+        ret.as_string()
+      ),
+      Type::Named(name) | Type::Struct { name, .. } | Type::Enum { name, .. } => name.to_string(),
+    }
+  }
 }
 
 impl UnaryOperator {
@@ -190,7 +447,9 @@ impl BinaryOperator {
       BinaryOperator::Le => "<=",
       BinaryOperator::BitAND => "&",
       BinaryOperator::BitXOR => "^",
-      BinaryOperator::BitOR => "||",
+      BinaryOperator::LogicalAnd => "&&",
+      BinaryOperator::LogicalOr => "||",
+      BinaryOperator::BitOR => "|",
       BinaryOperator::LeftShift => "<<",
       BinaryOperator::RightShift => ">>",
     })
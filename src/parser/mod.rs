@@ -1,58 +1,104 @@
+mod checker;
 mod formatter;
+// Diagnostics are only built when parsing fails, so their size doesn't matter on the happy path.
+#[allow(clippy::module_inception, clippy::result_large_err)]
 mod parser;
 mod validator;
-use crate::ast::Expression;
+use crate::ast::{Expression, ExpressionKind};
+use crate::diagnostic::Diagnostic;
+use crate::span::FileId;
+use checker::TypeChecker;
 use parser::{Expressions, Parser};
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use validator::Validator;
+pub use validator::{Exports, Item};
 
 #[derive(Debug, Clone)]
 pub struct SourceFile {
+  pub id: FileId,
   pub path: String,
+  pub source: String,
   // hash: String,
   pub dependencies: Vec<String>,
   pub expressions: Expressions,
+  pub diagnostics: Vec<Diagnostic>,
 }
 
 impl SourceFile {
-  pub fn new<S: AsRef<str>>(path: S) -> SourceFile {
+  pub fn new<S: AsRef<str>>(path: S, id: FileId) -> Result<SourceFile, String> {
     println!("[plume] parsing \"{}\" ", path.as_ref());
     match fs::read_to_string(Path::new(path.as_ref())) {
-      Ok(source) => {
-        let mut parser = Parser::from(source);
-        let expressions = parser.parse();
-        let dependencies = Self::get_depends(&expressions);
-        SourceFile {
-          path: path.as_ref().to_string(),
-          dependencies,
-          expressions,
-        }
-      }
-      Err(err) => panic!(
+      Ok(source) => Ok(Self::from_source(path, id, source)),
+      Err(err) => Err(format!(
         "An error occurred while trying to open file at path '{}': {}",
         path.as_ref(),
         err
-      ),
+      )),
+    }
+  }
+
+  /// Parses a file that has already been read.
+  pub fn from_source<S: AsRef<str>>(path: S, id: FileId, source: String) -> SourceFile {
+    let mut parser = Parser::from(&source, id);
+    let (expressions, diagnostics) = parser.parse();
+    let dependencies = Self::get_depends(&expressions);
+    SourceFile {
+      id,
+      path: path.as_ref().to_string(),
+      source,
+      dependencies,
+      expressions,
+      diagnostics,
     }
   }
 
+  /// Gets the path of a file imported by this one, which is relative to this file.
+  pub fn dependency_path(&self, depend: &str) -> String {
+    Path::new(&self.path)
+      .with_file_name(depend)
+      .to_str()
+      .expect("error")
+      .to_string()
+  }
+
   /// Gets the dependencies of a file
-  fn get_depends(expressions: &Vec<Expression>) -> Vec<String> {
+  fn get_depends(expressions: &[Expression]) -> Vec<String> {
     let dependencies: Vec<String> = expressions
       .iter()
-      .filter(|x| match x {
-        Expression::Import { .. } | Expression::ExportFromFile { .. } => true,
-        _ => false,
-      })
-      .map(|x| match x {
-        Expression::Import { path, .. } | Expression::ExportFromFile { path, .. } => path.clone(),
-        _ => unreachable!(),
+      .filter_map(|x| match &x.kind {
+        ExpressionKind::Import { path, .. } | ExpressionKind::ExportFromFile { path, .. } => {
+          Some(path.clone())
+        }
+        _ => None,
       })
       .collect();
     dependencies
   }
 
+  /// Gets the items this file imports from other files, by name.
+  pub fn imports(&self, exports: &Exports) -> HashMap<String, Item> {
+    let mut imports = HashMap::new();
+    for expr in &self.expressions {
+      if let ExpressionKind::Import {
+        idents,
+        import_all,
+        path,
+      } = &expr.kind
+      {
+        if let Some(items) = exports.get(&self.dependency_path(path)) {
+          for (name, item) in items {
+            if *import_all || idents.as_ref().is_some_and(|x| x.contains(name)) {
+              imports.insert(name.clone(), item.clone());
+            }
+          }
+        }
+      }
+    }
+    imports
+  }
+
   /// Formats the file
   pub fn format(&self) -> String {
     self
@@ -62,11 +108,6 @@ impl SourceFile {
       .collect::<Vec<String>>()
       .join("\n")
   }
-
-  /// Validates that the file has proper syntax and logic, on top of what was already done with the parser.
-  pub fn validate(&self) -> bool {
-    return true;
-  }
 }
 
 #[derive(Debug)]
@@ -75,30 +116,144 @@ pub struct Program {
 }
 
 impl Program {
-  pub fn new<S: AsRef<str>>(path: S) -> Program {
-    let entry_file = SourceFile::new(path);
+  pub fn new<S: AsRef<str>>(path: S) -> Result<Program, String> {
+    let entry_file = SourceFile::new(path, 0)?;
     let mut files: HashMap<String, SourceFile> = HashMap::new();
-    Self::resolve_depends(Path::new(&entry_file.path), &mut files, &entry_file);
+    Self::resolve_depends(&mut files, &entry_file)?;
     files.insert(entry_file.path.clone(), entry_file);
-    Program { files }
+    Ok(Program { files })
   }
 
-  fn resolve_depends(path: &Path, files: &mut HashMap<String, SourceFile>, source: &SourceFile) {
+  fn resolve_depends(
+    files: &mut HashMap<String, SourceFile>,
+    source: &SourceFile,
+  ) -> Result<(), String> {
     for depend in &source.dependencies {
-      let depend_path = path.with_file_name(depend);
-      let depend_path_str = depend_path.to_str().expect("error").to_string();
-      if files.contains_key(&depend_path_str) {
+      let depend_path = source.dependency_path(depend);
+      if files.contains_key(&depend_path) {
         continue;
       };
-      let file = SourceFile::new(depend_path_str.clone());
-      files.insert(depend_path_str, file.clone());
-      Self::resolve_depends(&depend_path, files, &file);
+      let file = SourceFile::new(depend_path.clone(), files.len() as FileId + 1)?;
+      files.insert(depend_path, file.clone());
+      Self::resolve_depends(files, &file)?;
     }
+    Ok(())
   }
 
-  pub fn validate(&self) {
-    for (path, source) in &self.files {
-      println!("VALIDATION \"{}\": {}", path, source.validate());
+  /// Gets the items every file of the program exports, including the ones it re-exports from other files.
+  pub fn exports(&self) -> Exports {
+    validator::collect_exports(&self.files)
+  }
+
+  /// Gets the paths of the program's files, ordered so that every file comes after the files it imports.
+  /// Files that import each other in a cycle are ordered by the order they were loaded in.
+  pub fn dependency_order(&self) -> Vec<String> {
+    let mut files: Vec<&SourceFile> = self.files.values().collect();
+    files.sort_by_key(|x| x.id);
+    let mut visited = vec![];
+    let mut order = vec![];
+    for file in files {
+      self.visit_dependencies(&file.path, &mut visited, &mut order);
     }
+    order
+  }
+
+  /// Adds a file to `order` after the files it imports, skipping files that were already visited.
+  fn visit_dependencies(&self, path: &str, visited: &mut Vec<String>, order: &mut Vec<String>) {
+    let file = match self.files.get(path) {
+      Some(file) if !visited.iter().any(|x| x == path) => file,
+      _ => return,
+    };
+    visited.push(path.to_string());
+    for depend in &file.dependencies {
+      self.visit_dependencies(&file.dependency_path(depend), visited, order);
+    }
+    order.push(path.to_string());
+  }
+
+  /// Prints the diagnostics found in every file of the program.
+  /// Returns true if any of them were errors.
+  pub fn report_diagnostics(&self) -> bool {
+    let mut has_errors = false;
+    let mut files: Vec<&SourceFile> = self.files.values().collect();
+    files.sort_by_key(|x| x.id);
+    for file in files {
+      for diagnostic in &file.diagnostics {
+        eprintln!("{}", diagnostic.render(&file.path, &file.source));
+        has_errors |= diagnostic.is_error();
+      }
+    }
+    has_errors
+  }
+
+  /// Checks that every file makes sense beyond its syntax, e.g. that the names it uses are defined and
+  /// that the types of its expressions fit together, annotating every expression with its type.
+  /// Problems are added to the diagnostics of the file they were found in.
+  pub fn validate(&mut self) {
+    let exports = self.exports();
+    for file in self.files.values_mut() {
+      let diagnostics = Validator::new(file, &exports).validate();
+      // Types can only be worked out once every name refers to something.
+      let resolved = !diagnostics.iter().any(|x| x.is_error());
+      file.diagnostics.extend(diagnostics);
+      if resolved {
+        let diagnostics = TypeChecker::new(&exports).check_file(file);
+        file.diagnostics.extend(diagnostics);
+      }
+    }
+  }
+}
+
+/// Parses `(path, source)` pairs into the files of a program, for tests that span several files.
+#[cfg(test)]
+pub(crate) fn files_from(sources: &[(&str, &str)]) -> HashMap<String, SourceFile> {
+  sources
+    .iter()
+    .enumerate()
+    .map(|(id, (path, source))| {
+      (
+        path.to_string(),
+        SourceFile::from_source(path, id as FileId, source.to_string()),
+      )
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn imports_across_files() {
+    let files = files_from(&[
+      (
+        "src/main.plume",
+        "import { sum, Point } from \"reexport.plume\";\nimport * from \"cycle.plume\";",
+      ),
+      ("src/reexport.plume", "export * from \"lib.plume\";"),
+      (
+        "src/lib.plume",
+        "export function sum(a: i32, b: i32) -> i32 { return a + b; }\nexport struct Point { x: i32 }\nexport let count: i32;",
+      ),
+      (
+        "src/cycle.plume",
+        "import * from \"main.plume\";\nexport const limit: i64 = 3;",
+      ),
+    ]);
+    let program = Program { files };
+    assert_eq!(
+      program.dependency_order(),
+      vec![
+        "src/lib.plume",
+        "src/reexport.plume",
+        "src/cycle.plume",
+        "src/main.plume"
+      ]
+    );
+    let imports = program.files["src/main.plume"].imports(&program.exports());
+    let mut names: Vec<&String> = imports.keys().collect();
+    names.sort();
+    assert_eq!(names, vec!["Point", "limit", "sum"]);
+    assert!(!imports["limit"].mutable);
   }
 }
@@ -1,22 +1,67 @@
-use crate::ast::{BinaryOperator, Expression, OperatorPosition, UnaryOperator};
+use crate::ast::{
+  BinaryOperator, Expression, ExpressionKind, MatchArm, OperatorPosition, Pattern, Type,
+  UnaryOperator, Variant,
+};
+use crate::diagnostic::Diagnostic;
 use crate::lexer::{Lexer, Token, TokenKind};
+use crate::span::{FileId, Span};
 pub struct Parser {
   lexer: Lexer,
+  /// A token that was consumed and then given back, to be returned by the next call to `next`.
+  pending: Option<Token>,
+  /// The span of the most recently consumed token.
+  last_span: Span,
+  /// The span of the token consumed before `last_span`, restored when a token is given back.
+  prev_span: Span,
+  diagnostics: Vec<Diagnostic>,
 }
 
 pub type Expressions = Vec<Expression>;
 
+type ParseResult<T> = Result<T, Diagnostic>;
+
 impl Parser {
   pub fn new(lexer: Lexer) -> Self {
-    Self { lexer }
+    let last_span = Span::new(lexer.file(), 0, 0);
+    Self {
+      lexer,
+      pending: None,
+      last_span,
+      prev_span: last_span,
+      diagnostics: vec![],
+    }
+  }
+
+  pub fn from<S: AsRef<str>>(source: S, file: FileId) -> Self {
+    Self::new(Lexer::new(source.as_ref().to_string(), file))
+  }
+
+  /// Consumes the next token, keeping track of its span.
+  fn next(&mut self) -> Option<Token> {
+    let token = self.pending.take().or_else(|| self.lexer.next());
+    self.diagnostics.extend(self.lexer.take_diagnostics());
+    if let Some(tok) = &token {
+      self.prev_span = self.last_span;
+      self.last_span = tok.span;
+    }
+    token
+  }
+
+  /// Gives back a token that was consumed but not used, so that the next call to `next` returns it.
+  fn put_back(&mut self, token: Token) {
+    self.last_span = self.prev_span;
+    self.pending = Some(token);
   }
 
-  pub fn from<S: AsRef<str>>(source: S) -> Self {
-    Self::new(Lexer::new(source.as_ref().to_string()))
+  fn peek(&mut self) -> Option<Token> {
+    match &self.pending {
+      Some(tok) => Some(tok.clone()),
+      None => self.lexer.peek(),
+    }
   }
 
   fn peek_is_kind(&mut self, kind: TokenKind) -> bool {
-    self.lexer.peek().filter(|x| x.is_kind(kind)).is_some()
+    self.peek().filter(|x| x.is_kind(kind)).is_some()
   }
 
   // fn peek_is_lit<S: AsRef<str>>(&mut self, literal: S) -> bool {
@@ -24,470 +69,1003 @@ impl Parser {
   // }
 
   fn peek_cmp_token<S: AsRef<str>>(&mut self, kind: TokenKind, literal: S) -> bool {
-    self
-      .lexer
-      .peek()
-      .filter(|x| x.cmp_token(kind, literal))
-      .is_some()
+    self.peek().filter(|x| x.cmp_token(kind, literal)).is_some()
+  }
+
+  /// Wraps an expression kind in a node spanning from `start` to the last consumed token.
+  fn node(&self, kind: ExpressionKind, start: Span) -> Expression {
+    Expression::new(kind, start.to(self.last_span))
   }
 
-  pub fn parse(&mut self) -> Expressions {
+  /// Creates an error for a token that isn't what the parser expected.
+  /// A missing token is reported just after the last token that was consumed.
+  fn unexpected(&self, token: Option<&Token>, expected: &str) -> Diagnostic {
+    match token {
+      Some(tok) => Diagnostic::error(
+        format!("expected {}, found `{}`", expected, tok.literal),
+        tok.span,
+      ),
+      None => Diagnostic::error(
+        format!("expected {}, found end of file", expected),
+        Span::new(self.last_span.file, self.last_span.end, self.last_span.end),
+      ),
+    }
+    .with_label(format!("expected {}", expected))
+  }
+
+  /// Consumes the next token, erroring if it isn't of the given kind.
+  /// A token that doesn't match is left to be parsed by whatever comes next.
+  fn expect_kind(&mut self, kind: TokenKind, expected: &str) -> ParseResult<Token> {
+    match self.next() {
+      Some(tok) if tok.is_kind(kind) => Ok(tok),
+      tok => Err(self.unexpected_put_back(tok, expected)),
+    }
+  }
+
+  /// Consumes the next token, erroring if it doesn't have the given kind and literal.
+  /// A token that doesn't match is left to be parsed by whatever comes next.
+  fn expect_token(&mut self, kind: TokenKind, literal: &str) -> ParseResult<Token> {
+    match self.next() {
+      Some(tok) if tok.cmp_token(kind, literal) => Ok(tok),
+      tok => Err(self.unexpected_put_back(tok, &format!("`{}`", literal))),
+    }
+  }
+
+  /// Creates an error for an unexpected token and gives it back, so that error recovery can see it.
+  fn unexpected_put_back(&mut self, token: Option<Token>, expected: &str) -> Diagnostic {
+    let diagnostic = self.unexpected(token.as_ref(), expected);
+    if let Some(tok) = token {
+      self.put_back(tok);
+    }
+    diagnostic
+  }
+
+  /// Skips tokens until parsing can safely resume: after a `;`, or before a `}` or a top-level keyword.
+  /// Blocks opened while skipping are skipped as a whole.
+  fn synchronize(&mut self) {
+    let mut depth = 0;
+    while let Some(tok) = self.peek() {
+      match tok.kind {
+        TokenKind::Semicolon if depth == 0 => {
+          self.next();
+          return;
+        }
+        TokenKind::Braces if tok.is_lit("}") && depth == 0 => return,
+        TokenKind::Braces if tok.is_lit("}") => depth -= 1,
+        TokenKind::Braces => depth += 1,
+        TokenKind::Keyword
+          if depth == 0
+            && (tok.is_lit("function")
+              || tok.is_lit("struct")
+              || tok.is_lit("enum")
+              || tok.is_lit("import")
+              || tok.is_lit("export")
+              || tok.is_lit("declare")) =>
+        {
+          return
+        }
+        _ => {}
+      }
+      self.next();
+    }
+  }
+
+  /// Parses a statement starting at `token`. If it fails, the error is recorded and the parser skips ahead
+  /// to the next statement, leaving an error node in place of the one that failed.
+  fn parse_statement(&mut self, token: Token) -> Option<Expression> {
+    let start = token.span;
+    match self.parse_expression(Some(token)) {
+      Ok(expr) => expr,
+      Err(diagnostic) => {
+        self.diagnostics.push(diagnostic);
+        self.synchronize();
+        Some(self.node(ExpressionKind::Error, start))
+      }
+    }
+  }
+
+  /// Parses an expression starting at `token`, erroring if there isn't one.
+  fn expect_expression(&mut self, token: Option<Token>, expected: &str) -> ParseResult<Expression> {
+    match token {
+      Some(tok) if !tok.is_kind(TokenKind::Semicolon) => {
+        let span = tok.span;
+        match self.parse_expression(Some(tok))? {
+          Some(expr) => Ok(expr),
+          None => Err(
+            Diagnostic::error(format!("expected {}", expected), span)
+              .with_label(format!("expected {}", expected)),
+          ),
+        }
+      }
+      tok => Err(self.unexpected_put_back(tok, expected)),
+    }
+  }
+
+  /// Parses the whole source, returning every expression along with the syntax errors found in it.
+  /// Constructs that failed to parse are replaced with `ExpressionKind::Error` nodes.
+  pub fn parse(&mut self) -> (Expressions, Vec<Diagnostic>) {
     let mut program = Expressions::new();
-    while let Some(token) = self.lexer.next() {
-      if let Some(expr) = self.parse_expression(Some(token), false) {
+    while let Some(token) = self.next() {
+      // A stray closing brace can't end anything at the top level, so skip over it.
+      if token.cmp_token(TokenKind::Braces, "}") {
+        self.diagnostics.push(
+          Diagnostic::error("unexpected closing brace", token.span).with_label("unmatched `}`"),
+        );
+        continue;
+      }
+      if let Some(expr) = self.parse_statement(token) {
         program.push(expr);
       }
     }
-    return program;
+    (program, std::mem::take(&mut self.diagnostics))
   }
 
-  fn parse_expression(&mut self, token: Option<Token>, ignore_op: bool) -> Option<Expression> {
-    if let Some(tok) = token {
-      match tok.kind {
-        TokenKind::UnaryOperator | TokenKind::SomeOperator => {
-          if let Some(operator) = UnaryOperator::from(tok.literal) {
-            let next_token = self.lexer.next();
-            let expr = self
-              .parse_expression(next_token, false)
-              .map(|x| Box::new(x))
-              .expect("Expected expression");
-            Some(Expression::UnaryOperation {
-              operator,
-              expr,
-              position: OperatorPosition::Prefix,
-            })
-          } else {
-            None
-          }
+  fn parse_expression(&mut self, token: Option<Token>) -> ParseResult<Option<Expression>> {
+    let tok = match token {
+      Some(tok) => tok,
+      None => return Ok(None),
+    };
+    let start = tok.span;
+    let kind = match tok.kind {
+      TokenKind::Keyword if tok.is_lit("function") => self.parse_function()?,
+      TokenKind::Keyword if tok.is_lit("struct") => self.parse_struct()?,
+      TokenKind::Keyword if tok.is_lit("enum") => self.parse_enum()?,
+      TokenKind::Keyword if tok.is_lit("import") => self.parse_module_reference(true)?,
+      TokenKind::Keyword
+        if tok.is_lit("export")
+          && (self.peek_cmp_token(TokenKind::Braces, "{")
+            || self.peek_cmp_token(TokenKind::SomeOperator, "*")) =>
+      {
+        self.parse_module_reference(false)?
+      }
+      TokenKind::Keyword if tok.is_lit("declare") => {
+        let next = self.next();
+        ExpressionKind::Declare(Box::new(
+          self.expect_expression(next, "a declaration after `declare`")?,
+        ))
+      }
+      TokenKind::Keyword if tok.is_lit("export") => {
+        let next = self.next();
+        ExpressionKind::Export(Box::new(
+          self.expect_expression(next, "a declaration after `export`")?,
+        ))
+      }
+      TokenKind::Keyword if tok.is_lit("return") => {
+        if self.peek_is_kind(TokenKind::Semicolon) || self.peek_cmp_token(TokenKind::Braces, "}") {
+          ExpressionKind::Return(None)
+        } else {
+          let next = self.next();
+          ExpressionKind::Return(self.parse_expression(next)?.map(Box::new))
         }
-        _ if ignore_op == false && self.peek_is_kind(TokenKind::UnaryOperator) => {
-          let expr = Box::new(
-            self
-              .parse_expression(Some(tok), true)
-              .expect("Expected lhs in binary operation"),
-          );
-          let op = self
-            .lexer
-            .next()
-            .map(|x| UnaryOperator::from(x.literal))
-            .expect("Expected an operator")
-            .expect("Invalid operator");
-          Some(Expression::UnaryOperation {
-            operator: op,
-            expr,
-            position: OperatorPosition::Postfix,
-          })
+      }
+      TokenKind::Keyword if tok.is_lit("break") || tok.is_lit("continue") => {
+        let label = if self.peek_is_kind(TokenKind::Ident) {
+          self.next().map(|label| label.literal)
+        } else {
+          None
+        };
+        if tok.is_lit("break") {
+          ExpressionKind::Break(label)
+        } else {
+          ExpressionKind::Continue(label)
         }
-        _ if ignore_op == false
-          && (self.peek_is_kind(TokenKind::BinaryOperator)
-            || self.peek_is_kind(TokenKind::SomeOperator)) =>
-        {
-          let lhs = Box::new(
-            self
-              .parse_expression(Some(tok), true)
-              .expect("Expected lhs in binary operation"),
-          );
-          let op = self
-            .lexer
-            .next()
-            .map(|x| BinaryOperator::from(x.literal))
-            .expect("Expected an operator")
-            .expect("Invalid operator");
-          let next_token = self.lexer.next();
-          let rhs = Box::new(
-            self
-              .parse_expression(next_token, false)
-              .expect("Expected rhs in binary operation"),
+      }
+      TokenKind::Ident if self.peek_is_kind(TokenKind::Colon) => self.parse_labeled_loop(tok)?,
+      TokenKind::Keyword if tok.is_lit("for") => self.parse_for_loop()?,
+      TokenKind::Keyword if tok.is_lit("while") => self.parse_control_flow("while")?,
+      TokenKind::Keyword if tok.is_lit("if") => self.parse_control_flow("if")?,
+      TokenKind::Keyword if tok.is_lit("else") => {
+        return Err(
+          Diagnostic::error("`else` without a preceding `if`", tok.span)
+            .with_label("expected an `if` statement before this"),
+        )
+      }
+      TokenKind::Keyword if tok.is_lit("let") || tok.is_lit("const") => {
+        let mutable = tok.is_lit("let");
+        let name = self.expect_kind(TokenKind::Ident, "a variable name")?;
+        if !self.peek_is_kind(TokenKind::Colon) {
+          return Err(
+            Diagnostic::error("typing is required for variable declarations", name.span)
+              .with_label("this variable has no type")
+              .with_note(format!(
+                "add a type after the name, e.g. `{} {}: i32`",
+                tok.literal, name.literal
+              )),
           );
-          Some(Expression::BinaryOperation {
-            operator: op,
+        }
+        self.next();
+        let variable_dec = ExpressionKind::VariableDeclaration {
+          name: name.literal,
+          ty: self.parse_type()?,
+          mutable,
+        };
+        if self.peek_cmp_token(TokenKind::BinaryOperator, "=") {
+          let lhs = Box::new(self.node(variable_dec, start));
+          self.next();
+          let next_token = self.next();
+          let rhs = Box::new(self.expect_expression(next_token, "a value to assign")?);
+          ExpressionKind::BinaryOperation {
+            operator: BinaryOperator::Assign,
             lhs,
             rhs,
-          })
+          }
+        } else {
+          variable_dec
         }
-        TokenKind::Keyword if tok.is_lit("function") => Some(self.parse_function()),
-        TokenKind::Keyword if tok.is_lit("import") => Some(self.parse_module_reference(true)),
-        TokenKind::Keyword
-          if tok.is_lit("export")
-            && (self.peek_cmp_token(TokenKind::Braces, "{")
-              || self.peek_cmp_token(TokenKind::SomeOperator, "*")) =>
-        {
-          Some(self.parse_module_reference(false))
+      }
+      // Block
+      TokenKind::Braces if tok.is_lit("{") => self.parse_block(tok.span)?,
+      // Comment
+      TokenKind::Comment => ExpressionKind::Comment(tok.literal),
+      // Semicolon
+      TokenKind::Semicolon => return Ok(None),
+      _ => return self.parse_binary(tok, 0).map(Some),
+    };
+    Ok(Some(self.node(kind, start)))
+  }
+
+  /// Parses an operation by precedence climbing. Only operators whose left binding power is at least
+  /// `min_bp` are consumed, which leaves looser operators to the callers further up.
+  fn parse_binary(&mut self, token: Token, min_bp: u8) -> ParseResult<Expression> {
+    let start = token.span;
+    let mut lhs = self.parse_prefix(token)?;
+    while let Some(op) = self.peek() {
+      // Postfix operators
+      if op.is_kind(TokenKind::UnaryOperator) && (op.is_lit("++") || op.is_lit("--")) {
+        if UnaryOperator::POSTFIX_BP < min_bp {
+          break;
         }
-        TokenKind::Bool => Some(Expression::Bool(tok.is_lit("true"))),
-        TokenKind::Keyword if tok.is_lit("declare") => {
-          let next = self.lexer.next();
-          Some(Expression::Declare(
-            self
-              .parse_expression(next, false)
-              .map(|x| Box::new(x))
-              .expect("Invalid declare syntax"),
-          ))
+        self.next();
+        let kind = ExpressionKind::UnaryOperation {
+          operator: UnaryOperator::from(&op.literal).unwrap(),
+          expr: Box::new(lhs),
+          position: OperatorPosition::Postfix,
+        };
+        lhs = self.node(kind, start);
+        continue;
+      }
+
+      // Field access
+      if op.is_kind(TokenKind::Dot) {
+        if UnaryOperator::POSTFIX_BP < min_bp {
+          break;
         }
-        TokenKind::Keyword if tok.is_lit("export") => {
-          let next = self.lexer.next();
-          Some(Expression::Export(
-            self
-              .parse_expression(next, false)
-              .map(|x| Box::new(x))
-              .expect("Invalid export syntax"),
-          ))
+        self.next();
+        let field = self.expect_kind(TokenKind::Ident, "a field name after `.`")?;
+        let kind = ExpressionKind::FieldAccess {
+          expr: Box::new(lhs),
+          field: field.literal,
+        };
+        lhs = self.node(kind, start);
+        continue;
+      }
+
+      // Indexing
+      if op.cmp_token(TokenKind::Brackets, "[") {
+        if UnaryOperator::POSTFIX_BP < min_bp {
+          break;
         }
-        TokenKind::Keyword if tok.is_lit("return") => {
-          let next = self.lexer.next();
-          Some(Expression::Return(
-            self.parse_expression(next, false).map(|x| Box::new(x)),
-          ))
+        let open_bracket = self.next().unwrap();
+        let index = self.expect_operand("an index", 0)?;
+        self
+          .expect_token(TokenKind::Brackets, "]")
+          .map_err(|err| err.with_secondary(open_bracket.span, "unclosed bracket"))?;
+        let kind = ExpressionKind::Index {
+          expr: Box::new(lhs),
+          index: Box::new(index),
+        };
+        lhs = self.node(kind, start);
+        continue;
+      }
+
+      // Casts
+      if op.cmp_token(TokenKind::Keyword, "as") {
+        if UnaryOperator::CAST_BP < min_bp {
+          break;
         }
-        TokenKind::Keyword if tok.is_lit("for") => Some(self.parse_for_loop()),
-        TokenKind::Keyword if tok.is_lit("while") => Some(self.parse_control_flow("while")),
-        TokenKind::Keyword if tok.is_lit("if") => Some(self.parse_control_flow("if")),
-        TokenKind::Keyword if tok.is_lit("else") => {
-          let next = self.lexer.next();
-          Some(Expression::Else {
-            body: self
-              .parse_expression(next, false)
-              .map(|x| Box::new(x))
-              .expect("else statements require a body"),
-          })
-        }
-        TokenKind::Keyword if tok.is_lit("let") || tok.is_lit("const") => {
-          let mutable = tok.is_lit("let");
-          let name = self
-            .lexer
-            .next()
-            .filter(|x| x.is_kind(TokenKind::Ident))
-            .map(|x| x.literal)
-            .expect("");
-          if !self.peek_is_kind(TokenKind::Colon) {
-            panic!("Typing is required for variable declarations");
-          }
-          self.lexer.next();
-          let variable_dec = Expression::VariableDeclaration {
-            name,
-            ty: self.parse_type(),
-            mutable,
-          };
-          if self.peek_cmp_token(TokenKind::BinaryOperator, "=") {
-            self.lexer.next();
-            let next_token = self.lexer.next();
-            let rhs = self
-              .parse_expression(next_token, false)
-              .map(|x| Box::new(x))
-              .expect("Expected something to be assigned to the variable");
-            Some(Expression::BinaryOperation {
-              operator: BinaryOperator::Assign,
-              lhs: Box::new(variable_dec),
-              rhs,
-            })
-          } else {
-            Some(variable_dec)
-          }
+        self.next();
+        let ty = self.parse_type_suffixes(true)?;
+        let kind = ExpressionKind::Cast {
+          expr: Box::new(lhs),
+          ty,
+        };
+        lhs = self.node(kind, start);
+        continue;
+      }
+
+      // Binary operators
+      let operator = match op.kind {
+        TokenKind::BinaryOperator | TokenKind::SomeOperator => BinaryOperator::from(&op.literal),
+        _ => None,
+      };
+      let operator = match operator {
+        Some(operator) => operator,
+        None => break,
+      };
+      let (l_bp, r_bp) = operator.bp();
+      if l_bp < min_bp {
+        break;
+      }
+      self.next();
+      let rhs = self.expect_operand(&format!("an expression after `{}`", op.literal), r_bp)?;
+      let kind = ExpressionKind::BinaryOperation {
+        operator,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+      };
+      lhs = self.node(kind, start);
+    }
+    Ok(lhs)
+  }
+
+  /// Parses the next operation, erroring if the source doesn't continue with one.
+  fn expect_operand(&mut self, expected: &str, min_bp: u8) -> ParseResult<Expression> {
+    match self.next() {
+      Some(tok) if !tok.is_kind(TokenKind::Semicolon) => self.parse_binary(tok, min_bp),
+      tok => Err(self.unexpected_put_back(tok, expected)),
+    }
+  }
+
+  /// Parses the start of an operation: a prefix operation, a parenthesized group, a call, or a single value.
+  fn parse_prefix(&mut self, tok: Token) -> ParseResult<Expression> {
+    let start = tok.span;
+    let kind = match tok.kind {
+      TokenKind::UnaryOperator | TokenKind::SomeOperator
+        if UnaryOperator::from(&tok.literal).is_some() =>
+      {
+        let operator = UnaryOperator::from(&tok.literal).unwrap();
+        let expr = self.expect_operand(
+          &format!("an expression after `{}`", tok.literal),
+          UnaryOperator::PREFIX_BP,
+        )?;
+        ExpressionKind::UnaryOperation {
+          operator,
+          expr: Box::new(expr),
+          position: OperatorPosition::Prefix,
         }
-        // Function Call
-        TokenKind::Ident if self.peek_cmp_token(TokenKind::Parens, "(") => {
-          let ident = tok.literal;
-          self
-            .lexer
-            .next()
-            .filter(|x| x.is_lit("("))
-            .expect("Invalid function call syntax");
-          let mut args: Vec<Expression> = vec![];
-          while let Some(t) = self.lexer.next() {
-            if t.cmp_token(TokenKind::Parens, ")") {
-              break;
-            }
-            if t.is_kind(TokenKind::Comma) {
-              continue;
+      }
+      // Grouping
+      TokenKind::Parens if tok.is_lit("(") => {
+        let inner = self.expect_operand("an expression", 0)?;
+        if !self.peek_cmp_token(TokenKind::Parens, ")") {
+          let next = self.next();
+          return Err(
+            self
+              .unexpected_put_back(next, "`)`")
+              .with_secondary(tok.span, "unclosed parenthesis"),
+          );
+        }
+        self.next();
+        return Ok(inner);
+      }
+      TokenKind::Bool => ExpressionKind::Bool(tok.is_lit("true")),
+      TokenKind::Keyword if tok.is_lit("match") => self.parse_match()?,
+      // Function Call
+      TokenKind::Ident if self.peek_cmp_token(TokenKind::Parens, "(") => {
+        let ident = tok.literal;
+        let open_parens = self.next().unwrap();
+        let mut args: Vec<Expression> = vec![];
+        if self.peek_cmp_token(TokenKind::Parens, ")") {
+          self.next();
+        } else {
+          loop {
+            args.push(self.expect_operand("an argument", 0)?);
+            match self.next() {
+              Some(t) if t.cmp_token(TokenKind::Parens, ")") => break,
+              Some(t) if t.is_kind(TokenKind::Comma) => continue,
+              t => {
+                return Err(
+                  self
+                    .unexpected_put_back(t, "`,` or `)`")
+                    .with_secondary(open_parens.span, "unclosed parenthesis"),
+                )
+              }
             }
-            if let Some(expr) = self.parse_expression(Some(t), false) {
-              args.push(expr);
+          }
+        }
+        ExpressionKind::FuncCall(ident, args)
+      }
+      // Array literal
+      TokenKind::Brackets if tok.is_lit("[") => {
+        let mut elements: Vec<Expression> = vec![];
+        if self.peek_cmp_token(TokenKind::Brackets, "]") {
+          self.next();
+        } else {
+          loop {
+            elements.push(self.expect_operand("an element", 0)?);
+            match self.next() {
+              Some(t) if t.cmp_token(TokenKind::Brackets, "]") => break,
+              Some(t) if t.is_kind(TokenKind::Comma) => continue,
+              t => {
+                return Err(
+                  self
+                    .unexpected_put_back(t, "`,` or `]`")
+                    .with_secondary(tok.span, "unclosed bracket"),
+                )
+              }
             }
           }
-          Some(Expression::FuncCall(ident, args))
-        }
-        // Variable reference
-        TokenKind::Ident => {
-          let ident = tok.literal;
-          Some(Expression::VariableRef(ident))
-        }
-        // Block
-        TokenKind::Braces if tok.is_lit("{") => Some(self.parse_block()),
-        // String literal
-        TokenKind::String => Some(Expression::String(tok.literal)),
-        // Char literal
-        TokenKind::Char => {
-          let mut chars = tok.literal.chars();
-          let first_char = chars
-            .nth(0)
-            .expect("SyntaxError: char literal contains no char.");
-          if chars.count() == 0 && first_char.is_ascii() {
-            Some(Expression::Char(first_char))
-          } else {
-            panic!("SyntaxError: char literal is larger than one byte.");
+        }
+        ExpressionKind::ArrayLiteral(elements)
+      }
+      // Enum variant
+      TokenKind::Ident if self.peek_is_kind(TokenKind::DoubleColon) => {
+        self.next();
+        let variant = self.expect_kind(TokenKind::Ident, "a variant name after `::`")?;
+        let args = if self.peek_cmp_token(TokenKind::Parens, "(") {
+          let open_parens = self.next().unwrap();
+          self.parse_parens_list(&open_parens, |parser| {
+            parser.expect_operand("a value for the variant", 0)
+          })?
+        } else {
+          vec![]
+        };
+        ExpressionKind::EnumVariant {
+          name: tok.literal,
+          variant: variant.literal,
+          args,
+        }
+      }
+      // Struct literal
+      TokenKind::Ident if self.peek_cmp_token(TokenKind::Braces, "{") => {
+        self.parse_struct_literal(tok.literal)?
+      }
+      // Variable reference
+      TokenKind::Ident => ExpressionKind::VariableRef(tok.literal),
+      // String literal
+      TokenKind::String => ExpressionKind::String(tok.literal),
+      // Char literal
+      TokenKind::Char => {
+        let mut chars = tok.literal.chars();
+        match (chars.next(), chars.count()) {
+          (Some(ch), 0) if ch.is_ascii() => ExpressionKind::Char(ch),
+          (None, _) => {
+            return Err(
+              Diagnostic::error("char literal contains no char", tok.span)
+                .with_label("empty char literal"),
+            )
+          }
+          _ => {
+            return Err(
+              Diagnostic::error("char literal is larger than one byte", tok.span)
+                .with_label("expected a single ASCII character")
+                .with_note("use double quotes for strings, e.g. \"hello\""),
+            )
           }
         }
-        // Decimal
-        TokenKind::Number if tok.literal.contains(".") => Some(Expression::Decimal(tok.literal)),
-        // Literal
-        TokenKind::Number => Some(Expression::Number(tok.literal)),
-        // Comment
-        TokenKind::Comment => Some(Expression::Comment(tok.literal)),
-        // Semicolon
-        TokenKind::Semicolon => None,
-        _ => None,
       }
-    } else {
-      None
-    }
+      // Decimal
+      TokenKind::Number if tok.literal.contains('.') => ExpressionKind::Decimal(tok.literal),
+      // Literal
+      TokenKind::Number => ExpressionKind::Number(tok.literal),
+      _ => return Err(self.unexpected_put_back(Some(tok), "an expression")),
+    };
+    Ok(self.node(kind, start))
   }
 
-  fn parse_function(&mut self) -> Expression {
-    if !self.peek_is_kind(TokenKind::Ident) {
-      panic!(
-        "SyntaxError: Invalid function signature. Expected an identifier, found {:?}",
-        self.lexer.peek()
-      );
-    }
-    let ident_tok = self.lexer.next().unwrap();
-    let open_parens = self.lexer.next().expect("msg: &str");
+  fn parse_function(&mut self) -> ParseResult<ExpressionKind> {
+    let ident_tok = self.expect_kind(TokenKind::Ident, "a function name")?;
     // Parse args
-    if !open_parens.cmp_token(TokenKind::Parens, "(") {
-      panic!(
-        "SyntaxError: Invalid function signature. Expected open parentheses, found {:?}",
-        open_parens
-      );
-    }
-    let mut args = Vec::<(String, String)>::new();
-    while let Some(token) = self.lexer.next() {
-      if token.cmp_token(TokenKind::Parens, ")") {
-        break;
-      }
-      if token.is_kind(TokenKind::Ident) {
-        let arg_name = token.literal;
-        self
-          .lexer
-          .next()
-          .filter(|x| x.is_kind(TokenKind::Colon))
-          .expect("Expected type signature");
-        let ty = self.parse_type();
-        args.push((arg_name, ty));
-      } else if !token.is_kind(TokenKind::Comma) {
-        panic!("No type identifier.");
+    let open_parens = self.expect_token(TokenKind::Parens, "(")?;
+    let mut args = Vec::<(String, Type)>::new();
+    loop {
+      match self.next() {
+        Some(token) if token.cmp_token(TokenKind::Parens, ")") => break,
+        Some(token) if token.is_kind(TokenKind::Comma) => continue,
+        Some(token) if token.is_kind(TokenKind::Ident) => {
+          let arg_name = token.literal;
+          self.expect_kind(TokenKind::Colon, "`:` followed by the argument type")?;
+          let ty = self.parse_type()?;
+          args.push((arg_name, ty));
+        }
+        token => {
+          return Err(
+            self
+              .unexpected_put_back(token, "an argument name")
+              .with_secondary(open_parens.span, "argument list starts here"),
+          )
+        }
       }
     }
 
     // Parse return type
-    let ret_type: String = if self.peek_is_kind(TokenKind::ReturnArrow) {
-      self.lexer.next();
-      if let Some(ret) = self.lexer.next().filter(|x| x.is_kind(TokenKind::Ident)) {
-        ret.literal
-      } else {
-        panic!("SyntaxError: Invalid function signature, no return type specified after arrow.");
-      }
+    let ret_type = if self.peek_is_kind(TokenKind::ReturnArrow) {
+      self.next();
+      self.parse_type()?
     } else {
-      self.lexer.next();
-      "void".to_owned()
+      Type::Void
     };
 
     // Parse body
     let body: Option<Box<Expression>> = if self.peek_cmp_token(TokenKind::Braces, "{") {
-      let next = self.lexer.next();
-      self.parse_expression(next, false).map(|x| Box::new(x))
+      let next = self.next();
+      self.parse_expression(next)?.map(Box::new)
     } else {
       None
     };
-    Expression::Function {
+    Ok(ExpressionKind::Function {
       name: ident_tok.literal,
       ret: ret_type,
-      args: args,
-      body: body,
+      args,
+      body,
+    })
+  }
+
+  fn parse_struct(&mut self) -> ParseResult<ExpressionKind> {
+    let name = self.expect_kind(TokenKind::Ident, "a struct name")?;
+    let open_brace = self.expect_token(TokenKind::Braces, "{")?;
+    let mut fields = Vec::<(String, Type)>::new();
+    loop {
+      match self.next() {
+        Some(token) if token.cmp_token(TokenKind::Braces, "}") => break,
+        Some(token) if token.is_kind(TokenKind::Comma) => continue,
+        Some(token) if token.is_kind(TokenKind::Ident) => {
+          self.expect_kind(TokenKind::Colon, "`:` followed by the field type")?;
+          fields.push((token.literal, self.parse_type()?));
+        }
+        token => {
+          return Err(
+            self
+              .unexpected_put_back(token, "a field name")
+              .with_secondary(open_brace.span, "field list starts here"),
+          )
+        }
+      }
+    }
+    Ok(ExpressionKind::Struct {
+      name: name.literal,
+      fields,
+    })
+  }
+
+  fn parse_enum(&mut self) -> ParseResult<ExpressionKind> {
+    let name = self.expect_kind(TokenKind::Ident, "an enum name")?;
+    let open_brace = self.expect_token(TokenKind::Braces, "{")?;
+    let mut variants = Vec::<Variant>::new();
+    loop {
+      match self.next() {
+        Some(token) if token.cmp_token(TokenKind::Braces, "}") => break,
+        Some(token) if token.is_kind(TokenKind::Comma) => continue,
+        Some(token) if token.is_kind(TokenKind::Ident) => {
+          let fields = if self.peek_cmp_token(TokenKind::Parens, "(") {
+            let open_parens = self.next().unwrap();
+            self.parse_parens_list(&open_parens, |parser| parser.parse_type())?
+          } else {
+            vec![]
+          };
+          let value = if self.peek_cmp_token(TokenKind::BinaryOperator, "=") {
+            self.next();
+            Some(self.parse_discriminant()?)
+          } else {
+            None
+          };
+          variants.push(Variant {
+            name: token.literal,
+            fields,
+            value,
+          });
+        }
+        token => {
+          return Err(
+            self
+              .unexpected_put_back(token, "a variant name")
+              .with_secondary(open_brace.span, "variant list starts here"),
+          )
+        }
+      }
+    }
+    Ok(ExpressionKind::Enum {
+      name: name.literal,
+      variants,
+    })
+  }
+
+  /// Parses the discriminant given to an enum variant after its `=`, e.g. `-1`.
+  fn parse_discriminant(&mut self) -> ParseResult<i64> {
+    let negative = self.peek_cmp_token(TokenKind::SomeOperator, "-");
+    if negative {
+      self.next();
+    }
+    let number = self.expect_kind(TokenKind::Number, "a discriminant")?;
+    let literal = if negative {
+      format!("-{}", number.literal)
+    } else {
+      number.literal.clone()
+    };
+    literal.parse::<i64>().map_err(|_| {
+      Diagnostic::error("invalid discriminant", number.span).with_label("expected a whole number")
+    })
+  }
+
+  fn parse_match(&mut self) -> ParseResult<ExpressionKind> {
+    self.expect_token(TokenKind::Parens, "(")?;
+    let value = self.expect_operand("a value to match", 0)?;
+    self.expect_token(TokenKind::Parens, ")")?;
+    let open_brace = self.expect_token(TokenKind::Braces, "{")?;
+    let mut arms = Vec::<MatchArm>::new();
+    loop {
+      let token = match self.next() {
+        Some(token) if token.cmp_token(TokenKind::Braces, "}") => break,
+        Some(token) => token,
+        None => {
+          return Err(
+            self
+              .unexpected(None, "`}`")
+              .with_secondary(open_brace.span, "unclosed match"),
+          )
+        }
+      };
+      let start = token.span;
+      let pattern = self.parse_pattern(token)?;
+      let span = start.to(self.last_span);
+      self.expect_kind(TokenKind::FatArrow, "`=>`")?;
+      let body_token = self.next();
+      let body = self.expect_expression(body_token, "an expression after `=>`")?;
+      // Arms are separated by commas, which are optional after a block.
+      let is_block = matches!(body.kind, ExpressionKind::Block { .. });
+      arms.push(MatchArm {
+        pattern,
+        span,
+        body,
+      });
+      match self.next() {
+        Some(token) if token.is_kind(TokenKind::Comma) => {}
+        Some(token) if token.cmp_token(TokenKind::Braces, "}") => break,
+        Some(token) if is_block => self.put_back(token),
+        token => {
+          return Err(
+            self
+              .unexpected_put_back(token, "`,` or `}`")
+              .with_secondary(open_brace.span, "arms start here"),
+          )
+        }
+      }
     }
+    Ok(ExpressionKind::Match {
+      value: Box::new(value),
+      arms,
+    })
   }
 
-  fn parse_block(&mut self) -> Expression {
+  /// Parses the pattern of a `match` arm, starting at `token`.
+  fn parse_pattern(&mut self, token: Token) -> ParseResult<Pattern> {
+    match token.kind {
+      TokenKind::Ident if token.is_lit("_") => Ok(Pattern::Wildcard),
+      TokenKind::Ident if self.peek_is_kind(TokenKind::DoubleColon) => {
+        self.next();
+        let variant = self.expect_kind(TokenKind::Ident, "a variant name after `::`")?;
+        let bindings = if self.peek_cmp_token(TokenKind::Parens, "(") {
+          let open_parens = self.next().unwrap();
+          self.parse_parens_list(&open_parens, |parser| {
+            Ok(parser.expect_kind(TokenKind::Ident, "a name")?.literal)
+          })?
+        } else {
+          vec![]
+        };
+        Ok(Pattern::Variant {
+          name: token.literal,
+          variant: variant.literal,
+          bindings,
+        })
+      }
+      TokenKind::Number | TokenKind::Char | TokenKind::Bool => {
+        Ok(Pattern::Literal(self.parse_prefix(token)?))
+      }
+      TokenKind::SomeOperator if token.is_lit("-") && self.peek_is_kind(TokenKind::Number) => {
+        Ok(Pattern::Literal(self.parse_prefix(token)?))
+      }
+      _ => Err(self.unexpected_put_back(Some(token), "a pattern")),
+    }
+  }
+
+  /// Parses a comma-separated list up to a `)`, after its opening parenthesis has been consumed.
+  fn parse_parens_list<T>(
+    &mut self,
+    open_parens: &Token,
+    mut parse_item: impl FnMut(&mut Self) -> ParseResult<T>,
+  ) -> ParseResult<Vec<T>> {
+    let mut items = vec![];
+    if self.peek_cmp_token(TokenKind::Parens, ")") {
+      self.next();
+      return Ok(items);
+    }
+    loop {
+      items.push(parse_item(self)?);
+      match self.next() {
+        Some(t) if t.cmp_token(TokenKind::Parens, ")") => return Ok(items),
+        Some(t) if t.is_kind(TokenKind::Comma) => continue,
+        t => {
+          return Err(
+            self
+              .unexpected_put_back(t, "`,` or `)`")
+              .with_secondary(open_parens.span, "unclosed parenthesis"),
+          )
+        }
+      }
+    }
+  }
+
+  fn parse_struct_literal(&mut self, name: String) -> ParseResult<ExpressionKind> {
+    let open_brace = self.next().unwrap();
+    let mut fields = Vec::<(String, Expression)>::new();
+    loop {
+      match self.next() {
+        Some(token) if token.cmp_token(TokenKind::Braces, "}") => break,
+        Some(token) if token.is_kind(TokenKind::Comma) => continue,
+        Some(token) if token.is_kind(TokenKind::Ident) => {
+          self.expect_kind(TokenKind::Colon, "`:` followed by the field's value")?;
+          let value = self.expect_operand("a value for the field", 0)?;
+          fields.push((token.literal, value));
+        }
+        token => {
+          return Err(
+            self
+              .unexpected_put_back(token, "a field name")
+              .with_secondary(open_brace.span, "struct literal starts here"),
+          )
+        }
+      }
+    }
+    Ok(ExpressionKind::StructLiteral { name, fields })
+  }
+
+  fn parse_block(&mut self, open_brace: Span) -> ParseResult<ExpressionKind> {
     let mut expressions = Vec::<Expression>::new();
-    while let Some(token) = self.lexer.next() {
-      if token.cmp_token(TokenKind::Braces, "}") {
-        break;
+    loop {
+      match self.next() {
+        Some(token) if token.cmp_token(TokenKind::Braces, "}") => break,
+        Some(token) => {
+          if let Some(expr) = self.parse_statement(token) {
+            expressions.push(expr);
+          }
+        }
+        None => {
+          return Err(
+            self
+              .unexpected(None, "`}`")
+              .with_secondary(open_brace, "unclosed block"),
+          )
+        }
       }
-      if let Some(expr) = self.parse_expression(Some(token), false) {
-        expressions.push(expr);
-      }
-    }
-    Expression::Block { expressions }
-  }
-
-  fn parse_control_flow<S: AsRef<str>>(&mut self, literal: S) -> Expression {
-    self
-      .lexer
-      .next()
-      .filter(|x| x.cmp_token(TokenKind::Parens, "("))
-      .expect("Expected parens to start the condition");
-    let condition_token = self.lexer.next();
-    let condition = Box::new(
-      self
-        .parse_expression(condition_token, false)
-        .expect("Expected a condition"),
-    );
-    self
-      .lexer
-      .next()
-      .filter(|x| x.cmp_token(TokenKind::Parens, ")"))
-      .expect("Expected parens to end the condition");
-    let body_token = self.lexer.next();
-    let body = self
-      .parse_expression(body_token, false)
-      .map(|x| Box::new(x))
-      .expect("Expected a body");
+    }
+    Ok(ExpressionKind::Block { expressions })
+  }
+
+  fn parse_control_flow<S: AsRef<str>>(&mut self, literal: S) -> ParseResult<ExpressionKind> {
+    self.expect_token(TokenKind::Parens, "(")?;
+    let condition_token = self.next();
+    let condition = Box::new(self.expect_expression(condition_token, "a condition")?);
+    self.expect_token(TokenKind::Parens, ")")?;
+    let body_token = self.next();
+    let body = Box::new(self.expect_expression(
+      body_token,
+      &format!("a body for the {} statement", literal.as_ref()),
+    )?);
     match literal.as_ref() {
-      "if" => Expression::If { condition, body },
-      "while" => Expression::While { condition, body },
+      "if" => {
+        // A body that isn't a block ends with a `;`, which comes before the `else`.
+        if !matches!(body.kind, ExpressionKind::Block { .. })
+          && self.peek_is_kind(TokenKind::Semicolon)
+        {
+          self.next();
+        }
+        let else_body = if self.peek_cmp_token(TokenKind::Keyword, "else") {
+          self.next();
+          let next = self.next();
+          Some(Box::new(
+            self.expect_expression(next, "a body for the else statement")?,
+          ))
+        } else {
+          None
+        };
+        Ok(ExpressionKind::If {
+          condition,
+          body,
+          else_body,
+        })
+      }
+      "while" => Ok(ExpressionKind::While {
+        condition,
+        body,
+        label: None,
+      }),
       _ => unimplemented!(),
     }
   }
 
-  fn parse_for_loop(&mut self) -> Expression {
-    self
-      .lexer
-      .next()
-      .filter(|x| x.cmp_token(TokenKind::Parens, "("))
-      .expect("Expected parens to start a condition"); // (
-    let cond_a_token = self.lexer.next();
-    let cond_a = self
-      .parse_expression(cond_a_token, false)
-      .map(|x| Box::new(x))
-      .expect("expected a condition");
-    self
-      .lexer
-      .next()
-      .filter(|x| x.is_kind(TokenKind::Semicolon))
-      .expect("Expected semicolon"); // ;
-    let cond_b_token = self.lexer.next();
-    let cond_b = self
-      .parse_expression(cond_b_token, false)
-      .map(|x| Box::new(x))
-      .expect("expected a condition");
-    self
-      .lexer
-      .next()
-      .filter(|x| x.is_kind(TokenKind::Semicolon))
-      .expect("Expected semicolon"); // ;
-    let cond_c_token = self.lexer.next();
-    let cond_c = self
-      .parse_expression(cond_c_token, false)
-      .map(|x| Box::new(x))
-      .expect("expected a condition");
-    self
-      .lexer
-      .next()
-      .filter(|x| x.cmp_token(TokenKind::Parens, ")"))
-      .expect("Expected parens to end a condition"); // )
-    let body_token = self.lexer.next();
-    let body: Box<Expression> = self
-      .parse_expression(body_token, false)
-      .map(|x| Box::new(x))
-      .expect("Expected a body for the for loop");
-    Expression::For {
+  fn parse_for_loop(&mut self) -> ParseResult<ExpressionKind> {
+    self.expect_token(TokenKind::Parens, "(")?; // (
+    let cond_a_token = self.next();
+    let cond_a = Box::new(self.expect_expression(cond_a_token, "a condition")?);
+    self.expect_token(TokenKind::Semicolon, ";")?; // ;
+    let cond_b_token = self.next();
+    let cond_b = Box::new(self.expect_expression(cond_b_token, "a condition")?);
+    self.expect_token(TokenKind::Semicolon, ";")?; // ;
+    let cond_c_token = self.next();
+    let cond_c = Box::new(self.expect_expression(cond_c_token, "a condition")?);
+    self.expect_token(TokenKind::Parens, ")")?; // )
+    let body_token = self.next();
+    let body = Box::new(self.expect_expression(body_token, "a body for the for loop")?);
+    Ok(ExpressionKind::For {
       conditions: [cond_a, cond_b, cond_c],
       body,
+      label: None,
+    })
+  }
+
+  /// Parses a loop preceded by a label, e.g. `outer: for (...) {...}`.
+  fn parse_labeled_loop(&mut self, label: Token) -> ParseResult<ExpressionKind> {
+    self.next(); // :
+    let mut kind = match self.next() {
+      Some(tok) if tok.cmp_token(TokenKind::Keyword, "for") => self.parse_for_loop()?,
+      Some(tok) if tok.cmp_token(TokenKind::Keyword, "while") => {
+        self.parse_control_flow("while")?
+      }
+      tok => {
+        return Err(
+          self
+            .unexpected_put_back(tok, "a loop after the label")
+            .with_secondary(label.span, "only loops can be labeled"),
+        )
+      }
+    };
+    match &mut kind {
+      ExpressionKind::For { label: slot, .. } | ExpressionKind::While { label: slot, .. } => {
+        *slot = Some(label.literal)
+      }
+      _ => unreachable!(),
     }
+    Ok(kind)
   }
 
   /// Handles the parsing of an import or `export _ from "..."`
-  fn parse_module_reference(&mut self, is_import: bool) -> Expression {
+  fn parse_module_reference(&mut self, is_import: bool) -> ParseResult<ExpressionKind> {
     let mut import_all = false;
     let mut idents: Option<Vec<String>> = None;
     // Parse imports
     if self.peek_cmp_token(TokenKind::Braces, "{") {
       let mut ident_vec = vec![];
-      self.lexer.next();
-      while let Some(token) = self.lexer.next() {
-        if token.cmp_token(TokenKind::Braces, "}") {
-          break;
-        }
-        if token.is_kind(TokenKind::Ident) {
-          ident_vec.push(token.literal);
+      let open_brace = self.next().unwrap();
+      loop {
+        match self.next() {
+          Some(token) if token.cmp_token(TokenKind::Braces, "}") => break,
+          Some(token) if token.is_kind(TokenKind::Comma) => continue,
+          Some(token) if token.is_kind(TokenKind::Ident) => ident_vec.push(token.literal),
+          token => {
+            return Err(
+              self
+                .unexpected_put_back(token, "an identifier")
+                .with_secondary(open_brace.span, "import list starts here"),
+            )
+          }
         }
       }
       idents = Some(ident_vec);
     } else if self.peek_cmp_token(TokenKind::SomeOperator, "*") {
-      self.lexer.next();
+      self.next();
       import_all = true;
     }
     // Expect "from" keyword
-    if self
-      .lexer
-      .next()
-      .filter(|x| x.cmp_token(TokenKind::Keyword, "from"))
-      .is_none()
-    {
-      panic!("SyntaxError: Expected 'from' keyword in import.");
-    }
+    self.expect_token(TokenKind::Keyword, "from")?;
 
     // Get path
     let path = self
-      .lexer
-      .next()
-      .filter(|x| x.is_kind(TokenKind::String))
-      .map(|x| x.literal)
-      .expect("SyntaxError: Expected path in import.");
+      .expect_kind(TokenKind::String, "a path to import from")?
+      .literal;
 
     // Return
     if is_import {
-      Expression::Import {
+      Ok(ExpressionKind::Import {
         path,
         idents,
         import_all,
-      }
+      })
     } else {
-      Expression::ExportFromFile {
+      Ok(ExpressionKind::ExportFromFile {
         path,
         idents,
         export_all: import_all,
-      }
+      })
     }
   }
 
-  fn parse_type(&mut self) -> String {
-    let mut is_pointer: bool = false;
-    let mut is_array: bool = false;
-    let base_type = self
-      .lexer
-      .next()
-      .filter(|x| x.is_kind(TokenKind::Ident))
-      .expect("Missing or improper type signature");
-    let arr_len: Option<String> = None;
-    if self.peek_cmp_token(TokenKind::SomeOperator, "*") {
-      self.lexer.next();
-      is_pointer = true;
-    } else if self.peek_cmp_token(TokenKind::Brackets, "[") {
-      self.lexer.next();
-      let next_token = self
-        .lexer
-        .next()
-        .filter(|x| x.cmp_token(TokenKind::Brackets, "]"))
-        .expect("SyntaxError: Invalid array type signature");
-      self.lexer.next();
-      if next_token.is_kind(TokenKind::Brackets) {
-        is_array = true;
-      } else {
-        if self.peek_cmp_token(TokenKind::Brackets, "]") {
-          is_array = true;
-        } else {
-          panic!("SyntaxError: Invalid array type signature");
+  /// Parses a type, e.g. `i32`, `char**`, `i32[4]`, or `function(i32, char*) -> bool`.
+  fn parse_type(&mut self) -> ParseResult<Type> {
+    self.parse_type_suffixes(false)
+  }
+
+  /// Parses a type along with its pointer and array suffixes. In a cast, a `*` followed by something that starts an
+  /// operand is a multiplication instead, so `x as i32 * 2` multiplies the cast value by 2.
+  fn parse_type_suffixes(&mut self, in_cast: bool) -> ParseResult<Type> {
+    let mut ty = match self.next() {
+      Some(tok) if tok.cmp_token(TokenKind::Keyword, "function") => self.parse_function_type()?,
+      Some(tok) if tok.is_kind(TokenKind::Ident) => Type::from_name(tok.literal),
+      tok => return Err(self.unexpected_put_back(tok, "a type")),
+    };
+    // Pointer and array suffixes wrap the type parsed so far, so `i32*[2]` is an array of pointers.
+    loop {
+      if self.peek_cmp_token(TokenKind::SomeOperator, "*") {
+        let star = self.next().unwrap();
+        if in_cast && self.peek().filter(starts_operand).is_some() {
+          self.put_back(star);
+          return Ok(ty);
         }
+        ty = Type::Pointer(Box::new(ty));
+      } else if self.peek_cmp_token(TokenKind::Brackets, "[") {
+        let open_bracket = self.next().unwrap();
+        let len = if self.peek_is_kind(TokenKind::Number) {
+          let len_tok = self.next().unwrap();
+          match len_tok.literal.parse::<u32>() {
+            Ok(len) => Some(len),
+            Err(_) => {
+              return Err(
+                Diagnostic::error("invalid array length", len_tok.span)
+                  .with_label("expected a whole number"),
+              )
+            }
+          }
+        } else {
+          None
+        };
+        self
+          .expect_token(TokenKind::Brackets, "]")
+          .map_err(|err| err.with_secondary(open_bracket.span, "array length starts here"))?;
+        ty = Type::Array(Box::new(ty), len);
+      } else {
+        return Ok(ty);
       }
     }
-    if is_pointer {
-      format!("{}*", base_type.literal)
-    } else if is_array && arr_len.is_some() {
-      format!("{}[{}]", base_type.literal, arr_len.unwrap())
-    } else if is_array {
-      format!("{}[]", base_type.literal)
+  }
+
+  /// Parses the argument and return types of a function type, after the `function` keyword.
+  fn parse_function_type(&mut self) -> ParseResult<Type> {
+    let open_parens = self.expect_token(TokenKind::Parens, "(")?;
+    let mut args = vec![];
+    if self.peek_cmp_token(TokenKind::Parens, ")") {
+      self.next();
     } else {
-      String::from(base_type.literal)
+      loop {
+        args.push(self.parse_type()?);
+        match self.next() {
+          Some(tok) if tok.cmp_token(TokenKind::Parens, ")") => break,
+          Some(tok) if tok.is_kind(TokenKind::Comma) => continue,
+          tok => {
+            return Err(
+              self
+                .unexpected_put_back(tok, "`,` or `)`")
+                .with_secondary(open_parens.span, "argument types start here"),
+            )
+          }
+        }
+      }
     }
+    let ret = if self.peek_is_kind(TokenKind::ReturnArrow) {
+      self.next();
+      self.parse_type()?
+    } else {
+      Type::Void
+    };
+    Ok(Type::Function {
+      args,
+      ret: Box::new(ret),
+    })
+  }
+}
+
+/// Whether a token can start an operand but can't continue a type, which is how a `*` after a cast type is told
+/// apart from a pointer suffix. A `*` or `[` continues the type, so `x as i32 * *p` needs parentheses.
+fn starts_operand(tok: &Token) -> bool {
+  match tok.kind {
+    TokenKind::Ident
+    | TokenKind::Number
+    | TokenKind::String
+    | TokenKind::Char
+    | TokenKind::Bool
+    | TokenKind::UnaryOperator => true,
+    TokenKind::Parens => tok.is_lit("("),
+    TokenKind::SomeOperator => tok.is_lit("-") || tok.is_lit("&"),
+    TokenKind::Keyword => tok.is_lit("match"),
+    _ => false,
   }
 }
 
@@ -497,15 +1075,20 @@ mod test {
   #[test]
   fn function() {
     assert_eq!(
-      Parser::from("function hello() { \"hello!\"; }").parse(),
-      vec![Expression::Function {
-        name: "hello".to_owned(),
-        ret: "void".to_owned(),
-        args: vec![],
-        body: Some(Box::new(Expression::Block {
-          expressions: vec![Expression::String("hello!".to_owned())]
-        }))
-      }]
+      Parser::from("function hello() { \"hello!\"; }", 0).parse(),
+      (
+        vec![Expression::from(ExpressionKind::Function {
+          name: "hello".to_owned(),
+          ret: Type::Void,
+          args: vec![],
+          body: Some(Box::new(Expression::from(ExpressionKind::Block {
+            expressions: vec![Expression::from(ExpressionKind::String(
+              "hello!".to_owned()
+            ))]
+          })))
+        })],
+        vec![]
+      )
     )
   }
 
@@ -513,32 +1096,559 @@ mod test {
   fn import() {
     // Import { print }
     assert_eq!(
-      Parser::from("import { print } from \"util.plume\"").parse(),
-      vec![Expression::Import {
-        path: "util.plume".to_owned(),
-        idents: Some(vec!["print".to_owned()]),
-        import_all: false,
-      }]
+      Parser::from("import { print } from \"util.plume\"", 0).parse(),
+      (
+        vec![Expression::from(ExpressionKind::Import {
+          path: "util.plume".to_owned(),
+          idents: Some(vec!["print".to_owned()]),
+          import_all: false,
+        })],
+        vec![]
+      )
     );
 
     // Import { print, hello }
     assert_eq!(
-      Parser::from("import { print, hello } from \"util.plume\"").parse(),
-      vec![Expression::Import {
-        path: "util.plume".to_owned(),
-        idents: Some(vec!["print".to_owned(), "hello".to_owned()]),
-        import_all: false,
-      }]
+      Parser::from("import { print, hello } from \"util.plume\"", 0).parse(),
+      (
+        vec![Expression::from(ExpressionKind::Import {
+          path: "util.plume".to_owned(),
+          idents: Some(vec!["print".to_owned(), "hello".to_owned()]),
+          import_all: false,
+        })],
+        vec![]
+      )
     );
 
     // Import *
     assert_eq!(
-      Parser::from("import * from \"util.plume\"").parse(),
-      vec![Expression::Import {
-        path: "util.plume".to_owned(),
-        idents: None,
-        import_all: true,
-      }]
+      Parser::from("import * from \"util.plume\"", 0).parse(),
+      (
+        vec![Expression::from(ExpressionKind::Import {
+          path: "util.plume".to_owned(),
+          idents: None,
+          import_all: true,
+        })],
+        vec![]
+      )
+    );
+  }
+
+  #[test]
+  fn spans() {
+    let source = "function sum(a: i32, b: i32) -> i32 {\n  return a + b;\n}";
+    let program = Parser::from(source, 2).parse().0;
+    assert_eq!(program[0].span, Span::new(2, 0, source.len()));
+    let body = match &program[0].kind {
+      ExpressionKind::Function {
+        body: Some(body), ..
+      } => body,
+      _ => unreachable!(),
+    };
+    let ret = match &body.kind {
+      ExpressionKind::Block { expressions } => &expressions[0],
+      _ => unreachable!(),
+    };
+    assert_eq!(&source[ret.span.start..ret.span.end], "return a + b");
+    match &ret.kind {
+      ExpressionKind::Return(Some(sum)) => {
+        assert_eq!(&source[sum.span.start..sum.span.end], "a + b")
+      }
+      _ => unreachable!(),
+    }
+  }
+
+  #[test]
+  fn errors() {
+    let errors = Parser::from("function main() {\n  let x = 1;\n}", 0)
+      .parse()
+      .1;
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+      errors[0].message,
+      "typing is required for variable declarations"
+    );
+    assert_eq!(errors[0].primary.span, Span::new(0, 24, 25));
+
+    let errors = Parser::from("function main( {", 0).parse().1;
+    assert_eq!(errors[0].message, "expected an argument name, found `{`");
+    assert_eq!(errors[0].secondary[0].span, Span::new(0, 13, 14));
+
+    let errors = Parser::from("function main() { return 1;", 0).parse().1;
+    assert_eq!(errors[0].message, "expected `}`, found end of file");
+    assert_eq!(errors[0].primary.span, Span::new(0, 27, 27));
+  }
+
+  #[test]
+  fn recovery() {
+    let source = "function main() {
+  let x = 1;
+  foo(;
+  return 0;
+}
+function other( {
+}
+import { a from \"a.plume\"
+";
+    let (program, errors) = Parser::from(source, 0).parse();
+    assert_eq!(
+      errors
+        .iter()
+        .map(|x| x.message.as_str())
+        .collect::<Vec<&str>>(),
+      vec![
+        "typing is required for variable declarations",
+        "expected an argument, found `;`",
+        "expected an argument name, found `{`",
+        "expected an identifier, found `from`",
+      ]
+    );
+    assert_eq!(program.len(), 3);
+    assert_eq!(
+      program[0].kind,
+      ExpressionKind::Function {
+        name: "main".to_owned(),
+        ret: Type::Void,
+        args: vec![],
+        body: Some(Box::new(Expression::from(ExpressionKind::Block {
+          expressions: vec![
+            Expression::from(ExpressionKind::Error),
+            Expression::from(ExpressionKind::Error),
+            Expression::from(ExpressionKind::Return(Some(Box::new(Expression::from(
+              ExpressionKind::Number("0".to_owned())
+            ))))),
+          ]
+        })))
+      }
+    );
+    assert_eq!(program[1].kind, ExpressionKind::Error);
+    assert_eq!(program[2].kind, ExpressionKind::Error);
+  }
+
+  /// Parses a single expression statement.
+  fn expr(source: &str) -> Expression {
+    let (mut program, errors) = Parser::from(source, 0).parse();
+    assert_eq!(errors, vec![]);
+    program.remove(0)
+  }
+
+  fn binary(operator: BinaryOperator, lhs: Expression, rhs: Expression) -> Expression {
+    Expression::from(ExpressionKind::BinaryOperation {
+      operator,
+      lhs: Box::new(lhs),
+      rhs: Box::new(rhs),
+    })
+  }
+
+  fn var(name: &str) -> Expression {
+    Expression::from(ExpressionKind::VariableRef(name.to_owned()))
+  }
+
+  #[test]
+  fn precedence() {
+    use BinaryOperator::*;
+    assert_eq!(
+      expr("a * b + c;"),
+      binary(Add, binary(Multiply, var("a"), var("b")), var("c"))
+    );
+    assert_eq!(
+      expr("a + b * c;"),
+      binary(Add, var("a"), binary(Multiply, var("b"), var("c")))
+    );
+    assert_eq!(
+      expr("a - b - c;"),
+      binary(Subtract, binary(Subtract, var("a"), var("b")), var("c"))
+    );
+    assert_eq!(
+      expr("a = b += c;"),
+      binary(Assign, var("a"), binary(AddAssign, var("b"), var("c")))
+    );
+    assert_eq!(
+      expr("a || b && c | d;"),
+      binary(
+        LogicalOr,
+        var("a"),
+        binary(LogicalAnd, var("b"), binary(BitOR, var("c"), var("d")))
+      )
+    );
+    assert_eq!(
+      expr("(a + b) * c;"),
+      binary(Multiply, binary(Add, var("a"), var("b")), var("c"))
+    );
+    assert_eq!(
+      expr("a < b << c == d & e;"),
+      binary(
+        BitAND,
+        binary(
+          Eq,
+          binary(Lt, var("a"), binary(LeftShift, var("b"), var("c"))),
+          var("d")
+        ),
+        var("e")
+      )
+    );
+    assert_eq!(
+      expr("-a++ * *b;"),
+      binary(
+        Multiply,
+        Expression::from(ExpressionKind::UnaryOperation {
+          operator: UnaryOperator::Negation,
+          expr: Box::new(Expression::from(ExpressionKind::UnaryOperation {
+            operator: UnaryOperator::Increment,
+            expr: Box::new(var("a")),
+            position: OperatorPosition::Postfix,
+          })),
+          position: OperatorPosition::Prefix,
+        }),
+        Expression::from(ExpressionKind::UnaryOperation {
+          operator: UnaryOperator::Deref,
+          expr: Box::new(var("b")),
+          position: OperatorPosition::Prefix,
+        })
+      )
+    );
+  }
+
+  #[test]
+  fn format_keeps_grouping() {
+    for source in [
+      "(a + b) * c",
+      "a - (b - c)",
+      "a = b = c",
+      "-(a + b)",
+      "*p = 1",
+      "a ^ b & c",
+      "(a || b) && !c",
+      "f(a, (b + c) * 2)",
+    ]
+    .iter()
+    {
+      assert_eq!(&expr(source).as_string(), source);
+    }
+  }
+
+  #[test]
+  fn types() {
+    let source = "declare function f(a: char**, b: i32[4], c: string*, d: u8[], e: function(i32, f64) -> bool) -> Point*;";
+    let (program, errors) = Parser::from(source, 0).parse();
+    assert_eq!(errors, vec![]);
+    let int = Type::Int {
+      bits: 32,
+      signed: true,
+    };
+    match &program[0].kind {
+      ExpressionKind::Declare(func) => match &func.kind {
+        ExpressionKind::Function { args, ret, .. } => {
+          let types: Vec<Type> = args.iter().map(|(_, ty)| ty.clone()).collect();
+          assert_eq!(
+            types,
+            vec![
+              Type::Pointer(Box::new(Type::Pointer(Box::new(Type::Char)))),
+              Type::Array(Box::new(int.clone()), Some(4)),
+              Type::Pointer(Box::new(Type::String)),
+              Type::Array(
+                Box::new(Type::Int {
+                  bits: 8,
+                  signed: false
+                }),
+                None
+              ),
+              Type::Function {
+                args: vec![int, Type::Float { bits: 64 }],
+                ret: Box::new(Type::Bool),
+              },
+            ]
+          );
+          assert_eq!(
+            ret,
+            &Type::Pointer(Box::new(Type::Named("Point".to_owned())))
+          );
+        }
+        kind => panic!("expected a function, found {:?}", kind),
+      },
+      kind => panic!("expected a declaration, found {:?}", kind),
+    }
+    assert_eq!(
+      program[0].as_string(),
+      "declare function f(a: char**,b: i32[4],c: string*,d: u8[],e: function(i32, f64) -> bool) -> Point*;"
+    );
+  }
+
+  #[test]
+  fn if_else() {
+    let block = |name: &str| {
+      Box::new(Expression::from(ExpressionKind::Block {
+        expressions: vec![var(name)],
+      }))
+    };
+    assert_eq!(
+      expr("if (a) b; else if (c) { d; } else { e; }"),
+      Expression::from(ExpressionKind::If {
+        condition: Box::new(var("a")),
+        body: Box::new(var("b")),
+        else_body: Some(Box::new(Expression::from(ExpressionKind::If {
+          condition: Box::new(var("c")),
+          body: block("d"),
+          else_body: Some(block("e")),
+        }))),
+      })
+    );
+    assert_eq!(
+      Parser::from("else { a; }", 0).parse().1[0].message,
+      "`else` without a preceding `if`"
+    );
+  }
+
+  #[test]
+  fn labeled_loops() {
+    let source = "outer: while (a) { for (i; j; k) { break outer; continue; } }";
+    let loop_expr = expr(source);
+    assert_eq!(
+      loop_expr,
+      Expression::from(ExpressionKind::While {
+        condition: Box::new(var("a")),
+        body: Box::new(Expression::from(ExpressionKind::Block {
+          expressions: vec![Expression::from(ExpressionKind::For {
+            conditions: [Box::new(var("i")), Box::new(var("j")), Box::new(var("k"))],
+            body: Box::new(Expression::from(ExpressionKind::Block {
+              expressions: vec![
+                Expression::from(ExpressionKind::Break(Some("outer".to_owned()))),
+                Expression::from(ExpressionKind::Continue(None)),
+              ],
+            })),
+            label: None,
+          })],
+        })),
+        label: Some("outer".to_owned()),
+      })
+    );
+    assert_eq!(
+      loop_expr.as_string(),
+      "outer: while (a) {for (i; j; k) {break outer;\ncontinue;}}"
+    );
+    assert_eq!(
+      Parser::from("outer: if (a) {}", 0).parse().1[0].message,
+      "expected a loop after the label, found `if`"
+    );
+  }
+
+  #[test]
+  fn structs() {
+    assert_eq!(
+      expr("struct Point { x: i32, y: i32* }"),
+      Expression::from(ExpressionKind::Struct {
+        name: "Point".to_owned(),
+        fields: vec![
+          (
+            "x".to_owned(),
+            Type::Int {
+              bits: 32,
+              signed: true
+            }
+          ),
+          (
+            "y".to_owned(),
+            Type::Pointer(Box::new(Type::Int {
+              bits: 32,
+              signed: true
+            }))
+          ),
+        ],
+      })
+    );
+    let access = expr("Point { x: 1, y: p }.y.z");
+    assert_eq!(
+      access,
+      Expression::from(ExpressionKind::FieldAccess {
+        expr: Box::new(Expression::from(ExpressionKind::FieldAccess {
+          expr: Box::new(Expression::from(ExpressionKind::StructLiteral {
+            name: "Point".to_owned(),
+            fields: vec![
+              (
+                "x".to_owned(),
+                Expression::from(ExpressionKind::Number("1".to_owned()))
+              ),
+              ("y".to_owned(), var("p")),
+            ],
+          })),
+          field: "y".to_owned(),
+        })),
+        field: "z".to_owned(),
+      })
+    );
+    assert_eq!(access.as_string(), "Point {x: 1, y: p}.y.z");
+    assert_eq!(expr("(*p).x").as_string(), "(*p).x");
+    assert_eq!(expr("a.b = c + d.e").as_string(), "a.b = c + d.e");
+    assert_eq!(
+      Parser::from("struct Point { x: i32, 1 }", 0).parse().1[0].message,
+      "expected a field name, found `1`"
+    );
+  }
+
+  #[test]
+  fn arrays() {
+    let index = expr("a[i + 1][0] = [1, b[2]]");
+    assert_eq!(
+      index,
+      binary(
+        BinaryOperator::Assign,
+        Expression::from(ExpressionKind::Index {
+          expr: Box::new(Expression::from(ExpressionKind::Index {
+            expr: Box::new(var("a")),
+            index: Box::new(binary(
+              BinaryOperator::Add,
+              var("i"),
+              Expression::from(ExpressionKind::Number("1".to_owned()))
+            )),
+          })),
+          index: Box::new(Expression::from(ExpressionKind::Number("0".to_owned()))),
+        }),
+        Expression::from(ExpressionKind::ArrayLiteral(vec![
+          Expression::from(ExpressionKind::Number("1".to_owned())),
+          Expression::from(ExpressionKind::Index {
+            expr: Box::new(var("b")),
+            index: Box::new(Expression::from(ExpressionKind::Number("2".to_owned()))),
+          }),
+        ]))
+      )
+    );
+    assert_eq!(index.as_string(), "a[i + 1][0] = [1, b[2]]");
+    assert_eq!(expr("(*p)[0].x").as_string(), "(*p)[0].x");
+    assert_eq!(expr("*p[0]").as_string(), "*p[0]");
+    assert_eq!(expr("[]").as_string(), "[]");
+    assert_eq!(
+      Parser::from("[1, 2;", 0).parse().1[0].message,
+      "expected `,` or `]`, found `;`"
+    );
+  }
+
+  #[test]
+  fn enums_and_match() {
+    let shape = expr("enum Shape { Circle(f64), Rect(f64, f64) = 4, Empty = -1 }");
+    assert_eq!(
+      shape,
+      Expression::from(ExpressionKind::Enum {
+        name: "Shape".to_owned(),
+        variants: vec![
+          Variant {
+            name: "Circle".to_owned(),
+            fields: vec![Type::Float { bits: 64 }],
+            value: None,
+          },
+          Variant {
+            name: "Rect".to_owned(),
+            fields: vec![Type::Float { bits: 64 }, Type::Float { bits: 64 }],
+            value: Some(4),
+          },
+          Variant {
+            name: "Empty".to_owned(),
+            fields: vec![],
+            value: Some(-1),
+          },
+        ],
+      })
+    );
+    assert_eq!(
+      shape.as_string(),
+      "enum Shape {Circle(f64), Rect(f64, f64) = 4, Empty = -1}"
+    );
+    let arms = expr("match (s) { Shape::Rect(w, _) => w, 0 => { x; } _ => Shape::Empty }");
+    assert_eq!(
+      arms,
+      Expression::from(ExpressionKind::Match {
+        value: Box::new(var("s")),
+        arms: vec![
+          MatchArm {
+            pattern: Pattern::Variant {
+              name: "Shape".to_owned(),
+              variant: "Rect".to_owned(),
+              bindings: vec!["w".to_owned(), "_".to_owned()],
+            },
+            span: Span::default(),
+            body: var("w"),
+          },
+          MatchArm {
+            pattern: Pattern::Literal(Expression::from(ExpressionKind::Number("0".to_owned()))),
+            span: Span::default(),
+            body: Expression::from(ExpressionKind::Block {
+              expressions: vec![var("x")],
+            }),
+          },
+          MatchArm {
+            pattern: Pattern::Wildcard,
+            span: Span::default(),
+            body: Expression::from(ExpressionKind::EnumVariant {
+              name: "Shape".to_owned(),
+              variant: "Empty".to_owned(),
+              args: vec![],
+            }),
+          },
+        ],
+      })
+    );
+    assert_eq!(
+      expr("x = match (c) { 'a' => Shape::Circle(1.0), -1 => y, }").as_string(),
+      "x = match (c) {'a' => Shape::Circle(1.0), -1 => y}"
+    );
+    assert_eq!(
+      Parser::from("match (x) { 1 => a 2 => b }", 0).parse().1[0].message,
+      "expected `,` or `}`, found `2`"
+    );
+    assert_eq!(
+      Parser::from("match (x) { a => b }", 0).parse().1[0].message,
+      "expected a pattern, found `a`"
+    );
+  }
+
+  #[test]
+  fn casts() {
+    let cast = expr("a * -b as f64 as i32*");
+    assert_eq!(
+      cast,
+      binary(
+        BinaryOperator::Multiply,
+        var("a"),
+        Expression::from(ExpressionKind::Cast {
+          expr: Box::new(Expression::from(ExpressionKind::Cast {
+            expr: Box::new(Expression::from(ExpressionKind::UnaryOperation {
+              operator: UnaryOperator::Negation,
+              expr: Box::new(var("b")),
+              position: OperatorPosition::Prefix,
+            })),
+            ty: Type::Float { bits: 64 },
+          })),
+          ty: Type::Pointer(Box::new(Type::Int {
+            bits: 32,
+            signed: true
+          })),
+        })
+      )
+    );
+    assert_eq!(cast.as_string(), "a * -b as f64 as i32*");
+    assert_eq!(expr("(a + b) as u8").as_string(), "(a + b) as u8");
+    assert_eq!(expr("-(x as i64)").as_string(), "-(x as i64)");
+    assert_eq!(expr("(p as Point*).x").as_string(), "(p as Point*).x");
+    assert_eq!(
+      expr("x as i32 * 2"),
+      binary(
+        BinaryOperator::Multiply,
+        Expression::from(ExpressionKind::Cast {
+          expr: Box::new(var("x")),
+          ty: Type::Int {
+            bits: 32,
+            signed: true
+          },
+        }),
+        Expression::from(ExpressionKind::Number("2".to_owned())),
+      )
+    );
+    assert_eq!(expr("x as i32 * 2").as_string(), "x as i32 * 2");
+    assert_eq!(expr("p as u8** * 2").as_string(), "p as u8** * 2");
+    assert_eq!(expr("(x as i32) * *p").as_string(), "(x as i32) * *p");
+    assert_eq!(expr("x as i64 + 1").as_string(), "x as i64 + 1");
+    assert_eq!(
+      Parser::from("x as 1;", 0).parse().1[0].message,
+      "expected a type, found `1`"
     );
   }
 }
@@ -1,4 +1,1187 @@
 // Handles the validation of parsed files.
-// pub struct Validator {
+use super::SourceFile;
+use crate::ast::{BinaryOperator, Expression, ExpressionKind, Pattern, Type, Variant};
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
+use std::collections::HashMap;
 
-// }
+/// A top-level definition that other files can import.
+#[derive(Debug, Clone)]
+pub struct Item {
+  pub ty: Type,
+  pub mutable: bool,
+  /// Where the item was defined, in the file that defines it.
+  pub span: Span,
+  /// Whether the item is `declare`d, so it's defined outside the program, usually in C.
+  pub declared: bool,
+}
+
+/// The items exported by each file of a program, keyed by path and then by name.
+pub type Exports = HashMap<String, HashMap<String, Item>>;
+
+#[derive(Debug, Clone)]
+enum SymbolKind {
+  Function,
+  Struct,
+  Enum,
+  /// A name bound by a `match` arm, whose type is only known once the match is type checked.
+  Binding,
+  /// A name imported from another file, whose kind isn't known here.
+  Import,
+  Variable(Type),
+}
+
+#[derive(Debug, Clone)]
+struct Symbol {
+  kind: SymbolKind,
+  /// Where the symbol was defined.
+  span: Span,
+}
+
+impl SymbolKind {
+  fn describe(&self) -> &str {
+    match self {
+      SymbolKind::Function => "function",
+      SymbolKind::Struct => "struct",
+      SymbolKind::Enum => "enum",
+      SymbolKind::Binding => "binding",
+      SymbolKind::Import => "import",
+      SymbolKind::Variable(_) => "variable",
+    }
+  }
+}
+
+/// Resolves the names used in a file to their definitions, reporting the ones that can't be.
+pub struct Validator<'a> {
+  file: &'a SourceFile,
+  exports: &'a Exports,
+  /// The scopes that are currently open, the first of which holds the file's top-level definitions.
+  scopes: Vec<HashMap<String, Symbol>>,
+  /// The labels of the loops that enclose the expression being visited, innermost last.
+  loops: Vec<Option<String>>,
+  /// The types held by value by each struct and enum the file defines or imports.
+  held_types: HashMap<String, Vec<Type>>,
+  diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Validator<'a> {
+  pub fn new(file: &'a SourceFile, exports: &'a Exports) -> Self {
+    Self {
+      file,
+      exports,
+      scopes: vec![],
+      loops: vec![],
+      held_types: HashMap::new(),
+      diagnostics: vec![],
+    }
+  }
+
+  /// Validates the file, returning the problems that were found.
+  pub fn validate(mut self) -> Vec<Diagnostic> {
+    self.scopes.push(HashMap::new());
+    // Top-level definitions are collected first, so they can be used before the point they're defined at.
+    let file = self.file;
+    for expr in &file.expressions {
+      self.declare_item(expr);
+    }
+    self.held_types = held_types(file, self.exports);
+    for expr in &file.expressions {
+      self.validate_item(expr);
+    }
+    self.diagnostics
+  }
+
+  /// Adds a top-level definition to the file's scope.
+  fn declare_item(&mut self, expr: &Expression) {
+    match &expr.kind {
+      ExpressionKind::Export(inner) | ExpressionKind::Declare(inner) => self.declare_item(inner),
+      ExpressionKind::Function { name, .. } => self.define(name, SymbolKind::Function, expr.span),
+      ExpressionKind::Struct { name, .. } => self.define(name, SymbolKind::Struct, expr.span),
+      ExpressionKind::Enum { name, .. } => self.define(name, SymbolKind::Enum, expr.span),
+      ExpressionKind::VariableDeclaration { name, ty, .. } => {
+        self.define(name, SymbolKind::Variable(ty.clone()), expr.span);
+      }
+      ExpressionKind::BinaryOperation {
+        operator: BinaryOperator::Assign,
+        lhs,
+        ..
+      } if matches!(lhs.kind, ExpressionKind::VariableDeclaration { .. }) => self.declare_item(lhs),
+      ExpressionKind::Import {
+        idents,
+        import_all,
+        path,
+      } => {
+        let names = self.check_exported(path, idents.as_ref(), expr.span);
+        if *import_all {
+          for name in names {
+            self.define(&name, SymbolKind::Import, expr.span);
+          }
+        } else if let Some(idents) = idents {
+          for ident in idents {
+            self.define(ident, SymbolKind::Import, expr.span);
+          }
+        }
+      }
+      ExpressionKind::ExportFromFile { idents, path, .. } => {
+        self.check_exported(path, idents.as_ref(), expr.span);
+      }
+      _ => {}
+    }
+  }
+
+  /// Checks that the given names are exported by the file at `path`, returning everything it exports.
+  fn check_exported(
+    &mut self,
+    path: &str,
+    idents: Option<&Vec<String>>,
+    span: Span,
+  ) -> Vec<String> {
+    let mut names: Vec<String> = match self.exports.get(&self.file.dependency_path(path)) {
+      Some(items) => items.keys().cloned().collect(),
+      None => return vec![],
+    };
+    names.sort();
+    for ident in idents.into_iter().flatten() {
+      if !names.contains(ident) {
+        self.diagnostics.push(
+          Diagnostic::error(format!("`{}` is not exported by \"{}\"", ident, path), span)
+            .with_label(format!("`{}` imported here", ident)),
+        );
+      }
+    }
+    names
+  }
+
+  /// Validates a top-level expression, whose definitions were already added to the file's scope.
+  fn validate_item(&mut self, expr: &Expression) {
+    match &expr.kind {
+      ExpressionKind::Declare(inner) => {
+        self.check_declaration(inner);
+        self.validate_item(inner)
+      }
+      ExpressionKind::Export(inner) => self.validate_item(inner),
+      ExpressionKind::Function { .. } => self.validate_function(expr),
+      ExpressionKind::BinaryOperation {
+        operator: BinaryOperator::Assign,
+        lhs,
+        rhs,
+      } if matches!(lhs.kind, ExpressionKind::VariableDeclaration { .. }) => {
+        self.visit(rhs);
+        self.validate_item(lhs);
+      }
+      ExpressionKind::VariableDeclaration { ty, .. } => self.validate_type(ty, expr.span),
+      ExpressionKind::Struct { name, fields } => {
+        for (field, ty) in fields {
+          // A struct can only contain itself through a pointer, or it would have no end.
+          if let Some(cycle) = self.find_cycle(ty, name) {
+            self.diagnostics.push(
+              Diagnostic::error(
+                format!("recursive type `{}` has infinite size", name),
+                expr.span,
+              )
+              .with_label(format!("field `{}` {}", field, describe_cycle(&cycle)))
+              .with_note(format!(
+                "use a pointer instead, e.g. `{}: {}*`",
+                field, cycle[0]
+              )),
+            );
+          }
+          self.validate_type(ty, expr.span);
+        }
+      }
+      ExpressionKind::Enum { name, variants } => self.validate_enum(name, variants, expr.span),
+      ExpressionKind::Import { .. } | ExpressionKind::ExportFromFile { .. } => {}
+      _ => self.visit(expr),
+    }
+  }
+
+  fn validate_enum(&mut self, name: &str, variants: &[Variant], span: Span) {
+    for (idx, variant) in variants.iter().enumerate() {
+      if variants[..idx].iter().any(|x| x.name == variant.name) {
+        self.diagnostics.push(
+          Diagnostic::error(
+            format!("variant `{}` is defined multiple times", variant.name),
+            span,
+          )
+          .with_label(format!("`{}` redefined here", variant.name)),
+        );
+      }
+      for ty in &variant.fields {
+        // Like a struct, an enum can only hold itself through a pointer.
+        if let Some(cycle) = self.find_cycle(ty, name) {
+          self.diagnostics.push(
+            Diagnostic::error(format!("recursive type `{}` has infinite size", name), span)
+              .with_label(format!(
+                "variant `{}` {}",
+                variant.name,
+                describe_cycle(&cycle)
+              ))
+              .with_note(format!("use a pointer instead, e.g. `{}*`", cycle[0])),
+          );
+        }
+        self.validate_type(ty, span);
+      }
+    }
+    // Discriminants are stored as an `i32`, like the `int` of a C enum.
+    let discriminants = Variant::discriminants(variants);
+    for (idx, (variant, value)) in variants.iter().zip(&discriminants).enumerate() {
+      if *value < i32::MIN as i64 || *value > i32::MAX as i64 {
+        self.diagnostics.push(
+          Diagnostic::error(
+            format!(
+              "discriminant of `{}` is out of range for `i32`",
+              variant.name
+            ),
+            span,
+          )
+          .with_label(format!("`{}` would be {}", variant.name, value)),
+        );
+      } else if let Some(first) = discriminants[..idx].iter().position(|x| x == value) {
+        self.diagnostics.push(
+          Diagnostic::error(
+            format!("discriminant value `{}` assigned more than once", value),
+            span,
+          )
+          .with_label(format!(
+            "`{}` and `{}` are both {}",
+            variants[first].name, variant.name, value
+          )),
+        );
+      }
+    }
+  }
+
+  /// Finds the types a value of type `ty` holds by value on the way to holding `name` itself, ending with `name`.
+  /// Fixed-size arrays hold their elements by value, but pointers don't.
+  fn find_cycle(&self, ty: &Type, name: &str) -> Option<Vec<String>> {
+    let mut path = vec![];
+    if self.hold_path(ty, name, &mut path) {
+      Some(path)
+    } else {
+      None
+    }
+  }
+
+  fn hold_path(&self, ty: &Type, name: &str, path: &mut Vec<String>) -> bool {
+    match ty {
+      Type::Array(ty, Some(_)) => self.hold_path(ty, name, path),
+      Type::Named(x) if x == name => {
+        path.push(x.clone());
+        true
+      }
+      // Cycles that don't lead back to `name` are reported for the types in them.
+      Type::Named(x) if path.contains(x) => false,
+      Type::Named(x) => {
+        path.push(x.clone());
+        let held = self.held_types.get(x).into_iter().flatten();
+        for ty in held {
+          if self.hold_path(ty, name, path) {
+            return true;
+          }
+        }
+        path.pop();
+        false
+      }
+      _ => false,
+    }
+  }
+
+  /// Checks that a `declare` only declares an item, as the object file defining it is linked in from elsewhere.
+  fn check_declaration(&mut self, expr: &Expression) {
+    let (kind, name, defined_by) = match &expr.kind {
+      ExpressionKind::Function {
+        name,
+        body: Some(_),
+        ..
+      } => ("function", name, "a body"),
+      ExpressionKind::BinaryOperation {
+        operator: BinaryOperator::Assign,
+        lhs,
+        ..
+      } => match &lhs.kind {
+        ExpressionKind::VariableDeclaration { name, .. } => ("global", name, "an initializer"),
+        _ => return,
+      },
+      _ => return,
+    };
+    self.diagnostics.push(
+      Diagnostic::error(
+        format!("declared {} `{}` has {}", kind, name, defined_by),
+        expr.span,
+      )
+      .with_label("`declare` only declares an item that's defined by another object file")
+      .with_note(format!(
+        "remove `declare` to define `{}` here, or remove {} to only declare it",
+        name, defined_by
+      )),
+    );
+  }
+
+  /// Checks that the named types used in a type refer to structs.
+  fn validate_type(&mut self, ty: &Type, span: Span) {
+    match ty {
+      Type::Pointer(ty) | Type::Array(ty, _) => self.validate_type(ty, span),
+      Type::Function { args, ret } => {
+        for arg in args {
+          self.validate_type(arg, span);
+        }
+        self.validate_type(ret, span);
+      }
+      Type::Named(name) => match self.lookup(name).cloned() {
+        None => self.diagnostics.push(
+          Diagnostic::error(format!("cannot find type `{}` in this scope", name), span)
+            .with_label("not found in this scope"),
+        ),
+        Some(Symbol {
+          kind: SymbolKind::Struct | SymbolKind::Enum | SymbolKind::Import,
+          ..
+        }) => {}
+        Some(symbol) => self.diagnostics.push(
+          Diagnostic::error(format!("`{}` is not a type", name), span)
+            .with_label("expected a type")
+            .with_secondary(
+              symbol.span,
+              format!("`{}` is a {}", name, symbol.kind.describe()),
+            ),
+        ),
+      },
+      _ => {}
+    }
+  }
+
+  fn validate_function(&mut self, expr: &Expression) {
+    if let ExpressionKind::Function { args, ret, .. } = &expr.kind {
+      for (_, ty) in args {
+        self.validate_type(ty, expr.span);
+      }
+      self.validate_type(ret, expr.span);
+    }
+    if let ExpressionKind::Function {
+      args,
+      body: Some(body),
+      ..
+    } = &expr.kind
+    {
+      // Loops around a nested function can't be exited from inside of it.
+      let loops = std::mem::take(&mut self.loops);
+      self.scopes.push(HashMap::new());
+      for (name, ty) in args {
+        self.define(name, SymbolKind::Variable(ty.clone()), expr.span);
+      }
+      // The body shares the arguments' scope, so redefining an argument is an error rather than shadowing.
+      match &body.kind {
+        ExpressionKind::Block { expressions } => {
+          for expr in expressions {
+            self.visit(expr);
+          }
+        }
+        _ => self.visit(body),
+      }
+      self.scopes.pop();
+      self.loops = loops;
+    }
+  }
+
+  /// Reports it if a name used as an enum, e.g. in `Color::Red`, isn't one.
+  fn check_enum_name(&mut self, name: &str, span: Span) {
+    match self.lookup(name).cloned() {
+      None => self.diagnostics.push(
+        Diagnostic::error(format!("cannot find enum `{}` in this scope", name), span)
+          .with_label("not found in this scope"),
+      ),
+      Some(Symbol {
+        kind: SymbolKind::Enum | SymbolKind::Import,
+        ..
+      }) => {}
+      Some(symbol) => self.diagnostics.push(
+        Diagnostic::error(format!("`{}` is not an enum", name), span)
+          .with_label("expected an enum")
+          .with_secondary(
+            symbol.span,
+            format!("`{}` is a {}", name, symbol.kind.describe()),
+          ),
+      ),
+    }
+  }
+
+  /// Validates an expression inside of a function.
+  fn visit(&mut self, expr: &Expression) {
+    match &expr.kind {
+      ExpressionKind::VariableRef(name) => match self.lookup(name).cloned() {
+        None => self.diagnostics.push(
+          Diagnostic::error(format!("cannot find `{}` in this scope", name), expr.span)
+            .with_label("not found in this scope"),
+        ),
+        Some(Symbol {
+          kind: kind @ (SymbolKind::Struct | SymbolKind::Enum),
+          span,
+        }) => self.diagnostics.push(
+          Diagnostic::error(
+            format!("expected a value, found {} `{}`", kind.describe(), name),
+            expr.span,
+          )
+          .with_label("not a value")
+          .with_secondary(span, format!("`{}` defined here", name)),
+        ),
+        Some(_) => {}
+      },
+      ExpressionKind::FuncCall(name, args) => {
+        match self.lookup(name).cloned() {
+          None => self.diagnostics.push(
+            Diagnostic::error(
+              format!("cannot find function `{}` in this scope", name),
+              expr.span,
+            )
+            .with_label("not found in this scope"),
+          ),
+          Some(Symbol {
+            kind: SymbolKind::Variable(ty),
+            span,
+          }) if !is_callable(&ty) => self.diagnostics.push(
+            Diagnostic::error(format!("`{}` is not a function", name), expr.span)
+              .with_label("called here")
+              .with_secondary(
+                span,
+                format!("`{}` is a variable of type `{}`", name, ty.as_string()),
+              ),
+          ),
+          Some(Symbol {
+            kind: kind @ (SymbolKind::Struct | SymbolKind::Enum),
+            span,
+          }) => self.diagnostics.push(
+            Diagnostic::error(format!("`{}` is not a function", name), expr.span)
+              .with_label("called here")
+              .with_secondary(span, format!("`{}` is a {}", name, kind.describe())),
+          ),
+          Some(_) => {}
+        }
+        for arg in args {
+          self.visit(arg);
+        }
+      }
+      ExpressionKind::VariableDeclaration { name, ty, .. } => {
+        self.validate_type(ty, expr.span);
+        self.define(name, SymbolKind::Variable(ty.clone()), expr.span);
+      }
+      ExpressionKind::StructLiteral { name, fields } => {
+        match self.lookup(name).cloned() {
+          None => self.diagnostics.push(
+            Diagnostic::error(
+              format!("cannot find struct `{}` in this scope", name),
+              expr.span,
+            )
+            .with_label("not found in this scope"),
+          ),
+          Some(Symbol {
+            kind: SymbolKind::Struct | SymbolKind::Import,
+            ..
+          }) => {}
+          Some(symbol) => self.diagnostics.push(
+            Diagnostic::error(format!("`{}` is not a struct", name), expr.span)
+              .with_label("expected a struct")
+              .with_secondary(
+                symbol.span,
+                format!("`{}` is a {}", name, symbol.kind.describe()),
+              ),
+          ),
+        }
+        for (_, value) in fields {
+          self.visit(value);
+        }
+      }
+      ExpressionKind::FieldAccess { expr, .. } => self.visit(expr),
+      ExpressionKind::EnumVariant { name, args, .. } => {
+        self.check_enum_name(name, expr.span);
+        for arg in args {
+          self.visit(arg);
+        }
+      }
+      ExpressionKind::Match { value, arms } => {
+        self.visit(value);
+        for arm in arms {
+          self.scopes.push(HashMap::new());
+          match &arm.pattern {
+            Pattern::Variant { name, bindings, .. } => {
+              self.check_enum_name(name, arm.span);
+              for binding in bindings.iter().filter(|x| *x != "_") {
+                self.define(binding, SymbolKind::Binding, arm.span);
+              }
+            }
+            Pattern::Literal(literal) => self.visit(literal),
+            Pattern::Wildcard => {}
+          }
+          self.visit(&arm.body);
+          self.scopes.pop();
+        }
+      }
+      ExpressionKind::ArrayLiteral(elements) => {
+        for element in elements {
+          self.visit(element);
+        }
+      }
+      ExpressionKind::Index { expr, index } => {
+        self.visit(expr);
+        self.visit(index);
+      }
+      ExpressionKind::Cast { expr: value, ty } => {
+        self.visit(value);
+        self.validate_type(ty, expr.span);
+      }
+      // The value is resolved before the variable exists, so `let x: i32 = x;` is an error.
+      ExpressionKind::BinaryOperation {
+        operator: BinaryOperator::Assign,
+        lhs,
+        rhs,
+      } if matches!(lhs.kind, ExpressionKind::VariableDeclaration { .. }) => {
+        self.visit(rhs);
+        self.visit(lhs);
+      }
+      ExpressionKind::BinaryOperation { lhs, rhs, .. } => {
+        self.visit(lhs);
+        self.visit(rhs);
+      }
+      ExpressionKind::UnaryOperation { expr, .. } => self.visit(expr),
+      ExpressionKind::Return(Some(expr)) => self.visit(expr),
+      ExpressionKind::Block { expressions } => {
+        self.scopes.push(HashMap::new());
+        for expr in expressions {
+          self.visit(expr);
+        }
+        self.scopes.pop();
+      }
+      // Variables declared in the loop's header are only visible inside of the loop.
+      ExpressionKind::For {
+        conditions,
+        body,
+        label,
+      } => {
+        self.scopes.push(HashMap::new());
+        for condition in conditions {
+          self.visit(condition);
+        }
+        self.loops.push(label.clone());
+        self.visit(body);
+        self.loops.pop();
+        self.scopes.pop();
+      }
+      ExpressionKind::While {
+        condition,
+        body,
+        label,
+      } => {
+        self.visit(condition);
+        self.loops.push(label.clone());
+        self.visit(body);
+        self.loops.pop();
+      }
+      ExpressionKind::Break(label) => self.check_loop_jump("break", label, expr.span),
+      ExpressionKind::Continue(label) => self.check_loop_jump("continue", label, expr.span),
+      ExpressionKind::If {
+        condition,
+        body,
+        else_body,
+      } => {
+        self.visit(condition);
+        self.visit(body);
+        if let Some(else_body) = else_body {
+          self.visit(else_body);
+        }
+      }
+      // Functions are only compiled at the top level, where they can't use the locals of another function.
+      ExpressionKind::Function { name, .. } => {
+        self.diagnostics.push(
+          Diagnostic::error(
+            format!("function `{}` is defined inside another function", name),
+            expr.span,
+          )
+          .with_label("functions can only be defined at the top level")
+          .with_note(format!("move `{}` out of the function it's in", name)),
+        );
+        self.declare_item(expr);
+        self.validate_function(expr);
+      }
+      _ => {}
+    }
+  }
+
+  /// Reports a `break` or `continue` that has no loop to jump out of.
+  fn check_loop_jump(&mut self, keyword: &str, label: &Option<String>, span: Span) {
+    match label {
+      _ if self.loops.is_empty() => self.diagnostics.push(
+        Diagnostic::error(format!("`{}` outside of a loop", keyword), span)
+          .with_label(format!("cannot `{}` outside of a loop", keyword)),
+      ),
+      Some(label) if !self.loops.iter().any(|x| x.as_ref() == Some(label)) => {
+        self.diagnostics.push(
+          Diagnostic::error(format!("use of undeclared label `{}`", label), span)
+            .with_label(format!("no enclosing loop is labeled `{}`", label)),
+        )
+      }
+      _ => {}
+    }
+  }
+
+  fn lookup(&self, name: &str) -> Option<&Symbol> {
+    self.scopes.iter().rev().find_map(|scope| scope.get(name))
+  }
+
+  /// Adds a definition to the innermost scope, reporting it if it clashes with another definition.
+  fn define(&mut self, name: &str, kind: SymbolKind, span: Span) {
+    let scope_count = self.scopes.len();
+    if let Some(prev) = self.scopes[scope_count - 1].get(name).cloned() {
+      self.diagnostics.push(
+        Diagnostic::error(
+          format!("the name `{}` is defined multiple times", name),
+          span,
+        )
+        .with_label(format!("`{}` redefined here", name))
+        .with_secondary(prev.span, format!("previous definition of `{}` here", name)),
+      );
+      return;
+    }
+    if scope_count > 1 {
+      if let Some(prev) = self.lookup(name).cloned() {
+        let diagnostic = match prev.kind {
+          SymbolKind::Variable(_) => Diagnostic::warning(
+            format!("`{}` shadows a variable from an outer scope", name),
+            span,
+          ),
+          // Calls look up functions by name, so hiding one behind a variable is never intended.
+          _ => Diagnostic::error(
+            format!(
+              "`{}` conflicts with the {} of the same name",
+              name,
+              prev.kind.describe()
+            ),
+            span,
+          ),
+        };
+        self.diagnostics.push(
+          diagnostic
+            .with_label(format!("`{}` declared here", name))
+            .with_secondary(prev.span, format!("`{}` first defined here", name)),
+        );
+      }
+    }
+    self.scopes[scope_count - 1].insert(name.to_string(), Symbol { kind, span });
+  }
+}
+
+/// Gets the types held by value by each struct and enum a file defines or imports, keyed by the name it's known by.
+fn held_types(file: &SourceFile, exports: &Exports) -> HashMap<String, Vec<Type>> {
+  let mut items = file.imports(exports);
+  for expr in &file.expressions {
+    let expr = match &expr.kind {
+      ExpressionKind::Export(inner) => inner,
+      _ => expr,
+    };
+    items.extend(defined_item(expr));
+  }
+  items
+    .into_iter()
+    .filter_map(|(name, item)| match item.ty {
+      Type::Struct { fields, .. } => Some((name, fields.into_iter().map(|(_, ty)| ty).collect())),
+      Type::Enum { variants, .. } => {
+        Some((name, variants.into_iter().flat_map(|x| x.fields).collect()))
+      }
+      _ => None,
+    })
+    .collect()
+}
+
+/// Describes how a field or variant holds the types of a cycle, e.g. "contains `B`, which contains `A`".
+fn describe_cycle(cycle: &[String]) -> String {
+  if cycle.len() == 1 {
+    return format!("contains `{}` itself", cycle[0]);
+  }
+  let names: Vec<String> = cycle.iter().map(|x| format!("`{}`", x)).collect();
+  format!("contains {}", names.join(", which contains "))
+}
+
+/// Whether a value of the given type can be called like a function.
+fn is_callable(ty: &Type) -> bool {
+  match ty {
+    Type::Function { .. } => true,
+    Type::Pointer(ty) => matches!(**ty, Type::Function { .. }),
+    _ => false,
+  }
+}
+
+/// Collects the names exported by every file, including the ones they re-export from other files.
+pub fn collect_exports(files: &HashMap<String, SourceFile>) -> Exports {
+  let mut exports = Exports::new();
+  for path in files.keys() {
+    exports_of(files, path, &mut exports, &mut vec![]);
+  }
+  exports
+}
+
+fn exports_of(
+  files: &HashMap<String, SourceFile>,
+  path: &str,
+  exports: &mut Exports,
+  visiting: &mut Vec<String>,
+) -> HashMap<String, Item> {
+  if let Some(items) = exports.get(path) {
+    return items.clone();
+  }
+  let file = match files.get(path) {
+    // Files that re-export each other in a cycle can't add anything new.
+    Some(file) if !visiting.iter().any(|x| x == path) => file,
+    _ => return HashMap::new(),
+  };
+  visiting.push(path.to_string());
+  let mut items = HashMap::new();
+  for expr in &file.expressions {
+    match &expr.kind {
+      ExpressionKind::Export(inner) => items.extend(defined_item(inner)),
+      ExpressionKind::ExportFromFile { idents, path, .. } => {
+        let mut reexported = exports_of(files, &file.dependency_path(path), exports, visiting);
+        if let Some(idents) = idents {
+          reexported.retain(|name, _| idents.contains(name));
+        }
+        items.extend(reexported);
+      }
+      _ => {}
+    }
+  }
+  visiting.pop();
+  exports.insert(path.to_string(), items.clone());
+  items
+}
+
+/// Gets the name and item defined by a top-level definition.
+pub fn defined_item(expr: &Expression) -> Option<(String, Item)> {
+  match &expr.kind {
+    ExpressionKind::Declare(inner) => defined_item(inner).map(|(name, item)| {
+      (
+        name,
+        Item {
+          declared: true,
+          ..item
+        },
+      )
+    }),
+    ExpressionKind::Function {
+      name, args, ret, ..
+    } => {
+      let ty = Type::Function {
+        args: args.iter().map(|(_, ty)| ty.clone()).collect(),
+        ret: Box::new(ret.clone()),
+      };
+      Some((
+        name.clone(),
+        Item {
+          ty,
+          mutable: false,
+          span: expr.span,
+          declared: false,
+        },
+      ))
+    }
+    ExpressionKind::VariableDeclaration { name, ty, mutable } => Some((
+      name.clone(),
+      Item {
+        ty: ty.clone(),
+        mutable: *mutable,
+        span: expr.span,
+        declared: false,
+      },
+    )),
+    ExpressionKind::Struct { name, fields } => Some((
+      name.clone(),
+      Item {
+        ty: Type::Struct {
+          name: name.clone(),
+          fields: fields.clone(),
+        },
+        mutable: false,
+        span: expr.span,
+        declared: false,
+      },
+    )),
+    ExpressionKind::Enum { name, variants } => Some((
+      name.clone(),
+      Item {
+        ty: Type::Enum {
+          name: name.clone(),
+          variants: variants.clone(),
+        },
+        mutable: false,
+        span: expr.span,
+        declared: false,
+      },
+    )),
+    ExpressionKind::BinaryOperation {
+      operator: BinaryOperator::Assign,
+      lhs,
+      ..
+    } => defined_item(lhs),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::diagnostic::Severity;
+  use crate::parser::files_from;
+
+  /// Validates `source` as the only file of a program, returning the diagnostic messages.
+  fn validate(source: &str) -> Vec<(Severity, String)> {
+    let mut files = HashMap::new();
+    files.insert(
+      "main.plume".to_owned(),
+      SourceFile::from_source("main.plume", 0, source.to_owned()),
+    );
+    validate_files(files, "main.plume")
+  }
+
+  fn validate_files(files: HashMap<String, SourceFile>, path: &str) -> Vec<(Severity, String)> {
+    let exports = collect_exports(&files);
+    Validator::new(&files[path], &exports)
+      .validate()
+      .into_iter()
+      .map(|x| (x.severity, x.message))
+      .collect()
+  }
+
+  #[test]
+  fn names() {
+    assert_eq!(
+      validate(
+        "
+        declare function putchar(ch: char) -> i32;
+        function main(argc: i32) -> i32 {
+          let x: i32 = argc;
+          helper(x, y);
+          return missing(x);
+        }
+        function helper(a: i32, b: i32) {}
+        "
+      ),
+      vec![
+        (Severity::Error, "cannot find `y` in this scope".to_owned()),
+        (
+          Severity::Error,
+          "cannot find function `missing` in this scope".to_owned()
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn scopes() {
+    assert_eq!(
+      validate(
+        "
+        function main(a: i32, a: i32) {
+          let x: i32 = x;
+          const x: i32;
+          {
+            let a: i32;
+            let main: i32;
+            let inner: i32;
+          }
+          inner;
+          a();
+        }
+        function main() {}
+        "
+      ),
+      vec![
+        (
+          Severity::Error,
+          "the name `main` is defined multiple times".to_owned()
+        ),
+        (
+          Severity::Error,
+          "the name `a` is defined multiple times".to_owned()
+        ),
+        (Severity::Error, "cannot find `x` in this scope".to_owned()),
+        (
+          Severity::Error,
+          "the name `x` is defined multiple times".to_owned()
+        ),
+        (
+          Severity::Warning,
+          "`a` shadows a variable from an outer scope".to_owned()
+        ),
+        (
+          Severity::Error,
+          "`main` conflicts with the function of the same name".to_owned()
+        ),
+        (
+          Severity::Error,
+          "cannot find `inner` in this scope".to_owned()
+        ),
+        (Severity::Error, "`a` is not a function".to_owned()),
+      ]
+    );
+  }
+
+  #[test]
+  fn declarations() {
+    assert_eq!(
+      validate(
+        "
+        declare function puts(s: string) -> i32;
+        declare function helper(x: i32) -> i32 { return x; }
+        declare let count: i32;
+        declare let limit: i32 = 3;
+        "
+      ),
+      vec![
+        (
+          Severity::Error,
+          "declared function `helper` has a body".to_owned()
+        ),
+        (
+          Severity::Error,
+          "declared global `limit` has an initializer".to_owned()
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn imports() {
+    let files = files_from(&[
+      (
+        "src/main.plume",
+        "import { sum, hidden, missing } from \"lib.plume\";\nimport * from \"reexport.plume\";\nfunction main() { sum(1, 2); product(1, 2); hidden(); }",
+      ),
+      (
+        "src/lib.plume",
+        "export function sum(a: i32, b: i32) -> i32 { return a + b; }\nfunction hidden() {}",
+      ),
+      (
+        "src/reexport.plume",
+        "export * from \"math.plume\";",
+      ),
+      (
+        "src/math.plume",
+        "export function product(a: i32, b: i32) -> i32 { return a * b; }",
+      ),
+    ]);
+    assert_eq!(
+      validate_files(files, "src/main.plume"),
+      vec![
+        (
+          Severity::Error,
+          "`hidden` is not exported by \"lib.plume\"".to_owned()
+        ),
+        (
+          Severity::Error,
+          "`missing` is not exported by \"lib.plume\"".to_owned()
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn loops() {
+    assert_eq!(
+      validate(
+        "
+        function main() {
+          break;
+          outer: while (true) {
+            for (let i: i32 = 0; i < 10; i += 1) {
+              continue outer;
+              break inner;
+            }
+            function nested() { continue; }
+          }
+        }
+        "
+      ),
+      vec![
+        (Severity::Error, "`break` outside of a loop".to_owned()),
+        (
+          Severity::Error,
+          "use of undeclared label `inner`".to_owned()
+        ),
+        (
+          Severity::Error,
+          "function `nested` is defined inside another function".to_owned()
+        ),
+        (Severity::Error, "`continue` outside of a loop".to_owned()),
+      ]
+    );
+  }
+
+  #[test]
+  fn nested_functions() {
+    assert_eq!(
+      validate(
+        "
+        function main() -> i32 {
+          let x: i32 = 1;
+          function inner() -> i32 { return x; }
+          return inner();
+        }
+        "
+      ),
+      vec![(
+        Severity::Error,
+        "function `inner` is defined inside another function".to_owned()
+      )]
+    );
+  }
+
+  #[test]
+  fn structs() {
+    assert_eq!(
+      validate(
+        "
+        let origin: Point;
+        struct Point { x: i32, y: i32 }
+        struct List { value: i32, next: List*, rest: List }
+        struct Tree { children: Tree[2] }
+        struct Bad { a: Missing }
+        declare function draw(p: Point) -> void;
+        declare function move(p: Point*) -> void;
+        function main(p: main) -> Point {
+          Point(1);
+          return Point;
+        }
+        "
+      ),
+      vec![
+        (
+          Severity::Error,
+          "recursive type `List` has infinite size".to_owned()
+        ),
+        (
+          Severity::Error,
+          "recursive type `Tree` has infinite size".to_owned()
+        ),
+        (
+          Severity::Error,
+          "cannot find type `Missing` in this scope".to_owned()
+        ),
+        (Severity::Error, "`main` is not a type".to_owned()),
+        (Severity::Error, "`Point` is not a function".to_owned()),
+        (
+          Severity::Error,
+          "expected a value, found struct `Point`".to_owned()
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn recursive_types() {
+    assert_eq!(
+      validate(
+        "
+        struct A { b: B }
+        struct B { a: A[2] }
+        enum E { X(F) }
+        enum F { Y(E), Z }
+        struct S { e: Holder }
+        enum Holder { V(S) }
+        struct Node { next: Link* }
+        struct Link { node: Node }
+        "
+      ),
+      vec![
+        (
+          Severity::Error,
+          "recursive type `A` has infinite size".to_owned()
+        ),
+        (
+          Severity::Error,
+          "recursive type `B` has infinite size".to_owned()
+        ),
+        (
+          Severity::Error,
+          "recursive type `E` has infinite size".to_owned()
+        ),
+        (
+          Severity::Error,
+          "recursive type `F` has infinite size".to_owned()
+        ),
+        (
+          Severity::Error,
+          "recursive type `S` has infinite size".to_owned()
+        ),
+        (
+          Severity::Error,
+          "recursive type `Holder` has infinite size".to_owned()
+        ),
+      ]
+    );
+
+    // Types imported from another file are followed too.
+    let files = files_from(&[
+      (
+        "main.plume",
+        "import { Outer } from \"lib.plume\";\nexport struct Inner { outer: Outer }",
+      ),
+      (
+        "lib.plume",
+        "import { Inner } from \"main.plume\";\nexport struct Outer { inner: Inner }",
+      ),
+    ]);
+    assert_eq!(
+      validate_files(files, "main.plume"),
+      vec![(
+        Severity::Error,
+        "recursive type `Inner` has infinite size".to_owned()
+      )]
+    );
+  }
+
+  #[test]
+  fn enums() {
+    assert_eq!(
+      validate(
+        "
+        enum Shape { Circle(f64), Square(f64), Circle, Nested(Shape) }
+        enum Color { Red = 1, Green, Blue = 2, Big = 3000000000 }
+        enum Size { Small, Large(Missing) }
+        function main(c: Color) -> i32 {
+          let s: Shape = Shape::Circle(1.0);
+          Point::Origin;
+          c::Red;
+          return match (s) {
+            Shape::Circle(r) => r,
+            Shape::Square(_) => r,
+            _ => 0,
+          };
+        }
+        "
+      ),
+      vec![
+        (
+          Severity::Error,
+          "variant `Circle` is defined multiple times".to_owned()
+        ),
+        (
+          Severity::Error,
+          "recursive type `Shape` has infinite size".to_owned()
+        ),
+        (
+          Severity::Error,
+          "discriminant value `2` assigned more than once".to_owned()
+        ),
+        (
+          Severity::Error,
+          "discriminant of `Big` is out of range for `i32`".to_owned()
+        ),
+        (
+          Severity::Error,
+          "cannot find type `Missing` in this scope".to_owned()
+        ),
+        (
+          Severity::Error,
+          "cannot find enum `Point` in this scope".to_owned()
+        ),
+        (Severity::Error, "`c` is not an enum".to_owned()),
+        (Severity::Error, "cannot find `r` in this scope".to_owned()),
+      ]
+    );
+  }
+}
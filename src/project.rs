@@ -1,5 +1,10 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Where build output goes when the project doesn't set `output_dir`.
+const DEFAULT_OUTPUT_DIR: &str = "build";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Project {
@@ -7,8 +12,26 @@ pub struct Project {
   pub version: String,
   pub authors: Option<Vec<String>>,
   pub description: Option<String>,
-  pub kind: String,
+  pub kind: ProjectKind,
   pub entry: String,
+  /// Extra libraries to link against, such as `m` for `-lm`.
+  pub libraries: Option<Vec<String>>,
+  /// Extra directories the linker searches for libraries.
+  pub library_paths: Option<Vec<String>>,
+  /// The directory build output goes in, relative to the project.
+  pub output_dir: Option<String>,
+}
+
+/// What the linker makes out of the compiled object files.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectKind {
+  #[serde(alias = "bin")]
+  Executable,
+  /// A static library archive, `lib<name>.a`.
+  Static,
+  /// A shared library, `lib<name>.so`.
+  Shared,
 }
 
 impl Project {
@@ -27,4 +50,68 @@ impl Project {
       ),
     }
   }
+
+  /// The name of the linked output, without the scope of names like `@plume/examples`.
+  pub fn output_name(&self) -> &str {
+    self.name.rsplit('/').next().unwrap_or(&self.name)
+  }
+
+  pub fn output_dir(&self) -> &Path {
+    Path::new(self.output_dir.as_deref().unwrap_or(DEFAULT_OUTPUT_DIR))
+  }
+
+  /// Removes the output directory, returning whether there was one to remove.
+  ///
+  /// An output directory that holds the project itself is never removed, in case `output_dir` is set to something
+  /// like `.` by mistake.
+  pub fn clean(&self) -> io::Result<bool> {
+    let output_dir = match self.output_dir().canonicalize() {
+      Ok(dir) => dir,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+      Err(err) => return Err(err),
+    };
+    let root = Path::new(".").canonicalize()?;
+    let entry = Path::new(&self.entry).canonicalize()?;
+    if root.starts_with(&output_dir) || entry.starts_with(&output_dir) {
+      return Err(io::Error::other(format!(
+        "refusing to remove \"{}\", since it contains the project",
+        self.output_dir().display()
+      )));
+    }
+    fs::remove_dir_all(output_dir)?;
+    Ok(true)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn kinds_and_link_settings() {
+    let parse = |json: &str| serde_json::from_str::<Project>(json).unwrap();
+    let project = parse(
+      r#"{"name": "@plume/examples", "version": "0.1.0", "entry": "main.plume", "kind": "bin"}"#,
+    );
+    assert_eq!(project.kind, ProjectKind::Executable);
+    assert_eq!(project.output_name(), "examples");
+    assert_eq!(project.libraries, None);
+    assert_eq!(project.output_dir(), Path::new("build"));
+
+    let project = parse(
+      r#"{"name": "lib", "version": "0.1.0", "entry": "lib.plume", "kind": "shared",
+        "libraries": ["m"], "library_paths": ["/usr/local/lib"]}"#,
+    );
+    assert_eq!(project.kind, ProjectKind::Shared);
+    assert_eq!(project.libraries, Some(vec!["m".to_string()]));
+    assert_eq!(
+      project.library_paths,
+      Some(vec!["/usr/local/lib".to_string()])
+    );
+
+    assert!(serde_json::from_str::<Project>(
+      r#"{"name": "x", "version": "0.1.0", "entry": "x.plume", "kind": "dll"}"#
+    )
+    .is_err());
+  }
 }
@@ -0,0 +1,25 @@
+/// Identifies a source file that has been loaded into a program.
+pub type FileId = u32;
+
+/// A range of bytes within a source file.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Hash)]
+pub struct Span {
+  pub file: FileId,
+  pub start: usize,
+  pub end: usize,
+}
+
+impl Span {
+  pub fn new(file: FileId, start: usize, end: usize) -> Self {
+    Self { file, start, end }
+  }
+
+  /// Creates a span that covers both this span and `other`.
+  pub fn to(&self, other: Span) -> Span {
+    Span {
+      file: self.file,
+      start: self.start.min(other.start),
+      end: self.end.max(other.end),
+    }
+  }
+}